@@ -0,0 +1,492 @@
+//! `wavedash build analyze`: where the bytes in an upload directory go, and
+//! whether they fit the `[budgets]` in wavedash.toml.
+//!
+//! Scans exactly what `build push` would upload (same excludes), so the report
+//! and the push never disagree about what's in the build. Over budget is an
+//! error, which makes `build analyze && build push` a CI gate.
+
+use anyhow::{Context, Result};
+use comfy_table::{
+    modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Cell, CellAlignment, ContentArrangement,
+    Table,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::uploader::{build_object_key, format_bytes, scan_directory, ScannedFile};
+use crate::config::WavedashConfig;
+use crate::exclude::ExcludeRules;
+use crate::manifest::hash_reader;
+
+const READ_BUFFER_SIZE: usize = 1024 * 1024; // 1 MiB
+/// Quality 5 is within a few percent of maximum for game assets and an order
+/// of magnitude faster; this is an estimate, not what gets shipped.
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+
+/// Formats that are already compressed. Running them through gzip costs time
+/// and says nothing useful, so they're counted at their own size.
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "webp", "avif", "gif", "mp3", "ogg", "opus", "m4a", "aac", "mp4", "webm",
+    "zip", "7z", "gz", "br", "zst", "woff", "woff2", "ktx2", "basis",
+];
+
+pub struct BuildAnalyzeArgs {
+    pub config_path: PathBuf,
+    pub verbose: bool,
+    pub json: bool,
+    /// How many of the largest files to list.
+    pub top: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Analysis {
+    upload_dir: PathBuf,
+    file_count: usize,
+    total_bytes: u64,
+    largest: Vec<FileSize>,
+    by_extension: Vec<SizeGroup>,
+    by_directory: Vec<SizeGroup>,
+    compression: CompressionEstimate,
+    duplicates: Vec<Duplicate>,
+    over_budget: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct FileSize {
+    path: String,
+    size: u64,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct SizeGroup {
+    name: String,
+    files: usize,
+    bytes: u64,
+}
+
+/// What the compressible files would come to under each encoding. Files in
+/// already-compressed formats are included at their own size.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CompressionEstimate {
+    compressible_bytes: u64,
+    gzip_bytes: u64,
+    brotli_bytes: u64,
+}
+
+/// The same content stored under more than one path; all but one copy is
+/// `wasted_bytes`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Duplicate {
+    size: u64,
+    wasted_bytes: u64,
+    paths: Vec<String>,
+}
+
+pub async fn handle_build_analyze(args: BuildAnalyzeArgs) -> Result<()> {
+    let BuildAnalyzeArgs {
+        config_path,
+        verbose,
+        json,
+        top,
+    } = args;
+
+    let wavedash_config = WavedashConfig::load(&config_path)?;
+    let (config_dir, upload_dir) = super::resolve_upload_dir(&config_path, &wavedash_config)?;
+    let rules = ExcludeRules::load(&upload_dir, config_dir, wavedash_config.excludes())?;
+    let scan = scan_directory(&upload_dir, &rules, wavedash_config.symlinks())?;
+    scan.warn_about_links();
+    if scan.files.is_empty() {
+        anyhow::bail!("No files found in {}", upload_dir.display());
+    }
+    if verbose && !json {
+        println!(
+            "Analyzing {} files ({})...",
+            scan.files.len(),
+            format_bytes(scan.total_bytes)
+        );
+    }
+
+    let mut analysis = Analysis {
+        upload_dir: upload_dir.canonicalize()?,
+        file_count: scan.files.len(),
+        total_bytes: scan.total_bytes,
+        largest: largest(&scan.files, top),
+        by_extension: group_by(&scan.files, extension_of),
+        by_directory: group_by(&scan.files, directory_of),
+        compression: estimate_compression(&scan.files)?,
+        duplicates: find_duplicates(&scan.files)?,
+        over_budget: Vec::new(),
+    };
+
+    let budgets = wavedash_config.budgets();
+    if let Some(max_total) = budgets.max_total {
+        if analysis.total_bytes > max_total {
+            analysis.over_budget.push(format!(
+                "total size {} is over the {} budget",
+                format_bytes(analysis.total_bytes),
+                format_bytes(max_total)
+            ));
+        }
+    }
+    if let Some(max_file) = budgets.max_file {
+        for file in scan.files.iter().filter(|f| f.size > max_file) {
+            analysis.over_budget.push(format!(
+                "{} is {}, over the {} per-file budget",
+                build_object_key("", &file.relative_path),
+                format_bytes(file.size),
+                format_bytes(max_file)
+            ));
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&analysis)?);
+    } else {
+        print_report(&analysis);
+    }
+
+    if !analysis.over_budget.is_empty() {
+        anyhow::bail!(
+            "Build is over its size budget in wavedash.toml:\n  {}",
+            analysis.over_budget.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+fn largest(files: &[ScannedFile], top: usize) -> Vec<FileSize> {
+    let mut sorted: Vec<&ScannedFile> = files.iter().collect();
+    sorted.sort_by(|a, b| {
+        b.size
+            .cmp(&a.size)
+            .then(a.relative_path.cmp(&b.relative_path))
+    });
+    sorted
+        .into_iter()
+        .take(top)
+        .map(|f| FileSize {
+            path: build_object_key("", &f.relative_path),
+            size: f.size,
+        })
+        .collect()
+}
+
+/// Totals per `key`, largest first.
+fn group_by(files: &[ScannedFile], key: fn(&Path) -> String) -> Vec<SizeGroup> {
+    let mut groups: HashMap<String, SizeGroup> = HashMap::new();
+    for file in files {
+        let name = key(&file.relative_path);
+        let group = groups.entry(name.clone()).or_insert(SizeGroup {
+            name,
+            files: 0,
+            bytes: 0,
+        });
+        group.files += 1;
+        group.bytes += file.size;
+    }
+    let mut groups: Vec<SizeGroup> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.name.cmp(&b.name)));
+    groups
+}
+
+/// Lowercased, so `.PNG` and `.png` are one line. Unity's double extensions
+/// are grouped by the last one (`.br`, `.gz`), which is what decides how the
+/// file is served.
+fn extension_of(path: &Path) -> String {
+    path.extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy().to_ascii_lowercase()))
+        .unwrap_or_else(|| "(none)".to_string())
+}
+
+/// The top-level directory a file lives under; `.` for the upload dir itself.
+fn directory_of(path: &Path) -> String {
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => format!("{}/", first.as_os_str().to_string_lossy()),
+        _ => ".".to_string(),
+    }
+}
+
+fn is_precompressed(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.as_str()))
+}
+
+fn estimate_compression(files: &[ScannedFile]) -> Result<CompressionEstimate> {
+    let mut estimate = CompressionEstimate::default();
+    for file in files {
+        if is_precompressed(&file.relative_path) {
+            estimate.gzip_bytes += file.size;
+            estimate.brotli_bytes += file.size;
+            continue;
+        }
+        let (gzip, brotli) =
+            file.read_with(|reader| compressed_sizes(reader, &file.relative_path))?;
+        estimate.compressible_bytes += file.size;
+        estimate.gzip_bytes += gzip;
+        estimate.brotli_bytes += brotli;
+    }
+    Ok(estimate)
+}
+
+/// Counts what's written to it and keeps none of it.
+#[derive(Default)]
+struct CountingSink(u64);
+
+impl Write for CountingSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// gzip (default level) and brotli sizes of one file, compressed in a single
+/// streaming read so a large file never has to fit in memory.
+fn compressed_sizes(reader: &mut dyn Read, path: &Path) -> Result<(u64, u64)> {
+    let mut gzip =
+        flate2::write::GzEncoder::new(CountingSink::default(), flate2::Compression::default());
+    let mut brotli = brotli::CompressorWriter::new(
+        CountingSink::default(),
+        READ_BUFFER_SIZE,
+        BROTLI_QUALITY,
+        BROTLI_WINDOW,
+    );
+    let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+    loop {
+        let read = reader
+            .read(&mut buffer)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        gzip.write_all(&buffer[..read])?;
+        brotli.write_all(&buffer[..read])?;
+    }
+    let gzip = gzip.finish()?.0;
+    brotli.flush()?;
+    let brotli = brotli.into_inner().0;
+    Ok((gzip, brotli))
+}
+
+/// Files with identical content. Only files that share a size with another
+/// file are hashed, which in a typical build is almost none of them.
+fn find_duplicates(files: &[ScannedFile]) -> Result<Vec<Duplicate>> {
+    let mut by_size: HashMap<u64, Vec<&ScannedFile>> = HashMap::new();
+    for file in files.iter().filter(|f| f.size > 0) {
+        by_size.entry(file.size).or_default().push(file);
+    }
+
+    let mut by_hash: HashMap<String, Vec<&ScannedFile>> = HashMap::new();
+    for candidates in by_size.into_values().filter(|c| c.len() > 1) {
+        for file in candidates {
+            let label = file.relative_path.display().to_string();
+            let (_, sha256) = file.read_with(|reader| hash_reader(reader, &label))?;
+            by_hash.entry(sha256).or_default().push(file);
+        }
+    }
+
+    let mut duplicates: Vec<Duplicate> = by_hash
+        .into_values()
+        .filter(|copies| copies.len() > 1)
+        .map(|copies| {
+            let size = copies[0].size;
+            let mut paths: Vec<String> = copies
+                .iter()
+                .map(|f| build_object_key("", &f.relative_path))
+                .collect();
+            paths.sort();
+            Duplicate {
+                size,
+                wasted_bytes: size * (paths.len() as u64 - 1),
+                paths,
+            }
+        })
+        .collect();
+    duplicates.sort_by(|a, b| {
+        b.wasted_bytes
+            .cmp(&a.wasted_bytes)
+            .then(a.paths.cmp(&b.paths))
+    });
+    Ok(duplicates)
+}
+
+fn size_table(header: &str, rows: impl IntoIterator<Item = (String, String, u64)>) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new(header),
+            Cell::new("Files"),
+            Cell::new("Size"),
+        ]);
+    for (name, files, bytes) in rows {
+        table.add_row(vec![
+            Cell::new(name),
+            Cell::new(files).set_alignment(CellAlignment::Right),
+            Cell::new(format_bytes(bytes)).set_alignment(CellAlignment::Right),
+        ]);
+    }
+    table
+}
+
+fn percent_saved(original: u64, compressed: u64) -> String {
+    if original == 0 {
+        return "0%".to_string();
+    }
+    let saved = original.saturating_sub(compressed) as f64 / original as f64;
+    format!("{:.0}%", saved * 100.0)
+}
+
+fn print_report(analysis: &Analysis) {
+    println!(
+        "{}: {} files, {}\n",
+        analysis.upload_dir.display(),
+        analysis.file_count,
+        format_bytes(analysis.total_bytes)
+    );
+
+    println!("Largest files");
+    let mut largest = Table::new();
+    largest
+        .load_preset(UTF8_FULL)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![Cell::new("Path"), Cell::new("Size")]);
+    for file in &analysis.largest {
+        largest.add_row(vec![
+            Cell::new(&file.path),
+            Cell::new(format_bytes(file.size)).set_alignment(CellAlignment::Right),
+        ]);
+    }
+    println!("{largest}\n");
+
+    println!("By extension");
+    let rows = analysis
+        .by_extension
+        .iter()
+        .map(|g| (g.name.clone(), g.files.to_string(), g.bytes));
+    println!("{}\n", size_table("Extension", rows));
+
+    println!("By directory");
+    let rows = analysis
+        .by_directory
+        .iter()
+        .map(|g| (g.name.clone(), g.files.to_string(), g.bytes));
+    println!("{}\n", size_table("Directory", rows));
+
+    let compression = &analysis.compression;
+    println!(
+        "Compression estimate ({} of {} is compressible):",
+        format_bytes(compression.compressible_bytes),
+        format_bytes(analysis.total_bytes)
+    );
+    println!(
+        "  gzip:   {} ({} smaller)",
+        format_bytes(compression.gzip_bytes),
+        percent_saved(analysis.total_bytes, compression.gzip_bytes)
+    );
+    println!(
+        "  brotli: {} ({} smaller)",
+        format_bytes(compression.brotli_bytes),
+        percent_saved(analysis.total_bytes, compression.brotli_bytes)
+    );
+
+    if analysis.duplicates.is_empty() {
+        println!("\nNo duplicate files.");
+    } else {
+        let wasted: u64 = analysis.duplicates.iter().map(|d| d.wasted_bytes).sum();
+        println!(
+            "\nDuplicate files ({} could be saved):",
+            format_bytes(wasted)
+        );
+        for duplicate in &analysis.duplicates {
+            println!(
+                "  {} × {}: {}",
+                duplicate.paths.len(),
+                format_bytes(duplicate.size),
+                duplicate.paths.join(", ")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_by_lowercased_extension_and_top_level_directory() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let files = vec![
+            ScannedFile::written(dir.path(), "index.html", b"12"),
+            ScannedFile::written(dir.path(), "assets/a.PNG", b"1234"),
+            ScannedFile::written(dir.path(), "assets/deep/b.png", b"123456"),
+        ];
+
+        assert_eq!(
+            group_by(&files, extension_of),
+            vec![
+                SizeGroup {
+                    name: ".png".to_string(),
+                    files: 2,
+                    bytes: 10
+                },
+                SizeGroup {
+                    name: ".html".to_string(),
+                    files: 1,
+                    bytes: 2
+                },
+            ]
+        );
+        let directories: Vec<String> = group_by(&files, directory_of)
+            .into_iter()
+            .map(|g| g.name)
+            .collect();
+        assert_eq!(directories, vec!["assets/", "."]);
+    }
+
+    #[test]
+    fn same_content_under_two_paths_is_a_duplicate() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let files = vec![
+            ScannedFile::written(dir.path(), "a/music.ogg", b"same bytes"),
+            ScannedFile::written(dir.path(), "b/music.ogg", b"same bytes"),
+            // Same size, different content.
+            ScannedFile::written(dir.path(), "c/other.ogg", b"diff bytes"),
+        ];
+
+        let duplicates = find_duplicates(&files).expect("duplicates");
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].paths, vec!["a/music.ogg", "b/music.ogg"]);
+        assert_eq!(duplicates[0].wasted_bytes, 10);
+    }
+
+    #[test]
+    fn precompressed_files_are_counted_as_they_are() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let text = "wavedash ".repeat(1000);
+        let files = vec![
+            ScannedFile::written(dir.path(), "game.js", text.as_bytes()),
+            ScannedFile::written(dir.path(), "game.wasm.br", text.as_bytes()),
+        ];
+
+        let estimate = estimate_compression(&files).expect("estimate");
+        assert_eq!(estimate.compressible_bytes, text.len() as u64);
+        assert!(estimate.gzip_bytes < 2 * text.len() as u64);
+        assert!(estimate.gzip_bytes > text.len() as u64);
+        assert!(estimate.brotli_bytes > text.len() as u64);
+    }
+}
@@ -0,0 +1,548 @@
+//! Reading a build straight out of a `.zip` or `.tar.gz`, so a build farm's
+//! zipped export can be pushed without unpacking it somewhere first.
+//!
+//! The two formats differ in the one way that matters here. A zip has a central
+//! directory, so any entry can be opened on its own. A `.tar.gz` is a single
+//! gzip stream, and reaching an entry means decompressing everything before it.
+//! So anything that needs every entry reads them in one pass with
+//! [`Archive::for_each_entry`], and [`Archive::read_entry`] is for the odd
+//! entry that has to be read again, like a retried upload.
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveKind {
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Archive {
+    path: PathBuf,
+    kind: ArchiveKind,
+}
+
+/// One regular file in the archive, or a tar hard link to one, which is
+/// uploaded as a copy of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// `/`-separated and relative, with any `./` prefix removed: the same form
+    /// as a manifest path or an object key's suffix.
+    pub path: String,
+    pub size: u64,
+}
+
+/// A symlink stored in the archive, or a hard link to no file before it.
+/// There's nothing on disk for it to point at, so it's never uploaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveLink {
+    pub path: String,
+    pub target: Option<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+pub struct ArchiveListing {
+    pub files: Vec<ArchiveEntry>,
+    pub links: Vec<ArchiveLink>,
+}
+
+impl Archive {
+    /// True when `path` names a format this module reads. Goes by the file
+    /// name alone, the way `upload_dir` is told apart from a directory.
+    pub fn is_archive(path: &Path) -> bool {
+        ArchiveKind::of(path).is_some()
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let kind = ArchiveKind::of(path).ok_or_else(|| {
+            anyhow::anyhow!("{} isn't a .zip, .tar.gz or .tgz archive", path.display())
+        })?;
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", path.display()))?;
+        if !path.is_file() {
+            anyhow::bail!("Archive is not a file: {}", path.display());
+        }
+        Ok(Self { path, kind })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every file and symlink in the archive, in archive order. Directory
+    /// entries are implied by the files in them and left out, and a hard link
+    /// is listed as a file the size of its target; one with no target before
+    /// it is listed as a link. Fails on an entry that would land outside the
+    /// build, and on a path that appears twice, since only one of the two
+    /// could be uploaded.
+    pub fn list(&self) -> Result<ArchiveListing> {
+        let mut listing = ArchiveListing::default();
+        match self.kind {
+            ArchiveKind::Zip => {
+                let mut zip = self.open_zip()?;
+                for index in 0..zip.len() {
+                    let mut file = zip.by_index(index).with_context(|| self.context())?;
+                    if file.is_dir() {
+                        continue;
+                    }
+                    let path = entry_path(file.name())?;
+                    if file.is_symlink() {
+                        let mut target = String::new();
+                        let target = file
+                            .read_to_string(&mut target)
+                            .ok()
+                            .map(|_| PathBuf::from(target));
+                        listing.links.push(ArchiveLink { path, target });
+                    } else {
+                        listing.files.push(ArchiveEntry {
+                            path,
+                            size: file.size(),
+                        });
+                    }
+                }
+            }
+            ArchiveKind::TarGz => {
+                let mut tar = self.open_tar()?;
+                let mut hard_links = HardLinks::default();
+                for entry in tar.entries().with_context(|| self.context())? {
+                    let entry = entry.with_context(|| self.context())?;
+                    let kind = entry.header().entry_type();
+                    if !(kind.is_file() || kind.is_symlink() || kind.is_hard_link()) {
+                        continue;
+                    }
+                    let raw = entry.path().with_context(|| self.context())?;
+                    let path = entry_path(&raw.to_string_lossy())?;
+                    let target = entry
+                        .link_name()
+                        .ok()
+                        .flatten()
+                        .map(|target| target.into_owned());
+                    if kind.is_file() {
+                        hard_links.file(&path, entry.size());
+                        listing.files.push(ArchiveEntry {
+                            path,
+                            size: entry.size(),
+                        });
+                    } else if let Some((_, size)) = kind
+                        .is_hard_link()
+                        .then(|| hard_links.resolve(&path, target.as_deref()))
+                        .flatten()
+                    {
+                        listing.files.push(ArchiveEntry { path, size });
+                    } else {
+                        listing.links.push(ArchiveLink { path, target });
+                    }
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for path in listing
+            .files
+            .iter()
+            .map(|f| &f.path)
+            .chain(listing.links.iter().map(|l| &l.path))
+        {
+            if !seen.insert(path) {
+                anyhow::bail!("{} contains {} more than once", self.path.display(), path);
+            }
+        }
+        Ok(listing)
+    }
+
+    /// Calls `f` with each regular file and its contents, in archive order, in
+    /// a single pass. `f` doesn't have to read an entry to the end. A tar's
+    /// hard links come last, since their contents are their targets', which
+    /// the pass has gone by; reading them takes another.
+    pub fn for_each_entry(
+        &self,
+        mut f: impl FnMut(&ArchiveEntry, &mut dyn Read) -> Result<()>,
+    ) -> Result<()> {
+        match self.kind {
+            ArchiveKind::Zip => {
+                let mut zip = self.open_zip()?;
+                for index in 0..zip.len() {
+                    let mut file = zip.by_index(index).with_context(|| self.context())?;
+                    if file.is_dir() || file.is_symlink() {
+                        continue;
+                    }
+                    let entry = ArchiveEntry {
+                        path: entry_path(file.name())?,
+                        size: file.size(),
+                    };
+                    f(&entry, &mut file)?;
+                }
+            }
+            ArchiveKind::TarGz => self.for_each_tar_entry(None, &mut f)?,
+        }
+        Ok(())
+    }
+
+    /// [`Archive::for_each_entry`] for a `.tar.gz`, leaving out the hard links
+    /// other than `only`, when given, so reading one file doesn't take a
+    /// second pass for links that weren't asked for.
+    fn for_each_tar_entry(
+        &self,
+        only: Option<&str>,
+        f: &mut dyn FnMut(&ArchiveEntry, &mut dyn Read) -> Result<()>,
+    ) -> Result<()> {
+        let mut hard_links = HardLinks::default();
+        // Each link with the regular file it's a copy of.
+        let mut linked: Vec<(ArchiveEntry, String)> = Vec::new();
+        let mut tar = self.open_tar()?;
+        for entry in tar.entries().with_context(|| self.context())? {
+            let mut entry = entry.with_context(|| self.context())?;
+            let kind = entry.header().entry_type();
+            if !(kind.is_file() || kind.is_hard_link()) {
+                continue;
+            }
+            let raw = entry.path().with_context(|| self.context())?;
+            let path = entry_path(&raw.to_string_lossy())?;
+            if kind.is_hard_link() {
+                let target = entry.link_name().ok().flatten();
+                if let Some((target, size)) = hard_links.resolve(&path, target.as_deref()) {
+                    if only.is_none_or(|only| only == path) {
+                        linked.push((ArchiveEntry { path, size }, target));
+                    }
+                }
+                continue;
+            }
+            hard_links.file(&path, entry.size());
+            let archive_entry = ArchiveEntry {
+                path,
+                size: entry.size(),
+            };
+            f(&archive_entry, &mut entry)?;
+        }
+
+        // A target with several links to it is read once per pass, for one
+        // of them.
+        while !linked.is_empty() {
+            let before = linked.len();
+            let mut tar = self.open_tar()?;
+            for entry in tar.entries().with_context(|| self.context())? {
+                let mut entry = entry.with_context(|| self.context())?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let raw = entry.path().with_context(|| self.context())?;
+                let path = entry_path(&raw.to_string_lossy())?;
+                if let Some(at) = linked.iter().position(|(_, target)| *target == path) {
+                    let (link, _) = linked.remove(at);
+                    f(&link, &mut entry)?;
+                }
+            }
+            // Only an archive rewritten between passes loses a target; the
+            // caller reports whatever never turned up.
+            if linked.len() == before {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with the contents of the file at `path`. Cheap for a zip;
+    /// for a `.tar.gz` it decompresses up to the entry.
+    pub fn read_entry<T>(
+        &self,
+        path: &str,
+        f: impl FnOnce(&mut dyn Read) -> Result<T>,
+    ) -> Result<T> {
+        let mut f = Some(f);
+        let mut result = None;
+        self.for_each_until(path, &mut |reader| {
+            let f = f.take().expect("an archive path is only matched once");
+            result = Some(f(reader)?);
+            Ok(())
+        })?;
+        result.ok_or_else(|| anyhow::anyhow!("{} is not in {}", path, self.path.display()))
+    }
+
+    fn for_each_until(
+        &self,
+        path: &str,
+        f: &mut dyn FnMut(&mut dyn Read) -> Result<()>,
+    ) -> Result<()> {
+        match self.kind {
+            ArchiveKind::Zip => {
+                let mut zip = self.open_zip()?;
+                // Names are compared after normalizing, so find the index
+                // without decompressing anything.
+                let index = (0..zip.len()).find(|&index| {
+                    zip.name_for_index(index)
+                        .and_then(|name| entry_path(name).ok())
+                        .is_some_and(|name| name == path)
+                });
+                if let Some(index) = index {
+                    let mut file = zip.by_index(index).with_context(|| self.context())?;
+                    f(&mut file)?;
+                }
+                Ok(())
+            }
+            ArchiveKind::TarGz => {
+                let mut found = false;
+                self.for_each_tar_entry(Some(path), &mut |entry, reader| {
+                    if !found && entry.path == path {
+                        found = true;
+                        f(reader)?;
+                    }
+                    Ok(())
+                })
+            }
+        }
+    }
+
+    fn open_zip(&self) -> Result<zip::ZipArchive<BufReader<File>>> {
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        zip::ZipArchive::new(BufReader::new(file)).with_context(|| self.context())
+    }
+
+    fn open_tar(&self) -> Result<tar::Archive<GzDecoder<BufReader<File>>>> {
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        Ok(tar::Archive::new(GzDecoder::new(BufReader::new(file))))
+    }
+
+    fn context(&self) -> String {
+        format!("Failed to read archive {}", self.path.display())
+    }
+}
+
+/// The regular files a tar has gone by, for telling what its hard links are
+/// copies of. A link always names an entry stored before it, which may itself
+/// be a link.
+#[derive(Default)]
+struct HardLinks {
+    /// Regular file path to size.
+    files: HashMap<String, u64>,
+    /// Link path to the regular file it ends up at.
+    links: HashMap<String, String>,
+}
+
+impl HardLinks {
+    fn file(&mut self, path: &str, size: u64) {
+        self.files.insert(path.to_string(), size);
+    }
+
+    /// The regular file the link at `path` is a copy of, and its size, or
+    /// `None` when its target isn't one seen so far.
+    fn resolve(&mut self, path: &str, target: Option<&Path>) -> Option<(String, u64)> {
+        let target = entry_path(&target?.to_string_lossy()).ok()?;
+        let target = self.links.get(&target).cloned().unwrap_or(target);
+        let size = *self.files.get(&target)?;
+        self.links.insert(path.to_string(), target.clone());
+        Some((target, size))
+    }
+}
+
+/// An entry's name as a build path, or an error when it isn't a plain relative
+/// path. Archives are written by other tools, and a `../` entry would
+/// otherwise turn into an object key outside the build.
+pub fn entry_path(raw: &str) -> Result<String> {
+    let mut parts = Vec::new();
+    for component in Path::new(&raw.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                anyhow::bail!("Archive entry '{}' points outside the archive", raw)
+            }
+        }
+    }
+    if parts.is_empty() {
+        anyhow::bail!("Archive entry '{}' has no file name", raw);
+    }
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).expect("create"));
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("Build/", options).expect("dir");
+        for (name, contents) in files {
+            zip.start_file(*name, options).expect("start");
+            zip.write_all(contents).expect("write");
+        }
+        zip.finish().expect("finish");
+    }
+
+    fn write_tar_gz(path: &Path, files: &[(&str, &[u8])]) {
+        let gz = flate2::write::GzEncoder::new(
+            File::create(path).expect("create"),
+            flate2::Compression::fast(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, name, *contents)
+                .expect("append");
+        }
+        tar.into_inner().expect("tar").finish().expect("gzip");
+    }
+
+    fn contents(archive: &Archive) -> Vec<(String, String)> {
+        let mut read = Vec::new();
+        archive
+            .for_each_entry(|entry, reader| {
+                let mut text = String::new();
+                reader.read_to_string(&mut text)?;
+                read.push((entry.path.clone(), text));
+                Ok(())
+            })
+            .expect("read");
+        read
+    }
+
+    #[test]
+    fn zip_and_tar_gz_list_and_read_the_same_way() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let files: &[(&str, &[u8])] = &[("index.html", b"hello"), ("Build/game.wasm", b"wasm")];
+        let zip_path = dir.path().join("web.zip");
+        write_zip(&zip_path, files);
+        let tar_path = dir.path().join("web.tar.gz");
+        // `tar -C out .` writes `./`-prefixed names.
+        write_tar_gz(
+            &tar_path,
+            &[("./index.html", b"hello"), ("./Build/game.wasm", b"wasm")],
+        );
+
+        for path in [&zip_path, &tar_path] {
+            let archive = Archive::open(path).expect("open");
+            let listing = archive.list().expect("list");
+            assert_eq!(
+                listing.files,
+                vec![
+                    ArchiveEntry {
+                        path: "index.html".to_string(),
+                        size: 5
+                    },
+                    ArchiveEntry {
+                        path: "Build/game.wasm".to_string(),
+                        size: 4
+                    },
+                ]
+            );
+            assert_eq!(
+                contents(&archive),
+                vec![
+                    ("index.html".to_string(), "hello".to_string()),
+                    ("Build/game.wasm".to_string(), "wasm".to_string()),
+                ]
+            );
+            let wasm = archive
+                .read_entry("Build/game.wasm", |reader| {
+                    let mut text = String::new();
+                    reader.read_to_string(&mut text)?;
+                    Ok(text)
+                })
+                .expect("read one");
+            assert_eq!(wasm, "wasm");
+            assert!(archive.read_entry("missing.js", |_| Ok(())).is_err());
+        }
+    }
+
+    #[test]
+    fn tar_hard_links_read_as_copies_of_their_target() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("web.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            File::create(&path).expect("create"),
+            flate2::Compression::fast(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "./Build/game.wasm", &b"wasm"[..])
+            .expect("append");
+        // `tar` names the first copy it stored; a link to a link ends up there
+        // too, and one to nothing stored before it can't be read.
+        for (name, target) in [
+            ("Build/copy.wasm", "./Build/game.wasm"),
+            ("Build/again.wasm", "Build/copy.wasm"),
+            ("Build/lost.wasm", "Build/later.wasm"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Link);
+            header.set_size(0);
+            tar.append_link(&mut header, name, target).expect("link");
+        }
+        tar.into_inner().expect("tar").finish().expect("gzip");
+
+        let archive = Archive::open(&path).expect("open");
+        let listing = archive.list().expect("list");
+        let files: Vec<(&str, u64)> = listing
+            .files
+            .iter()
+            .map(|f| (f.path.as_str(), f.size))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("Build/game.wasm", 4),
+                ("Build/copy.wasm", 4),
+                ("Build/again.wasm", 4)
+            ]
+        );
+        assert_eq!(listing.links.len(), 1);
+        assert_eq!(listing.links[0].path, "Build/lost.wasm");
+
+        assert_eq!(
+            contents(&archive),
+            vec![
+                ("Build/game.wasm".to_string(), "wasm".to_string()),
+                ("Build/copy.wasm".to_string(), "wasm".to_string()),
+                ("Build/again.wasm".to_string(), "wasm".to_string()),
+            ]
+        );
+        let copy = archive
+            .read_entry("Build/again.wasm", |reader| {
+                let mut text = String::new();
+                reader.read_to_string(&mut text)?;
+                Ok(text)
+            })
+            .expect("read one");
+        assert_eq!(copy, "wasm");
+    }
+
+    #[test]
+    fn entries_must_stay_inside_the_build() {
+        assert_eq!(entry_path("./Build/game.js").unwrap(), "Build/game.js");
+        assert_eq!(entry_path("Build\\game.js").unwrap(), "Build/game.js");
+        assert!(entry_path("../evil.js").is_err());
+        assert!(entry_path("/etc/passwd").is_err());
+        assert!(entry_path("./").is_err());
+
+        assert!(Archive::is_archive(Path::new("out/web.TGZ")));
+        assert!(!Archive::is_archive(Path::new("out/web")));
+    }
+}
@@ -0,0 +1,316 @@
+//! `wavedash build list` and `build show`: reading a game's builds back from
+//! the server, so a build id never has to be scraped from `build push` output.
+
+use anyhow::Result;
+use comfy_table::{
+    modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Cell, CellAlignment, ContentArrangement,
+    Table,
+};
+use serde::{Deserialize, Serialize};
+
+use super::uploader::format_bytes;
+use crate::auth::require_api_key;
+use crate::config;
+use crate::git::GitProvenance;
+
+pub const DEFAULT_LIST_LIMIT: usize = 20;
+
+/// One build as the server reports it. Optional fields are the ones a build
+/// can legitimately lack (no engine, no message), plus anything older builds
+/// were created without.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildRecord {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub uuid: String,
+    #[serde(default)]
+    pub upload_source: Option<String>,
+    #[serde(default)]
+    pub engine: Option<String>,
+    #[serde(default)]
+    pub engine_version: Option<String>,
+    #[serde(default)]
+    pub entrypoint: Option<String>,
+    #[serde(default)]
+    pub entrypoint_params: Option<serde_json::Value>,
+    #[serde(default, rename = "buildMessage")]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub build_size_bytes: Option<u64>,
+    /// Where the build came from, for builds pushed from a git checkout.
+    #[serde(default)]
+    pub git: Option<GitProvenance>,
+    /// The build this one was pushed against, which stores every file this
+    /// one didn't upload.
+    #[serde(default)]
+    pub base_build_id: Option<String>,
+    /// Milliseconds since the epoch.
+    #[serde(rename = "_creationTime")]
+    pub created_at: f64,
+    /// Has been published at least once.
+    #[serde(default)]
+    pub published: bool,
+    /// Is the build players currently get.
+    #[serde(default)]
+    pub live: bool,
+    /// Created by `wavedash dev` rather than uploaded.
+    #[serde(default)]
+    pub local: bool,
+}
+
+impl BuildRecord {
+    pub(super) fn created_label(&self) -> String {
+        chrono::DateTime::from_timestamp_millis(self.created_at as i64)
+            .map(|t| {
+                t.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    pub(super) fn status_label(&self) -> &'static str {
+        if self.live {
+            "live"
+        } else if self.published {
+            "published"
+        } else if self.local {
+            "dev"
+        } else {
+            "-"
+        }
+    }
+
+    /// `a1b2c3d* main v1.2`: the commit, `*` if the tree was dirty, then the
+    /// branch and tag it had.
+    fn git_label(&self) -> String {
+        let Some(git) = &self.git else {
+            return "-".to_string();
+        };
+        [
+            Some(git.short_commit()),
+            git.branch.clone(),
+            git.tag.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+
+    fn engine_label(&self) -> String {
+        match (&self.engine, &self.engine_version) {
+            (Some(engine), Some(version)) => format!("{} {}", engine, version),
+            (Some(engine), None) => engine.clone(),
+            (None, _) => "-".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildListResponse {
+    pub builds: Vec<BuildRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct BuildShowResponse {
+    pub build: BuildRecord,
+    pub game_slug: String,
+}
+
+/// Where a build can be played before it's published.
+pub fn playtest_url(site_host: &str, game_slug: &str, uuid: &str) -> String {
+    format!("{}/playtest/{}/{}", site_host, game_slug, uuid)
+}
+
+/// A game's builds, newest first. `limit` of `None` asks for all of them.
+pub async fn fetch_builds(
+    game_id: &str,
+    limit: Option<usize>,
+    api_key: &str,
+) -> Result<BuildListResponse> {
+    let client = config::create_http_client()?;
+    let api_host = config::get("api_host")?;
+    let mut url = format!("{}/api/games/{}/builds", api_host, game_id);
+    if let Some(limit) = limit {
+        url.push_str(&format!("?limit={}", limit));
+    }
+
+    let response = client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await?;
+    let response = config::check_api_response(response).await?;
+    Ok(response.json().await?)
+}
+
+pub(super) async fn fetch_build(
+    game_id: &str,
+    build_id: &str,
+    api_key: &str,
+) -> Result<BuildShowResponse> {
+    let client = config::create_http_client()?;
+    let api_host = config::get("api_host")?;
+    let url = format!("{}/api/games/{}/builds/{}", api_host, game_id, build_id);
+
+    let response = client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await?;
+    let response = config::check_api_response(response).await?;
+    Ok(response.json().await?)
+}
+
+pub async fn handle_build_list(game_id: &str, limit: usize, json: bool) -> Result<()> {
+    let api_key = require_api_key()?;
+    let data = fetch_builds(game_id, Some(limit), &api_key).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&data.builds)?);
+        return Ok(());
+    }
+
+    if data.builds.is_empty() {
+        println!("No builds found. Upload one with `wavedash build push`.");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("ID"),
+            Cell::new("UUID"),
+            Cell::new("Source"),
+            Cell::new("Engine"),
+            Cell::new("Message"),
+            Cell::new("Commit"),
+            Cell::new("Size"),
+            Cell::new("Created"),
+            Cell::new("Status"),
+        ]);
+
+    for build in &data.builds {
+        table.add_row(vec![
+            Cell::new(&build.id),
+            Cell::new(&build.uuid),
+            Cell::new(build.upload_source.as_deref().unwrap_or("-")),
+            Cell::new(build.engine_label()),
+            Cell::new(build.message.as_deref().unwrap_or("-")),
+            Cell::new(build.git_label()),
+            Cell::new(
+                build
+                    .build_size_bytes
+                    .map(format_bytes)
+                    .unwrap_or_else(|| "-".to_string()),
+            )
+            .set_alignment(CellAlignment::Right),
+            Cell::new(build.created_label()),
+            Cell::new(build.status_label()),
+        ]);
+    }
+
+    println!("{table}");
+    Ok(())
+}
+
+pub async fn handle_build_show(game_id: &str, build_id: &str, json: bool) -> Result<()> {
+    let api_key = require_api_key()?;
+    let BuildShowResponse { build, game_slug } = fetch_build(game_id, build_id, &api_key).await?;
+    let site_host = config::get("open_browser_website_host")?;
+    let play_url = playtest_url(&site_host, &game_slug, &build.uuid);
+
+    if json {
+        let mut value = serde_json::to_value(&build)?;
+        value["playtestUrl"] = serde_json::json!(play_url);
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+
+    println!("Build ID:    {}", build.id);
+    println!("UUID:        {}", build.uuid);
+    println!("Status:      {}", build.status_label());
+    println!("Created:     {}", build.created_label());
+    println!(
+        "Source:      {}",
+        build.upload_source.as_deref().unwrap_or("-")
+    );
+    println!("Engine:      {}", build.engine_label());
+    if let Some(entrypoint) = &build.entrypoint {
+        println!("Entrypoint:  {}", entrypoint);
+    }
+    if let Some(params) = &build.entrypoint_params {
+        println!("Entrypoint params: {}", params);
+    }
+    if let Some(size) = build.build_size_bytes {
+        println!("Size:        {}", format_bytes(size));
+    }
+    if let Some(message) = &build.message {
+        println!("Message:     {}", message);
+    }
+    if let Some(git) = &build.git {
+        let dirty = if git.dirty {
+            " (uncommitted changes)"
+        } else {
+            ""
+        };
+        println!("Commit:      {}{}", git.commit, dirty);
+        if let Some(branch) = &git.branch {
+            println!("Branch:      {}", branch);
+        }
+        if let Some(tag) = &git.tag {
+            println!("Tag:         {}", tag);
+        }
+    }
+    println!("▶ Play at: {}", play_url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Older builds predate most of the optional fields; they still have to
+    /// list.
+    #[test]
+    fn a_build_with_only_the_required_fields_parses() {
+        let build: BuildRecord = serde_json::from_str(
+            r#"{"_id": "b1", "uuid": "u1", "_creationTime": 1700000000000.5}"#,
+        )
+        .expect("parse");
+        assert_eq!(build.status_label(), "-");
+        assert_eq!(build.engine_label(), "-");
+        assert!(build.message.is_none());
+    }
+
+    #[test]
+    fn live_wins_over_published() {
+        let build: BuildRecord = serde_json::from_str(
+            r#"{"_id": "b1", "uuid": "u1", "_creationTime": 0, "published": true, "live": true,
+                "engine": "GODOT", "engineVersion": "4.3", "buildMessage": "hi"}"#,
+        )
+        .expect("parse");
+        assert_eq!(build.status_label(), "live");
+        assert_eq!(build.engine_label(), "GODOT 4.3");
+        assert_eq!(build.message.as_deref(), Some("hi"));
+        assert_eq!(build.git_label(), "-");
+    }
+
+    #[test]
+    fn git_provenance_lists_as_commit_branch_and_tag() {
+        let build: BuildRecord = serde_json::from_str(
+            r#"{"_id": "b1", "uuid": "u1", "_creationTime": 0,
+                "git": {"commit": "0123456789abcdef0123456789abcdef01234567", "branch": "main",
+                        "tag": "v1.2", "dirty": true, "subject": "Fix the jump arc"}}"#,
+        )
+        .expect("parse");
+        assert_eq!(build.git_label(), "0123456* main v1.2");
+    }
+}
@@ -0,0 +1,354 @@
+//! `[build]`: running the project's export before `build push` and `dev`, so
+//! what goes up is what the project currently builds to rather than whatever
+//! was last exported by hand.
+//!
+//! With `inputs`, a run is skipped while the files they match are the ones the
+//! last successful run saw. A file counts as unchanged when its size and mtime
+//! match, or failing that when its SHA-256 does, so a checkout that rewrites
+//! files without changing them doesn't force a re-export. What the last run
+//! saw is kept per config file under `wavedash_dir()/build-stamps`.
+
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+use walkdir::WalkDir;
+
+use crate::config::{self, BuildStep, WavedashConfig};
+use crate::manifest::{hash_file, hash_reader};
+
+/// What a successful run saw: the step as it was configured, and every input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Stamp {
+    command: String,
+    cwd: PathBuf,
+    env: BTreeMap<String, String>,
+    inputs: Vec<String>,
+    files: BTreeMap<String, InputFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct InputFile {
+    size: u64,
+    /// Nanoseconds since the Unix epoch.
+    modified: u64,
+    sha256: String,
+}
+
+/// An input as found on disk, before anything has been hashed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Observed {
+    size: u64,
+    modified: u64,
+}
+
+/// Run the config's `[build]` command, if it has one and its inputs have
+/// changed, with its output streamed through. Fails if the command does.
+/// Under `json` the command's stdout goes to stderr, so the caller's report
+/// stays the only thing on stdout.
+pub fn run_build_step(
+    wavedash_config: &WavedashConfig,
+    config_path: &Path,
+    json: bool,
+) -> Result<()> {
+    let Some(step) = wavedash_config.build_step()? else {
+        return Ok(());
+    };
+    let config_path = config_path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", config_path.display()))?;
+    let config_dir = config_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Config file has no parent directory"))?;
+    let cwd = config_dir.join(step.cwd.unwrap_or(Path::new("")));
+    let cwd = cwd
+        .canonicalize()
+        .with_context(|| format!("[build] cwd {} does not exist", cwd.display()))?;
+    let upload_dir = config_dir.join(wavedash_config.upload_dir()?);
+    let say = |line: String| {
+        if json {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    };
+
+    let stamp_path = stamp_path(&config_path)?;
+    if !step.inputs.is_empty() && upload_dir.exists() {
+        let observed = scan_inputs(&cwd, step.inputs, &upload_dir)?;
+        if let Some(stamp) = read_stamp(&stamp_path) {
+            if let Some(fresh) = up_to_date(&stamp, &step, &cwd, &observed)? {
+                if fresh != stamp {
+                    write_stamp(&stamp_path, &fresh)?;
+                }
+                say(format!(
+                    "✓ Build is up to date with its inputs, skipping `{}`",
+                    step.command
+                ));
+                return Ok(());
+            }
+        }
+    }
+
+    say(format!("Running build: {}", step.command));
+    let mut command = shell(step.command);
+    command.current_dir(&cwd).envs(step.env);
+    if json {
+        command.stdout(Stdio::from(std::io::stderr()));
+    }
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to run [build] command `{}`", step.command))?;
+    if !status.success() {
+        anyhow::bail!(
+            "[build] command `{}` failed ({}). Fix the build, or pass --no-build to use what's already in {}.",
+            step.command,
+            status,
+            upload_dir.display()
+        );
+    }
+
+    if !step.inputs.is_empty() {
+        let observed = scan_inputs(&cwd, step.inputs, &upload_dir)?;
+        write_stamp(&stamp_path, &stamp_for(&step, &cwd, &observed)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// Every file under `cwd` that `patterns` match, by `/`-separated path
+/// relative to it. `upload_dir` is never searched: it's what the command
+/// writes, not what it reads.
+fn scan_inputs(
+    cwd: &Path,
+    patterns: &[String],
+    upload_dir: &Path,
+) -> Result<BTreeMap<String, Observed>> {
+    let matcher = input_matcher(cwd, patterns)?;
+    let upload_dir = upload_dir.canonicalize().ok();
+    let mut observed = BTreeMap::new();
+    let walk = WalkDir::new(cwd).into_iter().filter_entry(|entry| {
+        !(entry.file_type().is_dir()
+            && (entry.file_name() == ".git" || Some(entry.path()) == upload_dir.as_deref()))
+    });
+    for entry in walk {
+        let entry = entry.with_context(|| format!("Failed to read {}", cwd.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(cwd)?;
+        if !matcher
+            .matched_path_or_any_parents(relative, false)
+            .is_ignore()
+        {
+            continue;
+        }
+        let metadata = entry
+            .metadata()
+            .with_context(|| format!("Failed to read {}", entry.path().display()))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos() as u64);
+        let key = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        observed.insert(
+            key,
+            Observed {
+                size: metadata.len(),
+                modified,
+            },
+        );
+    }
+    Ok(observed)
+}
+
+fn input_matcher(cwd: &Path, patterns: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(cwd);
+    let source = PathBuf::from("wavedash.toml");
+    for pattern in patterns {
+        builder
+            .add_line(Some(source.clone()), pattern)
+            .with_context(|| format!("Invalid [build] input pattern '{}'", pattern))?;
+    }
+    builder
+        .build()
+        .context("Failed to compile [build] input patterns")
+}
+
+/// `stamp` brought up to date with `observed` if nothing it recorded has
+/// really changed, or `None` if something has. Files whose mtime moved are
+/// hashed to tell.
+fn up_to_date(
+    stamp: &Stamp,
+    step: &BuildStep<'_>,
+    cwd: &Path,
+    observed: &BTreeMap<String, Observed>,
+) -> Result<Option<Stamp>> {
+    let same_step = stamp.command == step.command
+        && stamp.cwd == cwd
+        && stamp.env == *step.env
+        && stamp.inputs == step.inputs;
+    if !same_step || stamp.files.len() != observed.len() {
+        return Ok(None);
+    }
+    let mut fresh = stamp.clone();
+    for (path, seen) in observed {
+        let Some(recorded) = fresh.files.get_mut(path) else {
+            return Ok(None);
+        };
+        if recorded.size != seen.size {
+            return Ok(None);
+        }
+        if recorded.modified != seen.modified {
+            let (_, sha256) = hash_file(&cwd.join(path))?;
+            if sha256 != recorded.sha256 {
+                return Ok(None);
+            }
+            recorded.modified = seen.modified;
+        }
+    }
+    Ok(Some(fresh))
+}
+
+fn stamp_for(
+    step: &BuildStep<'_>,
+    cwd: &Path,
+    observed: &BTreeMap<String, Observed>,
+) -> Result<Stamp> {
+    let mut files = BTreeMap::new();
+    for (path, seen) in observed {
+        let (_, sha256) = hash_file(&cwd.join(path))?;
+        files.insert(
+            path.clone(),
+            InputFile {
+                size: seen.size,
+                modified: seen.modified,
+                sha256,
+            },
+        );
+    }
+    Ok(Stamp {
+        command: step.command.to_string(),
+        cwd: cwd.to_path_buf(),
+        env: step.env.clone(),
+        inputs: step.inputs.to_vec(),
+        files,
+    })
+}
+
+/// One stamp per config file, named for its canonical path.
+fn stamp_path(config_path: &Path) -> Result<PathBuf> {
+    let name = config_path.to_string_lossy();
+    let (_, digest) = hash_reader(&mut name.as_bytes(), &name)?;
+    Ok(config::wavedash_dir()?
+        .join("build-stamps")
+        .join(format!("{}.json", &digest[..16])))
+}
+
+/// The stamp at `path`. One that's missing or unreadable just means the
+/// command runs.
+fn read_stamp(path: &Path) -> Option<Stamp> {
+    let contents = std::fs::read(path).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+fn write_stamp(path: &Path, stamp: &Stamp) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(stamp)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::time::{Duration, SystemTime};
+
+    fn touch(path: &Path, seconds: u64) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn inputs_are_matched_by_pattern_and_checked_by_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(cwd.join("scenes")).unwrap();
+        fs::create_dir_all(cwd.join("export/web")).unwrap();
+        fs::write(cwd.join("project.godot"), "config").unwrap();
+        fs::write(cwd.join("scenes/main.tscn"), "scene").unwrap();
+        fs::write(cwd.join("scenes/notes.txt"), "notes").unwrap();
+        fs::write(cwd.join("export/web/main.tscn"), "exported").unwrap();
+
+        let patterns = vec!["*.godot".to_string(), "*.tscn".to_string()];
+        let env = BTreeMap::new();
+        let step = BuildStep {
+            command: "godot --export-release Web",
+            cwd: None,
+            env: &env,
+            inputs: &patterns,
+        };
+        let upload_dir = cwd.join("export/web");
+        let observed = scan_inputs(&cwd, &patterns, &upload_dir).unwrap();
+        assert_eq!(
+            observed.keys().collect::<Vec<_>>(),
+            ["project.godot", "scenes/main.tscn"]
+        );
+        let stamp = stamp_for(&step, &cwd, &observed).unwrap();
+        assert_eq!(
+            up_to_date(&stamp, &step, &cwd, &observed).unwrap(),
+            Some(stamp.clone())
+        );
+
+        // Touched but not changed: still up to date, at the new mtime.
+        touch(&cwd.join("scenes/main.tscn"), 1_000);
+        let observed = scan_inputs(&cwd, &patterns, &upload_dir).unwrap();
+        let fresh = up_to_date(&stamp, &step, &cwd, &observed)
+            .unwrap()
+            .expect("unchanged");
+        assert_eq!(fresh.files["scenes/main.tscn"].modified, 1_000_000_000_000);
+
+        // Same size, different bytes.
+        fs::write(cwd.join("scenes/main.tscn"), "SCENE").unwrap();
+        touch(&cwd.join("scenes/main.tscn"), 2_000);
+        let observed = scan_inputs(&cwd, &patterns, &upload_dir).unwrap();
+        assert_eq!(up_to_date(&fresh, &step, &cwd, &observed).unwrap(), None);
+
+        // A different command is a different build.
+        let other = BuildStep {
+            command: "godot --export-debug Web",
+            ..step
+        };
+        let observed = scan_inputs(&cwd, &patterns, &upload_dir).unwrap();
+        let stamp = stamp_for(&step, &cwd, &observed).unwrap();
+        assert_eq!(up_to_date(&stamp, &other, &cwd, &observed).unwrap(), None);
+    }
+}
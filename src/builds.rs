@@ -1,13 +1,50 @@
+use crate::archive::Archive;
 use crate::auth::AuthManager;
-use crate::config::{self, UploadSource, WavedashConfig};
+use crate::build_step::run_build_step;
+use crate::config::{self, Concurrency, StorageTarget, UploadSource, WavedashConfig};
+use crate::exclude::{ExcludeRules, IGNORE_FILE_NAME};
 use crate::file_staging::FileStaging;
+use crate::git::GitProvenance;
+use crate::manifest::BuildManifest;
+use crate::object_headers::HeaderRules;
+use crate::publish::{print_release, publish_build, ReleaseNoteArgs};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+#[path = "analyze.rs"]
+mod analyze;
+#[path = "build_info.rs"]
+mod build_info;
+#[path = "cleanup.rs"]
+mod cleanup;
+#[path = "diff.rs"]
+mod diff;
+#[path = "download.rs"]
+mod download;
+#[path = "lint.rs"]
+mod lint;
+#[path = "upload_journal.rs"]
+mod upload_journal;
 #[path = "uploader.rs"]
-mod uploader;
-
-use uploader::{scan_directory, R2Config, R2Uploader};
+pub(crate) mod uploader;
+#[path = "verify.rs"]
+mod verify;
+
+pub use analyze::{handle_build_analyze, BuildAnalyzeArgs};
+pub use build_info::{handle_build_list, handle_build_show, DEFAULT_LIST_LIMIT};
+pub use cleanup::{handle_build_delete, handle_build_prune, parse_age, BuildPruneArgs};
+pub use diff::{handle_build_diff, BuildDiffArgs};
+pub use download::{handle_build_download, BuildDownloadArgs};
+pub use lint::{handle_build_lint, BuildLintArgs};
+use upload_journal::{now_unix, JournalHeader, UploadJournal};
+use uploader::{
+    build_object_key, scan_archive, scan_directory, CredentialRefresher, DirectoryScan,
+    ExcludedPath, R2Config, R2Uploader, RenewedCredentials, RetryPolicy, ScannedFile,
+    DEFAULT_CONCURRENCY,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct R2Credentials {
@@ -42,8 +79,13 @@ struct BuildUploadInfo<'a> {
     entrypoint: Option<&'a str>,
     entrypoint_params: Option<serde_json::Value>,
     message: Option<&'a str>,
+    git: Option<&'a GitProvenance>,
     build_size_bytes: u64,
     upload_source: UploadSource,
+    manifest: &'a BuildManifest,
+    /// The build the manifest was diffed against. The server references that
+    /// build's blobs for every file this push doesn't upload.
+    base_build_id: Option<&'a str>,
 }
 
 async fn get_temp_credentials(
@@ -61,8 +103,13 @@ async fn get_temp_credentials(
     let mut request_body = serde_json::json!({
         "buildSizeBytes": info.build_size_bytes,
         "uploadSource": info.upload_source.as_label(),
+        "manifest": info.manifest,
     });
 
+    if let Some(base) = info.base_build_id {
+        request_body["baseBuildId"] = serde_json::json!(base);
+    }
+
     if let Some(eng) = info.engine {
         request_body["engine"] = serde_json::json!(eng);
     }
@@ -85,6 +132,10 @@ async fn get_temp_credentials(
         request_body["buildMessage"] = serde_json::json!(msg);
     }
 
+    if let Some(git) = info.git {
+        request_body["git"] = serde_json::json!(git);
+    }
+
     let response = client
         .post(&url)
         .header("Authorization", format!("Bearer {}", api_key))
@@ -99,15 +150,119 @@ async fn get_temp_credentials(
     Ok(creds)
 }
 
+#[derive(Debug, Deserialize)]
+struct RefreshedCredsResponse {
+    credentials: R2Credentials,
+    endpoint: String,
+    #[serde(rename = "expiresIn")]
+    expires_in: u64,
+}
+
+/// New temporary credentials scoped to an existing build's key prefix, for when
+/// the set `create-temp-r2-creds` handed out has expired or is about to.
+async fn refresh_temp_credentials(
+    game_id: &str,
+    build_id: &str,
+    api_key: &str,
+) -> Result<RefreshedCredsResponse> {
+    let client = config::create_http_client()?;
+    let api_host = config::get("api_host")?;
+
+    let url = format!(
+        "{}/api/games/{}/builds/{}/refresh-temp-r2-creds",
+        api_host, game_id, build_id
+    );
+
+    let response = client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Content-Type", "application/json")
+        .send()
+        .await?;
+
+    let response = config::check_api_response(response).await?;
+
+    let creds: RefreshedCredsResponse = response.json().await?;
+    Ok(creds)
+}
+
+#[derive(Debug, Deserialize)]
+struct LatestManifestResponse {
+    /// `None` when the game has no completed build to diff against yet.
+    #[serde(rename = "gameBuildId")]
+    game_build_id: Option<String>,
+    #[serde(default)]
+    manifest: Option<BuildManifest>,
+    #[serde(flatten)]
+    settings: BuildSettings,
+    #[serde(rename = "buildMessage", default)]
+    build_message: Option<String>,
+}
+
+/// What a build is besides its files, as sent when it's created: two builds
+/// with the same files but different settings run differently.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildSettings {
+    #[serde(default)]
+    engine: Option<String>,
+    #[serde(default)]
+    engine_version: Option<String>,
+    #[serde(default)]
+    entrypoint: Option<String>,
+    #[serde(default)]
+    entrypoint_params: Option<serde_json::Value>,
+}
+
+/// The game's most recent completed build, as a push diffs against it.
+struct LatestBuild {
+    id: String,
+    manifest: BuildManifest,
+    settings: BuildSettings,
+    message: Option<String>,
+}
+
+/// The game's most recent completed build, if it has a manifest. A build
+/// uploaded before manifests existed comes back without one, which is the same
+/// as having nothing to diff against.
+async fn fetch_latest_manifest(game_id: &str, api_key: &str) -> Result<Option<LatestBuild>> {
+    let client = config::create_http_client()?;
+    let api_host = config::get("api_host")?;
+
+    let url = format!("{}/api/games/{}/builds/latest/manifest", api_host, game_id);
+
+    let response = client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await?;
+
+    let response = config::check_api_response(response).await?;
+
+    let latest: LatestManifestResponse = response.json().await?;
+    Ok(latest
+        .game_build_id
+        .zip(latest.manifest)
+        .map(|(id, manifest)| LatestBuild {
+            id,
+            manifest,
+            settings: latest.settings,
+            message: latest.build_message,
+        }))
+}
+
 #[derive(Debug, Deserialize)]
 struct UploadCompleteResponse {
     #[serde(rename = "gameSlug")]
     game_slug: String,
 }
 
+/// `manifest_sha256` is the digest of the `wavedash-manifest.json` uploaded
+/// with the build, so the server can tell the one it lists is the one stored.
 async fn notify_upload_complete(
     game_id: &str,
     build_id: &str,
+    manifest_sha256: &str,
     api_key: &str,
 ) -> Result<UploadCompleteResponse> {
     let client = config::create_http_client()?;
@@ -122,6 +277,7 @@ async fn notify_upload_complete(
         .post(&url)
         .header("Authorization", format!("Bearer {}", api_key))
         .header("Content-Type", "application/json")
+        .json(&serde_json::json!({ "manifestSha256": manifest_sha256 }))
         .send()
         .await?;
 
@@ -131,84 +287,727 @@ async fn notify_upload_complete(
     Ok(result)
 }
 
-pub async fn handle_build_push(
-    config_path: PathBuf,
-    verbose: bool,
-    message: Option<String>,
-    upload_source: UploadSource,
-) -> Result<()> {
+pub struct BuildPushArgs {
+    pub config_path: PathBuf,
+    pub verbose: bool,
+    pub message: Option<String>,
+    pub upload_source: UploadSource,
+    /// Push from this archive instead of `upload_dir`.
+    pub archive: Option<PathBuf>,
+    pub full: bool,
+    pub resume: bool,
+    /// Skip the `[build]` command.
+    pub no_build: bool,
+    /// Retries per file for transient storage errors.
+    pub retries: u32,
+    /// `--concurrency`, ahead of the config's.
+    pub concurrency: Option<Concurrency>,
+    /// `--max-rate` in bytes per second, ahead of the environment's.
+    pub max_rate: Option<u64>,
+    /// `--storage`, ahead of the environment's.
+    pub storage: Option<StorageTarget>,
+    /// Check every uploaded object against its file before completing.
+    pub verify: bool,
+    /// `--publish`, with its release notes.
+    pub publish: Option<ReleaseNoteArgs>,
+    /// Stop after resolving and scanning, and report instead of uploading.
+    pub dry_run: bool,
+    pub json: bool,
+}
+
+pub async fn handle_build_push(args: BuildPushArgs) -> Result<()> {
+    let BuildPushArgs {
+        config_path,
+        verbose,
+        message,
+        upload_source,
+        archive,
+        full,
+        resume,
+        no_build,
+        retries,
+        concurrency,
+        max_rate,
+        storage,
+        verify,
+        publish,
+        dry_run,
+        json,
+    } = args;
     // Load wavedash.toml config
     let wavedash_config = WavedashConfig::load(&config_path)?;
+    let settings = UploadSettings {
+        verbose,
+        retry: RetryPolicy {
+            max_retries: retries,
+            ..RetryPolicy::default()
+        },
+        headers: HeaderRules::new(wavedash_config.header_overrides())?,
+        concurrency: match concurrency {
+            Some(concurrency) => concurrency,
+            None => wavedash_config
+                .upload_concurrency()?
+                .unwrap_or(Concurrency::Fixed(DEFAULT_CONCURRENCY)),
+        },
+        max_rate: match max_rate {
+            Some(rate) => Some(rate),
+            None => config::max_upload_rate_from_env()?,
+        },
+        storage: match storage {
+            Some(storage) => storage,
+            None => config::storage_target_from_env()?.unwrap_or_default(),
+        },
+        verify,
+        publish,
+    };
+
+    // A resume has to send what the interrupted push scanned, and an archive
+    // is already built, so neither runs the export. A dry run only says it
+    // would: the export can take minutes and writes over upload_dir.
+    let builds = !no_build && !resume && archive.is_none();
+    if builds && !dry_run {
+        run_build_step(&wavedash_config, &config_path, json)?;
+    }
+
+    let (config_dir, upload_dir, archive) =
+        resolve_push_source(&config_path, &wavedash_config, archive)?;
+    let packed = match archive {
+        Some(archive) => {
+            let listing = archive.list()?;
+            Some((Arc::new(archive), listing))
+        }
+        None => None,
+    };
+
+    // Validate required files exist in upload directory
+    match &packed {
+        Some((_, listing)) => {
+            let entries = listing.files.iter().map(|f| f.path.clone()).collect();
+            FileStaging::prepare_archive(&upload_dir, &entries, &wavedash_config)?;
+        }
+        None => {
+            FileStaging::prepare(&upload_dir, &wavedash_config)?;
+        }
+    }
+
+    let rules = ExcludeRules::load(&upload_dir, config_dir, wavedash_config.excludes())?;
+    let required = wavedash_config
+        .entrypoint()?
+        .into_iter()
+        .chain(wavedash_config.executable_files_to_validate()?);
+    for file in required {
+        if rules.excludes_path(Path::new(file)) {
+            anyhow::bail!(
+                "'{}' is required by wavedash.toml but excluded from the upload. Remove the pattern that matches it from `exclude` or {}.",
+                file,
+                IGNORE_FILE_NAME
+            );
+        }
+    }
+
+    // Scan directory to get file list and total size before requesting credentials
+    let scan = match packed {
+        Some((archive, listing)) => {
+            scan_archive(archive, &listing, &rules, wavedash_config.symlinks())?
+        }
+        None => scan_directory(&upload_dir, &rules, wavedash_config.symlinks())?,
+    };
+    scan.warn_about_links();
+    let DirectoryScan {
+        files: scanned_files,
+        total_bytes,
+        excluded,
+        ..
+    } = scan;
+    // The JSON report carries the excluded list itself, and stdout has to
+    // stay parseable.
+    if verbose && !json {
+        for skipped in &excluded {
+            println!(
+                "Skipped {} (matched {})",
+                skipped.relative_path.display(),
+                skipped.rule
+            );
+        }
+    }
+    if scanned_files.is_empty() {
+        anyhow::bail!("No files found in {}", upload_dir.display());
+    }
+    verify::refuse_reserved_name(&scanned_files)?;
+    lint::warn_before_push(&scanned_files, wavedash_config.entrypoint()?)?;
+
+    if dry_run {
+        let report = DryRunReport::new(
+            &wavedash_config,
+            builds,
+            &upload_dir,
+            &scanned_files,
+            total_bytes,
+            &excluded,
+            &settings.headers,
+        )?;
+        return report.print(json);
+    }
 
-    // Check authentication
+    // Check authentication. A dry run never gets this far, so it works
+    // without a login.
     let auth_manager = AuthManager::new()?;
     let api_key = auth_manager
         .get_api_key()
         .ok_or_else(|| anyhow::anyhow!("Not authenticated. Run 'wavedash auth login' first."))?;
 
-    // Resolve upload_dir relative to the config file's directory
+    let manifest = BuildManifest::from_scan(&scanned_files)?;
+    let game_id = wavedash_config.game_id()?;
+    let upload_dir = upload_dir.canonicalize()?;
+
+    if resume {
+        let journal = UploadJournal::open(game_id)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "No interrupted push to resume for game {}. Run `wavedash build push` without --resume.",
+                game_id
+            )
+        })?;
+        return resume_build_push(
+            journal,
+            scanned_files,
+            &upload_dir,
+            &manifest,
+            settings,
+            &api_key,
+        )
+        .await;
+    }
+    if let Some(stale) = UploadJournal::open(game_id)? {
+        println!(
+            "Abandoning the interrupted push of build {} (pass --resume to finish it instead).",
+            stale.header.game_build_id
+        );
+    }
+
+    let engine_kind = wavedash_config.engine_type()?;
+    let build_settings = BuildSettings {
+        engine: engine_kind.map(|e| e.as_label().to_string()),
+        engine_version: wavedash_config.engine_version()?.map(str::to_string),
+        entrypoint: wavedash_config.entrypoint()?.map(str::to_string),
+        entrypoint_params: wavedash_config.executable_entrypoint_params()?,
+    };
+
+    // Diff against the previous build so only content it lacks is sent. `--full`
+    // skips the lookup entirely, which also means no short-circuit. Failing to
+    // look costs bandwidth, not correctness, so it only downgrades the push.
+    let base = if full {
+        None
+    } else {
+        match fetch_latest_manifest(game_id, &api_key).await {
+            Ok(base) => base,
+            Err(e) => {
+                eprintln!(
+                    "Warning: couldn't fetch the latest build to diff against, so every file will be uploaded: {:#}",
+                    e
+                );
+                None
+            }
+        }
+    };
+    // Same files aren't enough to skip the push: a new engine version,
+    // entrypoint or -m message is a new build even over the same files.
+    if let Some(LatestBuild {
+        id: base_build_id,
+        manifest: base_manifest,
+        settings: base_settings,
+        message: base_message,
+    }) = &base
+    {
+        let unchanged = manifest.same_content_as(base_manifest)
+            && build_settings == *base_settings
+            && (message.is_none() || message == *base_message);
+        if unchanged {
+            println!(
+                "No changes since build {}. Nothing to upload (pass --full to push anyway).",
+                base_build_id
+            );
+            // That build already is what --publish asked to make live, so it's
+            // the one to publish. Succeeding without publishing would leave a
+            // release script thinking it shipped.
+            if let Some(notes) = settings.publish {
+                let release = publish_build(game_id, base_build_id, notes, &api_key).await?;
+                println!();
+                print_release(base_build_id, &release)?;
+            }
+            return Ok(());
+        }
+    }
+    let to_upload: Vec<_> = match &base {
+        Some(base) => {
+            let missing = manifest.paths_missing_from(&base.manifest);
+            scanned_files
+                .into_iter()
+                .filter(|f| missing.contains(&build_object_key("", &f.relative_path)))
+                .collect()
+        }
+        None => scanned_files,
+    };
+    let upload_bytes = to_upload
+        .iter()
+        .fold(0u64, |total, f| total.saturating_add(f.size));
+
+    // Without -m, the commit's subject is the next best description.
+    let git = GitProvenance::detect(config_dir);
+    let message = message.or_else(|| git.as_ref()?.subject.clone());
+
+    // Get temporary R2 credentials (includes build size)
+    let creds = get_temp_credentials(
+        BuildUploadInfo {
+            game_id,
+            engine: build_settings.engine.as_deref(),
+            engine_version: build_settings.engine_version.as_deref(),
+            entrypoint: build_settings.entrypoint.as_deref(),
+            entrypoint_params: build_settings.entrypoint_params,
+            message: message.as_deref(),
+            git: git.as_ref(),
+            build_size_bytes: total_bytes,
+            upload_source,
+            manifest: &manifest,
+            base_build_id: base.as_ref().map(|base| base.id.as_str()),
+        },
+        &api_key,
+    )
+    .await?;
+
+    // Journal the build before the first byte goes out, so anything from here
+    // on can be finished with --resume.
+    let journal = UploadJournal::create(JournalHeader {
+        game_id: game_id.to_string(),
+        game_build_id: creds.game_build_id,
+        uuid: creds.uuid,
+        r2_key_prefix: creds.r2_key_prefix,
+        bucket_name: creds.bucket_name,
+        endpoint: creds.endpoint,
+        credentials: creds.credentials,
+        expires_at: now_unix().saturating_add(creds.expires_in),
+        upload_dir,
+        manifest,
+        pending: to_upload
+            .iter()
+            .map(|f| build_object_key("", &f.relative_path))
+            .collect(),
+    })?;
+
+    if let Some(base) = &base {
+        println!(
+            "Uploading {} of {} files ({}); the rest are unchanged since build {}.",
+            to_upload.len(),
+            journal.header.manifest.files.len(),
+            uploader::format_bytes(upload_bytes),
+            base.id
+        );
+    }
+
+    upload_and_complete(journal, to_upload, settings, &api_key).await
+}
+
+/// The config file's directory and the `upload_dir` it names, resolved against
+/// it. Fails unless the upload directory exists.
+fn resolve_upload_dir<'a>(
+    config_path: &'a Path,
+    wavedash_config: &WavedashConfig,
+) -> Result<(&'a Path, PathBuf)> {
     let config_dir = config_path
         .parent()
         .ok_or_else(|| anyhow::anyhow!("Config file has no parent directory"))?;
     let upload_dir = config_dir.join(wavedash_config.upload_dir()?);
 
-    // Verify source directory exists
     if !upload_dir.exists() {
         anyhow::bail!("Source directory does not exist: {}", upload_dir.display());
     }
     if !upload_dir.is_dir() {
+        if Archive::is_archive(&upload_dir) {
+            anyhow::bail!(
+                "upload_dir is an archive ({}). Only `build push` reads a build from an archive; point upload_dir at the unpacked directory for this command.",
+                upload_dir.display()
+            );
+        }
         anyhow::bail!("Source must be a directory: {}", upload_dir.display());
     }
+    Ok((config_dir, upload_dir))
+}
 
-    // Validate required files exist in upload directory
-    FileStaging::prepare(&upload_dir, &wavedash_config)?;
-
-    // Scan directory to get file list and total size before requesting credentials
-    let (scanned_files, total_bytes) = scan_directory(&upload_dir)?;
-    if scanned_files.is_empty() {
-        anyhow::bail!("No files found in {}", upload_dir.display());
+/// Like [`resolve_upload_dir`], but a push can also read the build straight
+/// out of a `.zip` or `.tar.gz`: the one passed with `--archive`, or the one
+/// `upload_dir` names. The path returned is the archive's, when there is one.
+fn resolve_push_source<'a>(
+    config_path: &'a Path,
+    wavedash_config: &WavedashConfig,
+    archive: Option<PathBuf>,
+) -> Result<(&'a Path, PathBuf, Option<Archive>)> {
+    let config_dir = config_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Config file has no parent directory"))?;
+    let archive_path = match archive {
+        Some(path) => Some(path),
+        None => {
+            let upload_dir = config_dir.join(wavedash_config.upload_dir()?);
+            (Archive::is_archive(&upload_dir) && !upload_dir.is_dir()).then_some(upload_dir)
+        }
+    };
+    match archive_path {
+        Some(path) => {
+            let archive = Archive::open(&path)?;
+            Ok((config_dir, archive.path().to_path_buf(), Some(archive)))
+        }
+        None => {
+            let (config_dir, upload_dir) = resolve_upload_dir(config_path, wavedash_config)?;
+            Ok((config_dir, upload_dir, None))
+        }
     }
+}
 
-    // Get temporary R2 credentials (includes build size)
-    let engine_kind = wavedash_config.engine_type()?;
-    let creds = get_temp_credentials(
-        BuildUploadInfo {
+/// What `build push --dry-run` found: everything the real push would send to
+/// `create-temp-r2-creds`, plus the objects it would upload. Keys are relative
+/// to the build's prefix, which only the server can assign.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DryRunReport<'a> {
+    game_id: &'a str,
+    engine: Option<&'static str>,
+    engine_version: Option<&'a str>,
+    entrypoint: Option<&'a str>,
+    entrypoint_params: Option<serde_json::Value>,
+    /// The `[build]` command a real push would run first. The objects are
+    /// upload_dir as it is now, from before it runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build_command: Option<&'a str>,
+    upload_dir: PathBuf,
+    file_count: usize,
+    total_bytes: u64,
+    objects: Vec<DryRunObject>,
+    excluded: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DryRunObject {
+    key: String,
+    size: u64,
+    content_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_encoding: Option<String>,
+}
+
+impl<'a> DryRunReport<'a> {
+    fn new(
+        wavedash_config: &'a WavedashConfig,
+        builds: bool,
+        upload_dir: &Path,
+        files: &[ScannedFile],
+        total_bytes: u64,
+        excluded: &[ExcludedPath],
+        headers: &HeaderRules,
+    ) -> Result<Self> {
+        let mut objects: Vec<DryRunObject> = files
+            .iter()
+            .map(|f| {
+                let key = build_object_key("", &f.relative_path);
+                let headers = headers.headers_for(&key);
+                DryRunObject {
+                    key,
+                    size: f.size,
+                    content_type: headers.content_type,
+                    content_encoding: headers.content_encoding,
+                }
+            })
+            .collect();
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(Self {
             game_id: wavedash_config.game_id()?,
-            engine: engine_kind.map(|e| e.as_label()),
+            engine: wavedash_config.engine_type()?.map(|e| e.as_label()),
             engine_version: wavedash_config.engine_version()?,
             entrypoint: wavedash_config.entrypoint()?,
             entrypoint_params: wavedash_config.executable_entrypoint_params()?,
-            message: message.as_deref(),
-            build_size_bytes: total_bytes,
-            upload_source,
-        },
-        &api_key,
-    )
-    .await?;
+            build_command: match builds {
+                true => wavedash_config.build_step()?.map(|step| step.command),
+                false => None,
+            },
+            upload_dir: upload_dir.canonicalize()?,
+            file_count: files.len(),
+            total_bytes,
+            objects,
+            excluded: excluded
+                .iter()
+                .map(|e| build_object_key("", &e.relative_path))
+                .collect(),
+        })
+    }
 
-    // Create R2 config for uploader
-    let r2_config = R2Config {
-        access_key_id: creds.credentials.access_key_id,
-        secret_access_key: creds.credentials.secret_access_key,
-        session_token: creds.credentials.session_token,
-        endpoint: creds.endpoint,
+    fn print(&self, json: bool) -> Result<()> {
+        if json {
+            println!("{}", serde_json::to_string_pretty(self)?);
+            return Ok(());
+        }
+
+        println!("Dry run: nothing will be uploaded and no build will be created.\n");
+        println!("Game ID:     {}", self.game_id);
+        match (self.engine, self.engine_version) {
+            (Some(engine), Some(version)) => println!("Engine:      {} {}", engine, version),
+            (Some(engine), None) => println!("Engine:      {}", engine),
+            (None, _) => println!("Engine:      none"),
+        }
+        if let Some(entrypoint) = self.entrypoint {
+            println!("Entrypoint:  {}", entrypoint);
+        }
+        if let Some(params) = &self.entrypoint_params {
+            println!("Entrypoint params: {}", params);
+        }
+        if let Some(command) = self.build_command {
+            println!(
+                "Build:       {} (not run; a push runs it first, which can change the files below)",
+                command
+            );
+        }
+        println!("Upload dir:  {}", self.upload_dir.display());
+        println!(
+            "Files:       {} ({})",
+            self.file_count,
+            uploader::format_bytes(self.total_bytes)
+        );
+        if !self.excluded.is_empty() {
+            println!(
+                "Excluded:    {} (run with --verbose to see which rule matched each)",
+                self.excluded.len()
+            );
+        }
+
+        println!("\nObject keys, relative to the build's prefix:");
+        for object in &self.objects {
+            println!(
+                "  {}  ({})",
+                object.key,
+                uploader::format_bytes(object.size)
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Finish the build an earlier push journaled. The directory has to hold what
+/// it held then — the build row already has that manifest, and topping it up
+/// from a different tree would publish a mix of the two.
+async fn resume_build_push(
+    journal: UploadJournal,
+    scanned_files: Vec<ScannedFile>,
+    upload_dir: &Path,
+    manifest: &BuildManifest,
+    settings: UploadSettings,
+    api_key: &str,
+) -> Result<()> {
+    let header = &journal.header;
+    if header.upload_dir != upload_dir {
+        anyhow::bail!(
+            "The interrupted push of build {} was uploading {}, not {}. Resume from the same upload_dir, or push without --resume to start a new build.",
+            header.game_build_id,
+            header.upload_dir.display(),
+            upload_dir.display()
+        );
+    }
+    if !header.manifest.same_content_as(manifest) {
+        anyhow::bail!(
+            "Files in {} changed since build {} started uploading. Push without --resume to start a new build.",
+            upload_dir.display(),
+            header.game_build_id
+        );
+    }
+
+    let pending: HashSet<&str> = header.pending.iter().map(String::as_str).collect();
+    let owned: Vec<ScannedFile> = scanned_files
+        .into_iter()
+        .filter(|f| pending.contains(build_object_key("", &f.relative_path).as_str()))
+        .collect();
+    let remaining = owned
+        .iter()
+        .filter(|f| {
+            !journal.is_completed(&build_object_key(&header.r2_key_prefix, &f.relative_path))
+        })
+        .count();
+
+    println!(
+        "Resuming build {}: {} of {} files left to upload.",
+        header.game_build_id,
+        remaining,
+        header.pending.len()
+    );
+    upload_and_complete(journal, owned, settings, api_key).await
+}
+
+fn r2_config(credentials: &R2Credentials, endpoint: &str) -> R2Config {
+    R2Config {
+        access_key_id: credentials.access_key_id.clone(),
+        secret_access_key: credentials.secret_access_key.clone(),
+        session_token: credentials.session_token.clone(),
+        endpoint: endpoint.to_string(),
+    }
+}
+
+/// Renews a build's upload credentials through `refresh-temp-r2-creds`, for the
+/// uploader to call as the current set nears expiry.
+fn credential_refresher(game_id: &str, build_id: &str, api_key: &str) -> CredentialRefresher {
+    let (game_id, build_id, api_key) = (
+        game_id.to_string(),
+        build_id.to_string(),
+        api_key.to_string(),
+    );
+    Box::new(move || {
+        let (game_id, build_id, api_key) = (game_id.clone(), build_id.clone(), api_key.clone());
+        Box::pin(async move {
+            let fresh = refresh_temp_credentials(&game_id, &build_id, &api_key).await?;
+            Ok(RenewedCredentials {
+                config: r2_config(&fresh.credentials, &fresh.endpoint),
+                expires_in: Duration::from_secs(fresh.expires_in),
+            })
+        })
+    })
+}
+
+/// How the files of a push go up, and what happens once they're up, whether
+/// it's a fresh push or a resume.
+struct UploadSettings {
+    verbose: bool,
+    retry: RetryPolicy,
+    headers: HeaderRules,
+    concurrency: Concurrency,
+    /// Bytes per second; `None` for no cap.
+    max_rate: Option<u64>,
+    storage: StorageTarget,
+    /// `--verify`.
+    verify: bool,
+    /// Release notes to publish the build with once it's complete; `None`
+    /// leaves it unpublished.
+    publish: Option<ReleaseNoteArgs>,
+}
+
+/// Upload whichever of `files` the journal doesn't have yet into the journaled
+/// build, along with its manifest, then tell the server it's complete.
+/// The journal is only removed once that call succeeds: until then the build
+/// is still resumable, even if every object is already in the bucket.
+///
+/// The uploader starts on the journaled credentials and renews them as they
+/// near expiry — which on a `--resume` long after the fact is immediately.
+async fn upload_and_complete(
+    journal: UploadJournal,
+    files: Vec<ScannedFile>,
+    settings: UploadSettings,
+    api_key: &str,
+) -> Result<()> {
+    let header = &journal.header;
+    let UploadSettings {
+        verbose,
+        retry,
+        headers,
+        concurrency,
+        max_rate,
+        storage,
+        verify,
+        publish,
+    } = settings;
+    let interrupted = |e: anyhow::Error| {
+        e.context(format!(
+            "Upload interrupted. Run `wavedash build push --resume` to finish build {}",
+            header.game_build_id
+        ))
     };
 
-    // Initialize uploader and upload using pre-scanned files
-    let uploader = R2Uploader::new(&r2_config, &creds.bucket_name)?;
+    let uploader = R2Uploader::new(
+        &r2_config(&header.credentials, &header.endpoint),
+        &header.bucket_name,
+        storage,
+    )?
+    .with_retry_policy(retry)
+    .with_concurrency(concurrency)
+    .with_max_rate(max_rate)
+    .with_object_headers(headers)
+    .with_credential_refresh(
+        Duration::from_secs(header.expires_at.saturating_sub(now_unix())),
+        credential_refresher(&header.game_id, &header.game_build_id, api_key),
+    );
+
+    // Initialize uploader and upload using pre-scanned files. A push that only
+    // removed or renamed files has nothing new to send.
+    let to_send: Vec<ScannedFile> = files
+        .iter()
+        .filter(|f| {
+            !journal.is_completed(&build_object_key(&header.r2_key_prefix, &f.relative_path))
+        })
+        .cloned()
+        .collect();
+    if !to_send.is_empty() {
+        let upload_bytes = to_send
+            .iter()
+            .fold(0u64, |total, f| total.saturating_add(f.size));
+        let record = |key: &str| journal.record(key);
+        uploader
+            .upload_directory_from_scan(
+                &to_send,
+                upload_bytes,
+                &header.r2_key_prefix,
+                verbose,
+                &record,
+            )
+            .await
+            .map_err(interrupted)?;
+    }
+
+    let (document, manifest_sha256) = verify::manifest_document(&header.manifest)?;
+    let manifest_key = build_object_key(&header.r2_key_prefix, Path::new(verify::MANIFEST_OBJECT));
     uploader
-        .upload_directory_from_scan(&scanned_files, total_bytes, &creds.r2_key_prefix, verbose)
-        .await?;
+        .put_object(&manifest_key, document, "application/json")
+        .await
+        .map_err(interrupted)?;
+
+    if verify {
+        verify::verify_uploads(&uploader, &files, &header.r2_key_prefix)
+            .await
+            .map_err(|e| {
+                e.context(format!(
+                    "Build {} failed verification and was not marked complete. Push again to upload a fresh build.",
+                    header.game_build_id
+                ))
+            })?;
+    }
 
     // Notify the server that upload is complete
-    let result =
-        notify_upload_complete(wavedash_config.game_id()?, &creds.game_build_id, &api_key).await?;
+    let result = notify_upload_complete(
+        &header.game_id,
+        &header.game_build_id,
+        &manifest_sha256,
+        api_key,
+    )
+    .await?;
 
     // Print the play URL
     let site_host = config::get("open_browser_website_host")?;
-    let play_url = format!("{}/playtest/{}/{}", site_host, result.game_slug, creds.uuid);
-    println!("\nBuild ID: {}", creds.game_build_id);
+    let play_url = build_info::playtest_url(&site_host, &result.game_slug, &header.uuid);
+    println!("\nBuild ID: {}", header.game_build_id);
     println!("▶ Play at: {}", play_url);
 
+    let (game_id, build_id) = (header.game_id.clone(), header.game_build_id.clone());
+    if let Err(e) = journal.remove() {
+        eprintln!("Warning: {:#}", e);
+    }
+
+    // The build is complete whatever happens next, so a failed publish only
+    // needs the publish retried, not the push.
+    if let Some(notes) = publish {
+        let release = publish_build(&game_id, &build_id, notes, api_key)
+            .await
+            .map_err(|e| {
+                e.context(format!(
+                    "Build {} was uploaded but not published. Run `wavedash publish {}` to publish it",
+                    build_id, build_id
+                ))
+            })?;
+        println!();
+        print_release(&build_id, &release)?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,399 @@
+//! `wavedash build delete` and `build prune`: removing build rows, and the
+//! objects behind them, that nobody is going to play again.
+//!
+//! The build players currently get is never removed, by either command. Nor
+//! is a build another build was pushed against: a push only uploads what
+//! changed, and the server serves the rest from the base build's files, so
+//! deleting a base would leave holes in every build after it. Dev builds
+//! (`wavedash dev` creates one per run) and uploaded builds are pruned
+//! separately, so clearing out a week of dev sessions can't eat into the
+//! uploaded builds kept for rollback, and vice versa.
+
+use anyhow::Result;
+use colored::Colorize;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::build_info::{fetch_build, fetch_builds, BuildRecord, BuildShowResponse};
+use crate::auth::require_api_key;
+use crate::config;
+
+pub struct BuildPruneArgs<'a> {
+    pub game_id: &'a str,
+    /// Keep this many of the newest builds no matter how old they are.
+    pub keep: Option<usize>,
+    /// Only builds created longer ago than this are candidates.
+    pub older_than: Option<Duration>,
+    /// Prune `wavedash dev` builds instead of uploaded ones.
+    pub dev: bool,
+    pub dry_run: bool,
+    pub yes: bool,
+}
+
+/// `30d`, `12h`, `2w`, `90m`, `45s`: a whole number and one unit.
+pub fn parse_age(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("'{}' needs a unit: s, m, h, d or w (e.g. 30d)", value))?;
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not an age (expected e.g. 30d)", value))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "unknown unit '{}' in '{}': use s, m, h, d or w",
+                unit, value
+            ))
+        }
+    };
+    Ok(Duration::from_secs(number.saturating_mul(seconds)))
+}
+
+/// What `prune` would do with the builds its retention rules select.
+struct Pruning<'a> {
+    /// Oldest first.
+    doomed: Vec<&'a BuildRecord>,
+    /// Selected, but kept as the base of a build that isn't being deleted.
+    held: Vec<&'a BuildRecord>,
+}
+
+/// The builds `prune` would delete. `builds` is every build of the game in any
+/// order; `now_ms` is the current time in the same epoch milliseconds as
+/// [`BuildRecord::created_at`]. With neither `keep` nor `older_than` there's
+/// no retention rule to apply, and nothing is selected rather than everything.
+fn select_for_pruning(
+    builds: &[BuildRecord],
+    keep: Option<usize>,
+    older_than: Option<Duration>,
+    dev: bool,
+    now_ms: f64,
+) -> Pruning<'_> {
+    if keep.is_none() && older_than.is_none() {
+        return Pruning {
+            doomed: Vec::new(),
+            held: Vec::new(),
+        };
+    }
+    let mut candidates: Vec<&BuildRecord> = builds
+        .iter()
+        .filter(|b| b.local == dev && !b.live)
+        .collect();
+    // Newest first, so `keep` skips the front of the list.
+    candidates.sort_by(|a, b| b.created_at.total_cmp(&a.created_at));
+
+    let cutoff_ms = older_than.map(|age| now_ms - age.as_millis() as f64);
+    let mut selected: Vec<&BuildRecord> = candidates
+        .into_iter()
+        .skip(keep.unwrap_or(0))
+        .filter(|b| cutoff_ms.is_none_or(|cutoff| b.created_at < cutoff))
+        .collect();
+    selected.reverse();
+
+    let selected_ids: HashSet<&str> = selected.iter().map(|b| b.id.as_str()).collect();
+    let survivors = builds
+        .iter()
+        .filter(|b| !selected_ids.contains(b.id.as_str()));
+    let needed = bases_of(builds, survivors);
+    let (held, doomed) = selected
+        .into_iter()
+        .partition(|b| needed.contains(b.id.as_str()));
+    Pruning { doomed, held }
+}
+
+/// Every build one of `dependents` was pushed against, directly or through
+/// its base's own base.
+fn bases_of<'a>(
+    builds: &'a [BuildRecord],
+    dependents: impl Iterator<Item = &'a BuildRecord>,
+) -> HashSet<&'a str> {
+    let mut bases = HashSet::new();
+    for build in dependents {
+        let mut base = build.base_build_id.as_deref();
+        // `insert` failing means the rest of the chain is already in.
+        while let Some(id) = base.filter(|id| bases.insert(*id)) {
+            base = builds
+                .iter()
+                .find(|b| b.id == id)
+                .and_then(|b| b.base_build_id.as_deref());
+        }
+    }
+    bases
+}
+
+async fn delete_build(game_id: &str, build_id: &str, api_key: &str) -> Result<()> {
+    let client = config::create_http_client()?;
+    let api_host = config::get("api_host")?;
+    let url = format!("{}/api/games/{}/builds/{}", api_host, game_id, build_id);
+
+    let response = client
+        .delete(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await?;
+    config::check_api_response(response).await?;
+    Ok(())
+}
+
+pub async fn handle_build_delete(game_id: &str, build_id: &str, yes: bool) -> Result<()> {
+    let api_key = require_api_key()?;
+    let BuildShowResponse { build, .. } = fetch_build(game_id, build_id, &api_key).await?;
+    if build.live {
+        anyhow::bail!(
+            "Build {} is the live build for game {}. Publish another build before deleting it.",
+            build_id,
+            game_id
+        );
+    }
+    let builds = fetch_builds(game_id, None, &api_key).await?.builds;
+    let dependents: Vec<&str> = builds
+        .iter()
+        .filter(|b| b.base_build_id.as_deref() == Some(build_id))
+        .map(|b| b.id.as_str())
+        .collect();
+    if !dependents.is_empty() {
+        anyhow::bail!(
+            "Build {} stores the unchanged files of later builds pushed against it ({}). Delete those first.",
+            build_id,
+            dependents.join(", ")
+        );
+    }
+
+    if !yes {
+        if crate::is_non_interactive() {
+            anyhow::bail!(
+                "Refusing to delete a build without confirmation.\n\
+                 Re-run with --yes (alias --force / -y) to proceed non-interactively."
+            );
+        }
+
+        println!(
+            "{} This will permanently delete build {} ({}, created {}) and its files from game {}.",
+            "Warning:".yellow().bold(),
+            build_id.bold(),
+            build.message.as_deref().unwrap_or("no message"),
+            build.created_label(),
+            game_id.bold()
+        );
+        let confirmed = cliclack::confirm("Are you sure you want to continue?")
+            .initial_value(false)
+            .interact()?;
+        if !confirmed {
+            println!("Aborted. Nothing was deleted.");
+            return Ok(());
+        }
+    }
+
+    delete_build(game_id, build_id, &api_key).await?;
+    println!("✓ Deleted build {}", build_id);
+    Ok(())
+}
+
+pub async fn handle_build_prune(args: BuildPruneArgs<'_>) -> Result<()> {
+    let BuildPruneArgs {
+        game_id,
+        keep,
+        older_than,
+        dev,
+        dry_run,
+        yes,
+    } = args;
+    let kind = if dev { "dev builds" } else { "uploaded builds" };
+
+    let api_key = require_api_key()?;
+    let builds = fetch_builds(game_id, None, &api_key).await?.builds;
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as f64)
+        .unwrap_or(0.0);
+    let Pruning { doomed, held } = select_for_pruning(&builds, keep, older_than, dev, now_ms);
+
+    if !held.is_empty() {
+        println!(
+            "Keeping {} {} that later builds were pushed against and still need files from:",
+            held.len(),
+            kind
+        );
+        for build in &held {
+            println!("  {}  {}", build.id, build.created_label());
+        }
+    }
+    if doomed.is_empty() {
+        println!("No {} to prune.", kind);
+        return Ok(());
+    }
+
+    println!("{} {} would be deleted:", doomed.len(), kind);
+    for build in &doomed {
+        println!(
+            "  {}  {}  {}",
+            build.id,
+            build.created_label(),
+            build.message.as_deref().unwrap_or("-")
+        );
+    }
+    if dry_run {
+        println!("Dry run: nothing was deleted.");
+        return Ok(());
+    }
+
+    if !yes {
+        if crate::is_non_interactive() {
+            anyhow::bail!(
+                "Refusing to prune builds without confirmation.\n\
+                 Re-run with --yes (alias --force / -y) to proceed non-interactively."
+            );
+        }
+
+        println!(
+            "{} This will permanently delete the {} {} above from game {}.",
+            "Warning:".yellow().bold(),
+            doomed.len(),
+            kind,
+            game_id.bold()
+        );
+        let confirmed = cliclack::confirm("Are you sure you want to continue?")
+            .initial_value(false)
+            .interact()?;
+        if !confirmed {
+            println!("Aborted. Nothing was deleted.");
+            return Ok(());
+        }
+    }
+
+    for (deleted, build) in doomed.iter().enumerate() {
+        delete_build(game_id, &build.id, &api_key)
+            .await
+            .map_err(|e| {
+                e.context(format!(
+                    "Failed to delete build {} ({} of {} already deleted)",
+                    build.id,
+                    deleted,
+                    doomed.len()
+                ))
+            })?;
+    }
+    println!("✓ Pruned {} {}", doomed.len(), kind);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+    fn build(id: &str, age_days: f64, live: bool, local: bool) -> BuildRecord {
+        pushed_against(id, age_days, live, local, None)
+    }
+
+    fn pushed_against(
+        id: &str,
+        age_days: f64,
+        live: bool,
+        local: bool,
+        base: Option<&str>,
+    ) -> BuildRecord {
+        serde_json::from_value(serde_json::json!({
+            "_id": id,
+            "uuid": format!("uuid-{}", id),
+            "_creationTime": 100.0 * DAY_MS - age_days * DAY_MS,
+            "live": live,
+            "local": local,
+            "baseBuildId": base,
+        }))
+        .expect("build")
+    }
+
+    fn ids(builds: Vec<&BuildRecord>) -> Vec<&str> {
+        builds.into_iter().map(|b| b.id.as_str()).collect()
+    }
+
+    #[test]
+    fn ages_take_one_unit() {
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 86_400)));
+        assert_eq!(parse_age("2w"), Ok(Duration::from_secs(14 * 86_400)));
+        assert!(parse_age("30").is_err());
+        assert!(parse_age("3 days").is_err());
+    }
+
+    #[test]
+    fn the_live_build_survives_any_retention() {
+        let builds = vec![
+            build("old-live", 90.0, true, false),
+            build("old", 80.0, false, false),
+            build("new", 1.0, false, false),
+        ];
+        let pruning = select_for_pruning(&builds, Some(0), None, false, 100.0 * DAY_MS);
+        assert_eq!(ids(pruning.doomed), vec!["old", "new"]);
+    }
+
+    #[test]
+    fn keep_and_older_than_must_both_allow_a_deletion() {
+        let builds = vec![
+            build("a", 60.0, false, false),
+            build("b", 40.0, false, false),
+            build("c", 20.0, false, false),
+            build("d", 10.0, false, false),
+        ];
+        let thirty_days = Some(Duration::from_secs(30 * 86_400));
+
+        // Keep the newest three: only `a` is past them, and it's old enough.
+        let pruning = select_for_pruning(&builds, Some(3), thirty_days, false, 100.0 * DAY_MS);
+        assert_eq!(ids(pruning.doomed), vec!["a"]);
+        // No count to keep: everything past 30 days goes, oldest first.
+        let pruning = select_for_pruning(&builds, None, thirty_days, false, 100.0 * DAY_MS);
+        assert_eq!(ids(pruning.doomed), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn no_retention_rule_selects_nothing() {
+        let builds = vec![
+            build("old", 80.0, false, false),
+            build("new", 1.0, false, false),
+        ];
+        let pruning = select_for_pruning(&builds, None, None, false, 100.0 * DAY_MS);
+        assert!(pruning.doomed.is_empty());
+        assert!(pruning.held.is_empty());
+    }
+
+    #[test]
+    fn dev_builds_are_pruned_on_their_own() {
+        let builds = vec![
+            build("upload", 50.0, false, false),
+            build("dev-old", 50.0, false, true),
+            build("dev-new", 1.0, false, true),
+        ];
+        let pruning = select_for_pruning(&builds, Some(1), None, true, 100.0 * DAY_MS);
+        assert_eq!(ids(pruning.doomed), vec!["dev-old"]);
+        let pruning = select_for_pruning(&builds, Some(0), None, false, 100.0 * DAY_MS);
+        assert_eq!(ids(pruning.doomed), vec!["upload"]);
+    }
+
+    #[test]
+    fn bases_of_surviving_builds_are_held_back() {
+        let builds = vec![
+            build("a", 60.0, false, false),
+            pushed_against("b", 50.0, false, false, Some("a")),
+            build("d", 40.0, false, false),
+            pushed_against("c", 30.0, false, false, Some("b")),
+            pushed_against("e", 1.0, true, false, Some("d")),
+        ];
+        // `c` is kept by `keep`, and needs `b`, which needs `a`; the live `e`
+        // needs `d`.
+        let pruning = select_for_pruning(&builds, Some(1), None, false, 100.0 * DAY_MS);
+        assert!(pruning.doomed.is_empty());
+        assert_eq!(ids(pruning.held), vec!["a", "b", "d"]);
+
+        // Deleted together, a chain doesn't hold itself back.
+        let pruning = select_for_pruning(&builds, Some(0), None, false, 100.0 * DAY_MS);
+        assert_eq!(ids(pruning.doomed), vec!["a", "b", "c"]);
+        assert_eq!(ids(pruning.held), vec!["d"]);
+    }
+}
@@ -33,8 +33,10 @@ use clap::ValueEnum;
 use colored::Colorize;
 use directories::BaseDirs;
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
 
 /// Get the wavedash config directory (varies by environment)
 /// - Production: ~/.wavedash
@@ -75,7 +77,8 @@ impl Config {
 
         Ok(Config {
             open_browser_website_host: site_host,
-            api_host: env!("CONVEX_HTTP_URL").to_string(),
+            api_host: api_host_override()
+                .unwrap_or_else(|| env!("CONVEX_HTTP_URL").to_string()),
             playsite_host,
             cf_access_client_id: option_env!("CF_ACCESS_CLIENT_ID").map(|s| s.to_string()),
             cf_access_client_secret: option_env!("CF_ACCESS_CLIENT_SECRET").map(|s| s.to_string()),
@@ -83,6 +86,17 @@ impl Config {
     }
 }
 
+#[cfg(debug_assertions)]
+fn api_host_override() -> Option<String> {
+    raw_env(ENV_API_HOST).and_then(non_blank)
+}
+
+/// A release binary only ever talks to the API it was built for.
+#[cfg(not(debug_assertions))]
+fn api_host_override() -> Option<String> {
+    None
+}
+
 pub fn get(key: &str) -> Result<String> {
     let config = Config::load()?;
     match key {
@@ -187,6 +201,7 @@ pub const ENV_UPLOAD_DIR: &str = "WAVEDASH_UPLOAD_DIR";
 pub const ENV_ENTRYPOINT: &str = "WAVEDASH_ENTRYPOINT";
 pub const ENV_GODOT_VERSION: &str = "WAVEDASH_GODOT_VERSION";
 pub const ENV_UNITY_VERSION: &str = "WAVEDASH_UNITY_VERSION";
+pub const ENV_UPLOAD_CONCURRENCY: &str = "WAVEDASH_UPLOAD_CONCURRENCY";
 
 /// The API key, read by [`crate::auth`]. Not a config field and so not in
 /// [`EnvOverrides`] — it can't stand in for anything wavedash.toml supplies —
@@ -194,6 +209,29 @@ pub const ENV_UNITY_VERSION: &str = "WAVEDASH_UNITY_VERSION";
 /// next to the others rather than as a literal at its point of use.
 pub const ENV_TOKEN: &str = "WAVEDASH_TOKEN";
 
+/// The bandwidth cap for uploads, as `--max-rate` takes it. Likewise not a
+/// config field: how much of the uplink a push may take depends on where it
+/// runs, not on the game.
+pub const ENV_MAX_UPLOAD_RATE: &str = "WAVEDASH_MAX_UPLOAD_RATE";
+
+/// Where API requests go, in place of the host this binary was built for.
+/// Only for standing in a local API while testing a push end to end, and only
+/// read by debug builds: the API key goes wherever it points, so a release
+/// binary must not take it.
+#[cfg(debug_assertions)]
+pub const ENV_API_HOST: &str = "WAVEDASH_API_HOST";
+
+/// Where `build push` puts a build's objects instead of R2, as the hidden
+/// `--storage` takes it. See [`StorageTarget`]. Only read by debug builds,
+/// like [`ENV_API_HOST`]: a release binary would still create and complete the
+/// build with the real API, which would then have no objects behind it.
+#[cfg(debug_assertions)]
+pub const ENV_STORAGE: &str = "WAVEDASH_STORAGE";
+
+/// Which `[profiles.<name>]` to overlay on the file, as the global `--profile`
+/// takes it; the flag wins when both are given. See [`ProfileSection`].
+pub const ENV_PROFILE: &str = "WAVEDASH_PROFILE";
+
 /// What `entrypoint()` falls back to when nothing named one and no engine
 /// claimed the build. Only ever a guess, which is why a failure to find it
 /// reports differently from a missing file the user actually named.
@@ -213,6 +251,23 @@ fn raw_env(name: &str) -> Option<String> {
     std::env::var(name).ok()
 }
 
+/// `--profile`, handed over by `main` before any command runs. Process-wide
+/// because the flag is global: every command that loads wavedash.toml has to
+/// honor it, and threading it through each of them would add a parameter most
+/// only pass along.
+static PROFILE_FLAG: OnceLock<String> = OnceLock::new();
+
+/// Select `--profile` for every config loaded from here on. Called once, from
+/// `main`.
+pub fn select_profile(name: String) {
+    let _ = PROFILE_FLAG.set(name);
+}
+
+/// The overrides the process was started with, `--profile` included.
+fn process_overrides() -> EnvOverrides {
+    EnvOverrides::capture(raw_env).with_profile_flag(PROFILE_FLAG.get().map(String::as_str))
+}
+
 /// The `WAVEDASH_*` values, read once when a config is built.
 ///
 /// Snapshotting the environment rather than reading it inside each accessor is
@@ -229,6 +284,28 @@ struct EnvOverrides {
     entrypoint: Option<String>,
     godot_version: Option<String>,
     unity_version: Option<String>,
+    /// Kept as written and parsed on read, so a bad value is only an error
+    /// for a command that uploads.
+    upload_concurrency: Option<String>,
+    profile: Option<ProfileChoice>,
+}
+
+/// The profile to overlay, and whether `--profile` or [`ENV_PROFILE`] chose
+/// it — which is what an error about the name has to point at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProfileChoice {
+    name: String,
+    from_flag: bool,
+}
+
+impl ProfileChoice {
+    fn describe(&self) -> String {
+        if self.from_flag {
+            format!("--profile {}", self.name)
+        } else {
+            format!("{}={}", ENV_PROFILE, self.name)
+        }
+    }
 }
 
 impl EnvOverrides {
@@ -242,12 +319,32 @@ impl EnvOverrides {
             entrypoint: value(ENV_ENTRYPOINT),
             godot_version: value(ENV_GODOT_VERSION),
             unity_version: value(ENV_UNITY_VERSION),
+            upload_concurrency: value(ENV_UPLOAD_CONCURRENCY),
+            profile: value(ENV_PROFILE).map(|name| ProfileChoice {
+                name,
+                from_flag: false,
+            }),
         }
     }
 
+    /// Let a typed `--profile` replace [`ENV_PROFILE`], the way a flag beats
+    /// its variable everywhere else.
+    fn with_profile_flag(mut self, flag: Option<&str>) -> Self {
+        if let Some(name) = flag {
+            self.profile = Some(ProfileChoice {
+                name: name.to_string(),
+                from_flag: true,
+            });
+        }
+        self
+    }
+
     /// True when at least one override is set. A missing config file is only
     /// worth carrying on past if the environment might supply what the command
     /// needs; *which* field it supplies is the accessors' problem, not this one's.
+    /// `upload_concurrency` doesn't count: it's a tuning knob CI tends to set
+    /// for every job, and it can't stand in for anything a command needs.
+    /// Neither does `profile`: it picks from the file, so it can't replace one.
     fn any(&self) -> bool {
         self.game_id.is_some()
             || self.upload_dir.is_some()
@@ -257,16 +354,19 @@ impl EnvOverrides {
     }
 }
 
-/// A config value an override can change, and the key the once-only announcement
-/// guard is kept under. An override is announced the first time a command reads
-/// the field it applies to, so which overrides get mentioned follows from what
-/// the command actually uses — no per-command list to keep in sync.
+/// A config value an override or profile can change, and the key the once-only
+/// announcement guard is kept under. An override is announced the first time a
+/// command reads the field it applies to, so which overrides get mentioned
+/// follows from what the command actually uses — no per-command list to keep in
+/// sync.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Field {
     GameId,
     UploadDir,
     Entrypoint,
     Engine,
+    Concurrency,
+    Exclude,
 }
 
 impl Field {
@@ -276,6 +376,8 @@ impl Field {
             Field::UploadDir => 1 << 1,
             Field::Entrypoint => 1 << 2,
             Field::Engine => 1 << 3,
+            Field::Concurrency => 1 << 4,
+            Field::Exclude => 1 << 5,
         }
     }
 }
@@ -286,6 +388,16 @@ fn print_override_notice(text: &str) {
     eprintln!("{} {}", "env override:".yellow(), text);
 }
 
+/// The same for a value the selected profile supplied, so a run says where
+/// each value that isn't the file's own came from.
+fn print_profile_notice(text: &str) {
+    eprintln!("{} {}", "profile override:".yellow(), text);
+}
+
+fn profile_notice(profile: &str, setting: &str) -> String {
+    format!("[profiles.{}] → {}", profile, setting)
+}
+
 fn game_id_notice(value: &str) -> String {
     format!("{} → game_id = {}", ENV_GAME_ID, value)
 }
@@ -298,6 +410,10 @@ fn entrypoint_notice(entrypoint: &str) -> String {
     format!("{} → entrypoint = {}", ENV_ENTRYPOINT, entrypoint)
 }
 
+fn concurrency_notice(concurrency: Concurrency) -> String {
+    format!("{} → concurrency = {}", ENV_UPLOAD_CONCURRENCY, concurrency)
+}
+
 /// One line describing an engine version override. `added` marks the case where
 /// the config declared no engine, which is worth calling out separately: it
 /// decides the engine for the build rather than just its version.
@@ -360,6 +476,542 @@ impl ExecutableEngineSection {
     }
 }
 
+/// One `[[headers]]` entry: metadata for the uploaded objects whose path
+/// matches `match`, a pattern in the same syntax as `exclude`. Fields left out
+/// keep whatever the file extension implies; later entries win over earlier ones.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeaderOverride {
+    #[serde(rename = "match")]
+    pub pattern: String,
+    pub content_type: Option<String>,
+    pub cache_control: Option<String>,
+    pub content_encoding: Option<String>,
+}
+
+impl HeaderOverride {
+    fn treat_blank_values_as_unset(&mut self) {
+        self.content_type = self.content_type.take().and_then(non_blank);
+        self.cache_control = self.cache_control.take().and_then(non_blank);
+        self.content_encoding = self.content_encoding.take().and_then(non_blank);
+    }
+}
+
+/// `[build]`: the command that produces `upload_dir`, run by `build push` and
+/// `dev` first so a stale export can't be shipped by forgetting to re-export.
+/// Read through [`WavedashConfig::build_step`], which checks it has a command.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct BuildSection {
+    command: Option<String>,
+    cwd: Option<PathBuf>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    inputs: Vec<String>,
+}
+
+/// A `[build]` section with its command.
+#[derive(Debug, Clone, Copy)]
+pub struct BuildStep<'a> {
+    /// Run through the platform shell, so it can be a pipeline or a script.
+    pub command: &'a str,
+    /// Where the command runs, relative to the config file's directory, which
+    /// is also the default.
+    pub cwd: Option<&'a Path>,
+    /// Added to the command's environment, over anything inherited.
+    pub env: &'a BTreeMap<String, String>,
+    /// Patterns, relative to `cwd` and in the same syntax as `exclude`, for
+    /// the files the export is made from. With none the command always runs;
+    /// with some it's skipped while none of them have changed since it last
+    /// succeeded.
+    pub inputs: &'a [String],
+}
+
+/// `[budgets]`: size limits `wavedash build analyze` fails on. Each is a byte
+/// count or a string like `"200 MB"`, in the same 1024-based units the CLI
+/// prints sizes in.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BudgetsSection {
+    #[serde(default, deserialize_with = "deserialize_byte_size")]
+    pub max_total: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_byte_size")]
+    pub max_file: Option<u64>,
+}
+
+/// `symlinks = "..."`: what a push does with a symlink under `upload_dir`.
+/// Whatever the policy, a link whose target is outside `upload_dir` is called
+/// out, since that's usually a shared folder on one machine and nothing on the
+/// next.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Upload what the link points to, as if it were a real file or directory.
+    Follow,
+    /// Leave the link out and warn about it by name.
+    #[default]
+    Skip,
+    /// Refuse to push while `upload_dir` contains any symlink.
+    Error,
+}
+
+/// `concurrency = ...`: how many requests a push keeps in flight. Also
+/// `--concurrency` and `WAVEDASH_UPLOAD_CONCURRENCY`, which take the same
+/// values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Concurrency {
+    /// Start modestly and follow the throughput and error rate the push
+    /// actually gets, from a home DSL line to a CI runner's 10 Gbit.
+    Auto,
+    Fixed(usize),
+}
+
+impl std::fmt::Display for Concurrency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Concurrency::Auto => write!(f, "auto"),
+            Concurrency::Fixed(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// `"auto"`, or a whole number of requests, at least 1.
+pub fn parse_concurrency(text: &str) -> Result<Concurrency, String> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("auto") {
+        return Ok(Concurrency::Auto);
+    }
+    match text.parse::<usize>() {
+        Ok(0) => Err("concurrency must be at least 1".to_string()),
+        Ok(n) => Ok(Concurrency::Fixed(n)),
+        Err(_) => Err(format!(
+            "'{}' is not a concurrency (expected a number or \"auto\")",
+            text
+        )),
+    }
+}
+
+fn deserialize_concurrency<'de, D>(deserializer: D) -> Result<Option<Concurrency>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Number(u64),
+        Text(String),
+    }
+    let text = match Raw::deserialize(deserializer)? {
+        Raw::Number(n) => n.to_string(),
+        Raw::Text(text) => match non_blank(text) {
+            None => return Ok(None),
+            Some(text) => text,
+        },
+    };
+    parse_concurrency(&text)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_byte_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Bytes(u64),
+        Text(String),
+    }
+    match Raw::deserialize(deserializer)? {
+        Raw::Bytes(bytes) => Ok(Some(bytes)),
+        Raw::Text(text) => match non_blank(text) {
+            None => Ok(None),
+            Some(text) => parse_byte_size(&text).map(Some).map_err(serde::de::Error::custom),
+        },
+    }
+}
+
+/// `"512"`, `"64 KB"`, `"1.5GiB"`. KB and KiB are both 1024 bytes: the CLI
+/// labels its 1024-based sizes KB/MB/GB, and a budget should read the same as
+/// the number it's compared against.
+pub fn parse_byte_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a size (expected e.g. \"200 MB\")", text))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        other => return Err(format!("unknown size unit '{}' in '{}'", other, text)),
+    };
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// `"5MB/s"`, `"500 KB"`: a [`parse_byte_size`] size per second, with the
+/// `/s` optional. Zero would never finish, so it's refused.
+pub fn parse_byte_rate(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let size = text
+        .strip_suffix("/s")
+        .or_else(|| text.strip_suffix("/S"))
+        .unwrap_or(text);
+    match parse_byte_size(size) {
+        Ok(0) => Err("rate must be more than 0 bytes per second".to_string()),
+        Ok(rate) => Ok(rate),
+        Err(_) => Err(format!(
+            "'{}' is not a rate (expected e.g. \"5MB/s\")",
+            text
+        )),
+    }
+}
+
+/// [`ENV_MAX_UPLOAD_RATE`], in bytes per second; `None` when unset or blank.
+pub fn max_upload_rate_from_env() -> Result<Option<u64>> {
+    let Some(value) = raw_env(ENV_MAX_UPLOAD_RATE).and_then(non_blank) else {
+        return Ok(None);
+    };
+    parse_byte_rate(&value)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("{} is invalid: {}", ENV_MAX_UPLOAD_RATE, e))
+}
+
+/// Where a build's objects are stored. Always R2 in real use; the others are
+/// for running a push without it, with the API stood in as well (see
+/// `WAVEDASH_API_HOST`), and like it only debug builds take them. The bucket
+/// and key prefix still come from the API either way, so the object keys are
+/// the ones R2 would get.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum StorageTarget {
+    /// The endpoint and credentials the API hands out.
+    #[default]
+    R2,
+    /// Another S3-compatible endpoint, such as a local MinIO, used with the
+    /// credentials the API hands out.
+    S3 { endpoint: String },
+    /// A directory, with each bucket a subdirectory of it.
+    Fs { root: PathBuf },
+    /// One in-memory bucket store for the life of the process.
+    Memory,
+}
+
+/// `r2`, `memory`, `fs:<dir>`, or `s3:<endpoint>`; only `r2` in a release
+/// binary.
+pub fn parse_storage_target(text: &str) -> Result<StorageTarget, String> {
+    let text = text.trim();
+    let (kind, location) = match text.split_once(':') {
+        Some((kind, location)) => (kind, Some(location.trim())),
+        None => (text, None),
+    };
+    let target = match (kind.to_ascii_lowercase().as_str(), location) {
+        ("r2", None) => StorageTarget::R2,
+        ("memory", None) => StorageTarget::Memory,
+        ("fs", Some(root)) if !root.is_empty() => StorageTarget::Fs {
+            root: PathBuf::from(root),
+        },
+        ("s3", Some(endpoint)) if !endpoint.is_empty() => StorageTarget::S3 {
+            endpoint: endpoint.to_string(),
+        },
+        _ => {
+            return Err(format!(
+                "'{}' is not a storage target (expected r2, memory, fs:<dir> or s3:<endpoint>)",
+                text
+            ))
+        }
+    };
+    #[cfg(not(debug_assertions))]
+    if target != StorageTarget::R2 {
+        return Err(format!(
+            "'{}' is only for debug builds; a release build always stores in R2",
+            text
+        ));
+    }
+    Ok(target)
+}
+
+/// [`ENV_STORAGE`]; `None` when unset or blank.
+#[cfg(debug_assertions)]
+pub fn storage_target_from_env() -> Result<Option<StorageTarget>> {
+    let Some(value) = raw_env(ENV_STORAGE).and_then(non_blank) else {
+        return Ok(None);
+    };
+    parse_storage_target(&value)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("{} is invalid: {}", ENV_STORAGE, e))
+}
+
+/// A release binary only ever stores in R2.
+#[cfg(not(debug_assertions))]
+pub fn storage_target_from_env() -> Result<Option<StorageTarget>> {
+    Ok(None)
+}
+
+/// One `[profiles.<name>]`: a variant of the build — a demo, a staging game —
+/// kept in the same file as the real one. Selected with `--profile` or
+/// [`ENV_PROFILE`], it's overlaid on the file's own values before any accessor
+/// reads them: `game_id`, `upload_dir` and `entrypoint` replace the base's,
+/// `exclude` patterns go after the base's (so a `!pattern` can still re-include
+/// what the base left out), and declaring any engine section replaces the
+/// base's engine outright. Overrides still beat whatever the profile set, as
+/// they beat the file.
+///
+/// Unknown keys are refused here, unlike at the top level: a profile is only
+/// there for what it changes, so a key it can't change is a mistake worth
+/// hearing about rather than a setting silently left at the base's value.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileSection {
+    game_id: Option<String>,
+    upload_dir: Option<PathBuf>,
+    entrypoint: Option<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    godot: Option<GodotSection>,
+    unity: Option<UnitySection>,
+    jsdos: Option<ExecutableEngineSection>,
+    ruffle: Option<ExecutableEngineSection>,
+    renpy: Option<ExecutableEngineSection>,
+}
+
+/// `[workspace]` in a root wavedash.toml: the games of a monorepo, as
+/// directories with a wavedash.toml each, which `--all` and `--package` run a
+/// command across. Each entry is a directory relative to the root, or `dir/*`
+/// for every directory under `dir` that has a wavedash.toml.
+///
+/// A member inherits the root's shared settings: its `exclude` and
+/// `[[headers]]` come after the root's, and `[build]`, `[budgets]`,
+/// `symlinks` and `concurrency` are the root's wherever the member doesn't set
+/// its own. An inherited `[build]` runs as if the member had written it, from
+/// the member's directory. What makes a member a different game — `game_id`,
+/// `upload_dir`, `entrypoint`, the engine, its profiles — is never inherited.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct WorkspaceSection {
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+/// A workspace and the members it lists, as [`find_workspace`] finds them.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    /// The root's wavedash.toml.
+    pub root: PathBuf,
+    pub members: Vec<WorkspaceMember>,
+}
+
+/// One game in a [`Workspace`]: the name `--package` selects it by, which is
+/// its directory's, and its wavedash.toml.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceMember {
+    pub name: String,
+    pub config_path: PathBuf,
+}
+
+const CONFIG_FILE_NAME: &str = "wavedash.toml";
+
+/// Whether workspace discovery was asked for. `--all` and `--package` were,
+/// so anything wrong with the workspace is an error. A plain load only looks
+/// for a root to inherit from, and a wavedash.toml above the project that it
+/// can't read, or that belongs to another game entirely, mustn't break it:
+/// only a root that declares `[workspace]` and then fails to parse does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Discovery {
+    Explicit,
+    Implicit,
+}
+
+/// The workspace `config_path` belongs to: the one it roots, or else the one
+/// rooted in the nearest directory above it, if that lists it as a member.
+/// `None` when it's part of neither, or doesn't exist.
+pub fn find_workspace(config_path: &Path) -> Result<Option<Workspace>> {
+    let config_path = match config_path.canonicalize() {
+        Ok(path) => path,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => anyhow::bail!("Failed to resolve {}: {}", config_path.display(), e),
+    };
+    if let Some(root) = read_workspace_root(&config_path, Discovery::Explicit)? {
+        let members = workspace_members(&config_path, &root, Discovery::Explicit)?;
+        return Ok(Some(Workspace {
+            root: config_path,
+            members,
+        }));
+    }
+    Ok(enclosing_workspace(&config_path, Discovery::Explicit)?
+        .map(|(root, _, members)| Workspace { root, members }))
+}
+
+/// True when `contents` has a `[workspace]` table, in any of the ways TOML can
+/// spell one. Checked before parsing, so the other games' wavedash.toml files
+/// a project sits under are never parsed just to find they aren't roots.
+fn declares_workspace(contents: &str) -> bool {
+    contents.lines().any(|line| {
+        let line = line.trim_start();
+        let key = line.strip_prefix('[').map_or(line, str::trim_start);
+        key.strip_prefix("workspace")
+            .is_some_and(|rest| rest.trim_start().starts_with([']', '.', '=']))
+    })
+}
+
+/// The config at `path` if it roots a workspace; `None` if it doesn't, or
+/// there's no file there.
+fn read_workspace_root(path: &Path, discovery: Discovery) -> Result<Option<WavedashConfig>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(_) if discovery == Discovery::Implicit => return Ok(None),
+        Err(e) => anyhow::bail!("Failed to read {}: {}", path.display(), e),
+    };
+    if !declares_workspace(&contents) {
+        return Ok(None);
+    }
+    let config: WavedashConfig = toml::from_str(&contents)
+        .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+    Ok(config.workspace.is_some().then_some(config))
+}
+
+/// The workspace rooted above the member config at `config_path`, which has
+/// to be canonical: its root's path and config, and every member. Like Cargo,
+/// only the nearest root is considered — one that doesn't list the config
+/// means it isn't in a workspace, not that a root further up might.
+fn enclosing_workspace(
+    config_path: &Path,
+    discovery: Discovery,
+) -> Result<Option<(PathBuf, WavedashConfig, Vec<WorkspaceMember>)>> {
+    let Some(member_dir) = config_path.parent() else {
+        return Ok(None);
+    };
+    for dir in member_dir.ancestors().skip(1) {
+        let candidate = dir.join(CONFIG_FILE_NAME);
+        let Some(root) = read_workspace_root(&candidate, discovery)? else {
+            continue;
+        };
+        let members = workspace_members(&candidate, &root, discovery)?;
+        if !members
+            .iter()
+            .any(|member| member.config_path == config_path)
+        {
+            return Ok(None);
+        }
+        return Ok(Some((candidate, root, members)));
+    }
+    Ok(None)
+}
+
+/// The members a root lists, in the order it lists them, with canonical
+/// config paths. When discovery was asked for, `Err` for a listed directory
+/// with no wavedash.toml, or two members with the same name, since `--package`
+/// couldn't tell them apart. Otherwise what can't be found is left out: all a
+/// plain load needs is whether its own config is listed.
+fn workspace_members(
+    root_path: &Path,
+    root: &WavedashConfig,
+    discovery: Discovery,
+) -> Result<Vec<WorkspaceMember>> {
+    let strict = discovery == Discovery::Explicit;
+    let root_dir = root_path.parent().unwrap_or(Path::new(""));
+    let entries = root
+        .workspace
+        .as_ref()
+        .map(|section| section.members.as_slice())
+        .unwrap_or_default();
+    let mut dirs = Vec::new();
+    for entry in entries.iter().filter_map(|entry| non_blank(entry.clone())) {
+        if let Some(parent) = entry.strip_suffix("/*") {
+            let parent = root_dir.join(parent);
+            let listing = match std::fs::read_dir(&parent) {
+                Ok(listing) => listing,
+                Err(_) if !strict => continue,
+                Err(e) => anyhow::bail!(
+                    "[workspace] member {} in {}: can't read {}: {}",
+                    entry,
+                    root_path.display(),
+                    parent.display(),
+                    e
+                ),
+            };
+            let mut found: Vec<PathBuf> = listing
+                .filter_map(|dir| dir.ok().map(|dir| dir.path()))
+                .filter(|dir| dir.join(CONFIG_FILE_NAME).is_file())
+                .collect();
+            found.sort();
+            dirs.extend(found);
+        } else {
+            let dir = root_dir.join(&entry);
+            if !dir.join(CONFIG_FILE_NAME).is_file() {
+                if !strict {
+                    continue;
+                }
+                anyhow::bail!(
+                    "[workspace] member {} in {} has no {}",
+                    entry,
+                    root_path.display(),
+                    CONFIG_FILE_NAME
+                );
+            }
+            dirs.push(dir);
+        }
+    }
+
+    let mut members: Vec<WorkspaceMember> = Vec::new();
+    for dir in dirs {
+        let dir = match dir.canonicalize() {
+            Ok(dir) => dir,
+            Err(_) if !strict => continue,
+            Err(e) => anyhow::bail!("Failed to resolve {}: {}", dir.display(), e),
+        };
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let twin = members.iter().find(|member| member.name == name);
+        if let Some(twin) = twin.filter(|_| strict) {
+            anyhow::bail!(
+                "Two [workspace] members in {} are named {}: {} and {}. Rename one of the directories.",
+                root_path.display(),
+                name,
+                twin.config_path.display(),
+                dir.join(CONFIG_FILE_NAME).display()
+            );
+        }
+        members.push(WorkspaceMember {
+            name,
+            config_path: dir.join(CONFIG_FILE_NAME),
+        });
+    }
+    Ok(members)
+}
+
+/// The per-game overrides that are set, by variable name. Each would land on
+/// every member of a multi-member run, which is never what a workspace wants.
+pub fn per_game_overrides() -> Vec<&'static str> {
+    let env = EnvOverrides::capture(raw_env);
+    [
+        (ENV_GAME_ID, env.game_id.is_some()),
+        (ENV_UPLOAD_DIR, env.upload_dir.is_some()),
+        (ENV_ENTRYPOINT, env.entrypoint.is_some()),
+        (ENV_GODOT_VERSION, env.godot_version.is_some()),
+        (ENV_UNITY_VERSION, env.unity_version.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(name, set)| set.then_some(name))
+    .collect()
+}
+
+/// The profile that was overlaid, and which fields it set, so the accessors
+/// can say so when they hand one of its values out.
+#[derive(Debug, Default)]
+struct AppliedProfile {
+    name: String,
+    /// [`Field`] bits for the values it replaced.
+    fields: u8,
+    /// How many of the trailing `exclude` patterns are its own.
+    excludes: usize,
+}
+
 /// The resolved project layer: `wavedash.toml` plus `WAVEDASH_*` overrides, with
 /// the file optional. Not the file itself — see the module docs.
 ///
@@ -375,6 +1027,26 @@ pub struct WavedashConfig {
     #[serde(default)]
     upload_dir: Option<PathBuf>,
     entrypoint: Option<String>,
+    /// Gitignore-style patterns, relative to `upload_dir`, for files to leave
+    /// out of a push. Layered with `.wavedashignore` in `crate::exclude`.
+    #[serde(default)]
+    exclude: Vec<String>,
+    /// Per-pattern object metadata for uploads. See [`HeaderOverride`].
+    #[serde(default)]
+    headers: Vec<HeaderOverride>,
+    /// Size limits for `build analyze`. See [`BudgetsSection`].
+    #[serde(default)]
+    budgets: BudgetsSection,
+    /// See [`SymlinkPolicy`]. `None` rather than the default when unset, so a
+    /// workspace member can tell it left the choice to its root.
+    symlinks: Option<SymlinkPolicy>,
+    /// See [`Concurrency`]. `None` leaves it to the uploader's default.
+    #[serde(default, deserialize_with = "deserialize_concurrency")]
+    concurrency: Option<Concurrency>,
+    /// See [`BuildStep`].
+    build: Option<BuildSection>,
+    /// See [`WorkspaceSection`]. Only ever set on a workspace root.
+    workspace: Option<WorkspaceSection>,
 
     #[serde(rename = "godot")]
     godot: Option<GodotSection>,
@@ -391,6 +1063,11 @@ pub struct WavedashConfig {
     #[serde(rename = "renpy")]
     renpy: Option<ExecutableEngineSection>,
 
+    /// See [`ProfileSection`]. Only the selected one is ever read, and it's
+    /// folded into the fields above before anything does.
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileSection>,
+
     /// The environment as it stood when this config was built. Every accessor
     /// consults its own override here before the field above it, so precedence,
     /// override reporting, and the refusals that only concern a build all land on
@@ -399,6 +1076,10 @@ pub struct WavedashConfig {
     #[serde(skip)]
     env: EnvOverrides,
 
+    /// `None` unless a profile was selected.
+    #[serde(skip)]
+    profile: Option<AppliedProfile>,
+
     /// Bitmask of fields already announced. Atomic because the accessors take
     /// `&self` and the config is shared across the async call graph.
     #[serde(skip)]
@@ -509,7 +1190,7 @@ impl UploadSource {
 /// unpopulated CI variable the blank-is-unset rule exists to ignore. Resolving
 /// here keeps `stat`/`achievement` and `build push` reading one set of rules.
 pub fn resolve_game_id(cli_game_id: Option<&str>, config_path: &PathBuf) -> Result<String> {
-    resolve_game_id_with(cli_game_id, config_path, EnvOverrides::capture(raw_env))
+    resolve_game_id_with(cli_game_id, config_path, process_overrides())
 }
 
 /// [`resolve_game_id`] against a given environment, so the precedence above can
@@ -554,7 +1235,7 @@ impl WavedashConfig {
     /// needs is decided by the accessors it calls, so a file-less config only
     /// fails on the first field the environment didn't supply.
     pub fn load(config_path: &PathBuf) -> Result<Self> {
-        Self::with_overrides(config_path, EnvOverrides::capture(raw_env))
+        Self::with_overrides(config_path, process_overrides())
     }
 
     /// [`Self::load`] against a given environment. The seam exists so the
@@ -566,7 +1247,15 @@ impl WavedashConfig {
                 let mut config: WavedashConfig = toml::from_str(&config_content)
                     .map_err(|e| anyhow::anyhow!("Failed to parse config file: {}", e))?;
                 config.from_file = true;
-                config.treat_blank_file_values_as_unset();
+                // Workspaces don't nest, so a root never looks for one above it.
+                if config.workspace.is_none() {
+                    if let Ok(path) = config_path.canonicalize() {
+                        if let Some((_, root, _)) = enclosing_workspace(&path, Discovery::Implicit)?
+                        {
+                            config.inherit_from(root);
+                        }
+                    }
+                }
                 config
             }
             // Bail on a missing file only when the environment supplies nothing
@@ -586,6 +1275,8 @@ impl WavedashConfig {
             ),
         };
         config.config_path = config_path.clone();
+        config.apply_profile(env.profile.as_ref())?;
+        config.treat_blank_file_values_as_unset();
         config.env = env;
 
         // Nothing is resolved, announced, or refused here. Every override is
@@ -595,6 +1286,107 @@ impl WavedashConfig {
         Ok(config)
     }
 
+    /// Take what a workspace member leaves to its root — see
+    /// [`WorkspaceSection`]. Before profiles and blanks, so the root's values
+    /// are part of the base a profile overlays and get the file's treatment.
+    fn inherit_from(&mut self, root: WavedashConfig) {
+        let mut exclude = root.exclude;
+        exclude.append(&mut self.exclude);
+        self.exclude = exclude;
+        let mut headers = root.headers;
+        headers.append(&mut self.headers);
+        self.headers = headers;
+        self.build = self.build.take().or(root.build);
+        self.budgets.max_total = self.budgets.max_total.or(root.budgets.max_total);
+        self.budgets.max_file = self.budgets.max_file.or(root.budgets.max_file);
+        self.symlinks = self.symlinks.or(root.symlinks);
+        self.concurrency = self.concurrency.or(root.concurrency);
+    }
+
+    /// Overlay the selected profile on what the file said — see
+    /// [`ProfileSection`]. Done before blanks are dropped, so what the profile
+    /// brings in gets the file's treatment; a blank value in the profile itself
+    /// is skipped rather than overlaid, leaving the base's value in place
+    /// instead of unsetting it.
+    fn apply_profile(&mut self, choice: Option<&ProfileChoice>) -> Result<()> {
+        let Some(choice) = choice else {
+            return Ok(());
+        };
+        if !self.from_file {
+            anyhow::bail!(
+                "{} selects a profile, but there's no config file at {} to define it. Run `wavedash init`, or pass --config if the config lives elsewhere.",
+                choice.describe(),
+                self.config_path.display()
+            );
+        }
+        let Some(profile) = self.profiles.remove(&choice.name) else {
+            let defined = if self.profiles.is_empty() {
+                "It defines none.".to_string()
+            } else {
+                let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                format!("Defined: {}.", names.join(", "))
+            };
+            anyhow::bail!(
+                "{} selects [profiles.{}], but {} doesn't define it. {}",
+                choice.describe(),
+                choice.name,
+                self.config_path.display(),
+                defined
+            );
+        };
+
+        let mut fields = 0;
+        if let Some(game_id) = profile.game_id.and_then(non_blank) {
+            self.game_id = Some(game_id);
+            fields |= Field::GameId.bit();
+        }
+        if let Some(upload_dir) = profile
+            .upload_dir
+            .and_then(|dir| non_blank(dir.to_string_lossy().into_owned()))
+        {
+            self.upload_dir = Some(PathBuf::from(upload_dir));
+            fields |= Field::UploadDir.bit();
+        }
+        if let Some(entrypoint) = profile.entrypoint.and_then(non_blank) {
+            self.entrypoint = Some(entrypoint);
+            fields |= Field::Entrypoint.bit();
+        }
+        // Filtered here rather than left to the blank pass, so the profile's
+        // patterns stay the last `excludes` of the list after it runs.
+        let excludes: Vec<String> = profile
+            .exclude
+            .into_iter()
+            .filter(|pattern| !pattern.trim().is_empty())
+            .collect();
+        if !excludes.is_empty() {
+            fields |= Field::Exclude.bit();
+        }
+        let excludes_len = excludes.len();
+        self.exclude.extend(excludes);
+        // All or nothing: keeping any of the base's sections next to the
+        // profile's would be two engines, which every engine read refuses.
+        if profile.godot.is_some()
+            || profile.unity.is_some()
+            || profile.jsdos.is_some()
+            || profile.ruffle.is_some()
+            || profile.renpy.is_some()
+        {
+            self.godot = profile.godot;
+            self.unity = profile.unity;
+            self.jsdos = profile.jsdos;
+            self.ruffle = profile.ruffle;
+            self.renpy = profile.renpy;
+            fields |= Field::Engine.bit();
+        }
+
+        self.profile = Some(AppliedProfile {
+            name: choice.name.clone(),
+            fields,
+            excludes: excludes_len,
+        });
+        Ok(())
+    }
+
     /// A blank value written in the file means what a blank override means:
     /// nothing. Applied at load so the two can't disagree — and because a blank
     /// is worse than useless here rather than merely empty. `upload_dir = ""`
@@ -621,6 +1413,21 @@ impl WavedashConfig {
             .take()
             .and_then(|dir| non_blank(dir.to_string_lossy().into_owned()))
             .map(PathBuf::from);
+        self.exclude.retain(|pattern| !pattern.trim().is_empty());
+        // An entry that matches nothing can't set anything.
+        self.headers.retain(|entry| !entry.pattern.trim().is_empty());
+        for entry in &mut self.headers {
+            entry.treat_blank_values_as_unset();
+        }
+        if let Some(build) = &mut self.build {
+            build.command = build.command.take().and_then(non_blank);
+            build.cwd = build
+                .cwd
+                .take()
+                .and_then(|dir| non_blank(dir.to_string_lossy().into_owned()))
+                .map(PathBuf::from);
+            build.inputs.retain(|pattern| !pattern.trim().is_empty());
+        }
 
         if let Some(godot) = &mut self.godot {
             godot.version = godot.version.take().and_then(non_blank);
@@ -645,10 +1452,28 @@ impl WavedashConfig {
         self.announced.fetch_or(bit, Ordering::Relaxed) & bit == 0
     }
 
+    /// Announce, once, a value the selected profile supplied for `field`. Only
+    /// called once the environment has had its say, so a value is credited to
+    /// the source it really came from.
+    fn announce_profile_value(&self, field: Field, setting: impl FnOnce() -> String) {
+        let Some(profile) = &self.profile else {
+            return;
+        };
+        if profile.fields & field.bit() != 0 && self.first_read_of(field) {
+            print_profile_notice(&profile_notice(&profile.name, &setting()));
+        }
+    }
+
     /// Error for a field no command-visible source supplied. Named per field
     /// rather than up front, so a command is only ever asked for what it reads.
     fn missing_field(&self, field: &str, env_var: &str) -> anyhow::Error {
-        if self.from_file {
+        if self.workspace.is_some() {
+            anyhow::anyhow!(
+                "{} is not set. {} is a workspace root: pass --all or --package <name> to run for its members.",
+                field,
+                self.config_path.display()
+            )
+        } else if self.from_file {
             anyhow::anyhow!(
                 "{} is not set. Add it to {} or set {}.",
                 field,
@@ -687,8 +1512,8 @@ impl WavedashConfig {
         }
     }
 
-    /// The game to act on: `WAVEDASH_GAME_ID`, else `game_id` from the file.
-    /// `Err` when neither supplied it. To include the `--game-id` flag in the
+    /// The game to act on: `WAVEDASH_GAME_ID`, else `game_id` from the profile
+    /// or the file. `Err` when none supplied it. To include the `--game-id` flag in the
     /// precedence, go through [`resolve_game_id`] instead.
     pub fn game_id(&self) -> Result<&str> {
         if let Some(game_id) = &self.env.game_id {
@@ -697,13 +1522,16 @@ impl WavedashConfig {
             }
             return Ok(game_id);
         }
-        self.game_id
+        let game_id = self
+            .game_id
             .as_deref()
-            .ok_or_else(|| self.missing_field("game_id", ENV_GAME_ID))
+            .ok_or_else(|| self.missing_field("game_id", ENV_GAME_ID))?;
+        self.announce_profile_value(Field::GameId, || format!("game_id = {}", game_id));
+        Ok(game_id)
     }
 
     /// The directory to upload: `WAVEDASH_UPLOAD_DIR`, else `upload_dir` from the
-    /// file. `Err` when neither supplied it. Relative either way — callers resolve
+    /// profile or the file. `Err` when none supplied it. Relative either way — callers resolve
     /// it against the config file's directory, which is also why an absolute
     /// override wins outright: that's how their `config_dir.join(..)` behaves.
     pub fn upload_dir(&self) -> Result<&PathBuf> {
@@ -713,9 +1541,77 @@ impl WavedashConfig {
             }
             return Ok(upload_dir);
         }
-        self.upload_dir
+        let upload_dir = self
+            .upload_dir
             .as_ref()
-            .ok_or_else(|| self.missing_field("upload_dir", ENV_UPLOAD_DIR))
+            .ok_or_else(|| self.missing_field("upload_dir", ENV_UPLOAD_DIR))?;
+        self.announce_profile_value(Field::UploadDir, || {
+            format!("upload_dir = {}", upload_dir.display())
+        });
+        Ok(upload_dir)
+    }
+
+    /// `exclude` patterns from the file, then the profile's. There is no
+    /// override: a pattern list doesn't fit in one variable, and
+    /// `.wavedashignore` is the place for anything that varies by checkout.
+    pub fn excludes(&self) -> &[String] {
+        if let Some(profile) = &self.profile {
+            let own = &self.exclude[self.exclude.len() - profile.excludes..];
+            self.announce_profile_value(Field::Exclude, || {
+                format!("exclude += {}", own.join(", "))
+            });
+        }
+        &self.exclude
+    }
+
+    /// `[budgets]` from the file; both limits are `None` when it has none.
+    pub fn budgets(&self) -> &BudgetsSection {
+        &self.budgets
+    }
+
+    /// `symlinks` from the file; [`SymlinkPolicy::Skip`] when it's not set.
+    pub fn symlinks(&self) -> SymlinkPolicy {
+        self.symlinks.unwrap_or_default()
+    }
+
+    /// Requests a push keeps in flight: `WAVEDASH_UPLOAD_CONCURRENCY`, else
+    /// `concurrency` from the file; `None` when neither set it. `--concurrency`
+    /// goes ahead of both, which is the caller's to apply.
+    pub fn upload_concurrency(&self) -> Result<Option<Concurrency>> {
+        if let Some(value) = &self.env.upload_concurrency {
+            let concurrency = parse_concurrency(value)
+                .map_err(|e| anyhow::anyhow!("{} is invalid: {}", ENV_UPLOAD_CONCURRENCY, e))?;
+            if self.first_read_of(Field::Concurrency) {
+                print_override_notice(&concurrency_notice(concurrency));
+            }
+            return Ok(Some(concurrency));
+        }
+        Ok(self.concurrency)
+    }
+
+    /// `[build]` from the file; `None` when it has none. `Err` for a section
+    /// with no command, which can only be a mistake.
+    pub fn build_step(&self) -> Result<Option<BuildStep<'_>>> {
+        let Some(build) = &self.build else {
+            return Ok(None);
+        };
+        let command = build.command.as_deref().ok_or_else(|| {
+            anyhow::anyhow!(
+                "[build] has no command. Add one to {}, or remove the section.",
+                self.config_path.display()
+            )
+        })?;
+        Ok(Some(BuildStep {
+            command,
+            cwd: build.cwd.as_deref(),
+            env: &build.env,
+            inputs: &build.inputs,
+        }))
+    }
+
+    /// `[[headers]]` entries from the file, in file order.
+    pub fn header_overrides(&self) -> &[HeaderOverride] {
+        &self.headers
     }
 
     /// The engine section the config file declares, with its version. `Err` when
@@ -841,9 +1737,16 @@ impl WavedashConfig {
         notices
     }
 
-    /// Announce an override-supplied engine, once. Saying nothing leaves the bit
-    /// unspent for a later read that does have something to report.
+    /// Announce an override- or profile-supplied engine, once. Saying nothing
+    /// leaves the bit unspent for a later read that does have something to
+    /// report.
     fn announce_engine(&self, engine: &ActiveEngine<'_>) {
+        if engine.override_var.is_none() {
+            self.announce_profile_value(Field::Engine, || {
+                format!("[{}].version = {}", engine.section, engine.version)
+            });
+            return;
+        }
         let notices = self.engine_notices(engine);
         if notices.is_empty() || !self.first_read_of(Field::Engine) {
             return;
@@ -933,7 +1836,12 @@ impl WavedashConfig {
             return Ok(Some((entrypoint, EntrypointSource::Env)));
         }
         Ok(Some(match self.entrypoint.as_deref() {
-            Some(entrypoint) => (entrypoint, EntrypointSource::Config),
+            Some(entrypoint) => {
+                self.announce_profile_value(Field::Entrypoint, || {
+                    format!("entrypoint = {}", entrypoint)
+                });
+                (entrypoint, EntrypointSource::Config)
+            }
             None => (DEFAULT_ENTRYPOINT, EntrypointSource::Default),
         }))
     }
@@ -1007,6 +1915,9 @@ mod tests {
             toml::from_str(toml_str).expect("test config should parse");
         config.from_file = true;
         config.config_path = PathBuf::from("/games/thing/wavedash.toml");
+        config
+            .apply_profile(env.profile.as_ref())
+            .expect("profile should apply");
         config.treat_blank_file_values_as_unset();
         config.env = env;
         config
@@ -1266,6 +2177,120 @@ mod tests {
         assert_eq!(config.entrypoint().unwrap(), Some("index.html"));
     }
 
+    /// A blank pattern would be skipped by the matcher anyway; dropping it here
+    /// keeps it out of `--verbose` and anything else that lists the patterns.
+    #[test]
+    fn blank_exclude_patterns_are_dropped() {
+        let config = from_file(
+            "upload_dir = \"build\"\nexclude = [\"*.psd\", \"  \", \"\"]\n",
+            overrides(&[]),
+        );
+        assert_eq!(config.excludes(), ["*.psd".to_string()]);
+    }
+
+    #[test]
+    fn symlink_policy_defaults_to_skip() {
+        let config = from_file("upload_dir = \"build\"\n", overrides(&[]));
+        assert_eq!(config.symlinks(), SymlinkPolicy::Skip);
+        let config = from_file(
+            "upload_dir = \"build\"\nsymlinks = \"follow\"\n",
+            overrides(&[]),
+        );
+        assert_eq!(config.symlinks(), SymlinkPolicy::Follow);
+        assert!(toml::from_str::<WavedashConfig>("symlinks = \"sometimes\"\n").is_err());
+    }
+
+    #[test]
+    fn concurrency_comes_from_the_environment_then_the_file() {
+        let file = "upload_dir = \"build\"\nconcurrency = 4\n";
+        assert_eq!(
+            from_file(file, overrides(&[]))
+                .upload_concurrency()
+                .unwrap(),
+            Some(Concurrency::Fixed(4))
+        );
+        assert_eq!(
+            from_file(file, overrides(&[(ENV_UPLOAD_CONCURRENCY, "auto")]))
+                .upload_concurrency()
+                .unwrap(),
+            Some(Concurrency::Auto)
+        );
+        // Blank is unset, in either place.
+        assert_eq!(
+            from_file(file, overrides(&[(ENV_UPLOAD_CONCURRENCY, "  ")]))
+                .upload_concurrency()
+                .unwrap(),
+            Some(Concurrency::Fixed(4))
+        );
+        let blank = from_file("concurrency = \"\"\n", overrides(&[]));
+        assert_eq!(blank.upload_concurrency().unwrap(), None);
+
+        let err = from_file(file, overrides(&[(ENV_UPLOAD_CONCURRENCY, "0")]))
+            .upload_concurrency()
+            .expect_err("zero requests can't upload anything");
+        assert!(
+            err.to_string().contains(ENV_UPLOAD_CONCURRENCY),
+            "got: {}",
+            err
+        );
+        assert!(toml::from_str::<WavedashConfig>("concurrency = \"fast\"\n").is_err());
+    }
+
+    #[test]
+    fn budgets_take_bytes_or_sizes_in_the_units_the_cli_prints() {
+        let config = from_file(
+            "upload_dir = \"build\"\n[budgets]\nmax_total = \"1.5 GB\"\nmax_file = 1024\n",
+            overrides(&[]),
+        );
+        assert_eq!(config.budgets().max_total, Some(1536 * 1024 * 1024));
+        assert_eq!(config.budgets().max_file, Some(1024));
+
+        assert_eq!(parse_byte_size("64KiB"), Ok(64 * 1024));
+        assert!(parse_byte_size("lots").is_err());
+        assert!(parse_byte_size("5 parsecs").is_err());
+        assert!(toml::from_str::<WavedashConfig>("[budgets]\nmax_total = \"big\"\n").is_err());
+    }
+
+    #[test]
+    fn rates_are_sizes_per_second() {
+        assert_eq!(parse_byte_rate("5MB/s"), Ok(5 * 1024 * 1024));
+        assert_eq!(parse_byte_rate(" 512 KiB/s "), Ok(512 * 1024));
+        assert_eq!(parse_byte_rate("1000"), Ok(1000));
+        assert!(parse_byte_rate("0/s").is_err());
+        assert!(parse_byte_rate("fast").is_err());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn storage_targets_name_a_kind_and_where() {
+        assert_eq!(parse_storage_target("R2"), Ok(StorageTarget::R2));
+        assert_eq!(parse_storage_target("memory"), Ok(StorageTarget::Memory));
+        assert_eq!(
+            parse_storage_target("fs:/tmp/bucket"),
+            Ok(StorageTarget::Fs {
+                root: PathBuf::from("/tmp/bucket")
+            })
+        );
+        assert_eq!(
+            parse_storage_target("s3:http://localhost:9000"),
+            Ok(StorageTarget::S3 {
+                endpoint: "http://localhost:9000".to_string()
+            })
+        );
+        assert!(parse_storage_target("fs:").is_err());
+        assert!(parse_storage_target("memory:/tmp").is_err());
+        assert!(parse_storage_target("gcs:bucket").is_err());
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[test]
+    fn release_builds_only_store_in_r2() {
+        assert_eq!(parse_storage_target("r2"), Ok(StorageTarget::R2));
+        assert!(parse_storage_target("memory").is_err());
+        assert!(parse_storage_target("fs:/tmp/bucket").is_err());
+        assert!(parse_storage_target("s3:http://localhost:9000").is_err());
+    }
+
     /// Blank in the file, real value in the environment: the override supplies
     /// it, exactly as it would for an absent field.
     #[test]
@@ -1826,6 +2851,289 @@ mod tests {
         );
     }
 
+    #[test]
+    fn a_build_section_needs_a_command() {
+        let config = from_file(
+            "game_id = \"g\"\nupload_dir = \"dist\"\n\n[build]\ncommand = \" \"\ninputs = [\"src/**\", \"\"]\n",
+            overrides(&[]),
+        );
+        let err = config.build_step().unwrap_err();
+        assert!(
+            err.to_string().contains("[build] has no command"),
+            "got: {}",
+            err
+        );
+
+        let config = from_file(
+            "game_id = \"g\"\nupload_dir = \"dist\"\n\n[build]\ncommand = \"make web\"\ninputs = [\"src/**\", \"\"]\n\n[build.env]\nMODE = \"release\"\n",
+            overrides(&[]),
+        );
+        let step = config.build_step().unwrap().expect("build step");
+        assert_eq!(step.command, "make web");
+        assert_eq!(step.cwd, None);
+        assert_eq!(step.inputs, ["src/**"]);
+        assert_eq!(step.env["MODE"], "release");
+        assert!(from_file(CUSTOM_CONFIG, overrides(&[]))
+            .build_step()
+            .unwrap()
+            .is_none());
+    }
+
+    const PROFILES_CONFIG: &str = r#"
+        game_id = "full-game"
+        upload_dir = "build/web"
+        exclude = ["raw/"]
+
+        [godot]
+        version = "4.2"
+
+        [profiles.demo]
+        game_id = "demo-game"
+        upload_dir = "  "
+        exclude = ["!raw/credits.txt", "levels/4-*"]
+
+        [profiles.demo.unity]
+        version = "6000.0"
+
+        [profiles.staging]
+        game_id = "staging-game"
+    "#;
+
+    #[test]
+    fn a_profile_overlays_the_file_and_announces_what_it_set() {
+        let config = from_file(PROFILES_CONFIG, overrides(&[(ENV_PROFILE, "demo")]));
+
+        assert_eq!(config.game_id().unwrap(), "demo-game");
+        // Blank in the profile, so the base's stands — and isn't credited to it.
+        assert_eq!(config.upload_dir().unwrap(), &PathBuf::from("build/web"));
+        assert_eq!(
+            config.excludes(),
+            ["raw/", "!raw/credits.txt", "levels/4-*"]
+        );
+        // The profile's engine replaces the base's rather than joining it.
+        assert_eq!(config.engine_type().unwrap(), Some(EngineKind::Unity));
+        assert_eq!(config.engine_version().unwrap(), Some("6000.0"));
+        assert_eq!(
+            config.announced.load(Ordering::Relaxed),
+            Field::GameId.bit() | Field::Exclude.bit() | Field::Engine.bit()
+        );
+    }
+
+    #[test]
+    fn without_a_profile_selected_the_profiles_are_inert() {
+        let config = from_file(PROFILES_CONFIG, overrides(&[]));
+
+        assert_eq!(config.game_id().unwrap(), "full-game");
+        assert_eq!(config.excludes(), ["raw/"]);
+        assert_eq!(config.engine_version().unwrap(), Some("4.2"));
+        assert_eq!(config.announced.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn overrides_beat_the_profile_and_the_flag_beats_the_variable() {
+        let env = overrides(&[(ENV_PROFILE, "demo"), (ENV_GAME_ID, "from_env")])
+            .with_profile_flag(Some("staging"));
+        let config = from_file(PROFILES_CONFIG, env);
+
+        assert_eq!(config.game_id().unwrap(), "from_env");
+        // Staging sets no engine, so the base's is still in play.
+        assert_eq!(config.engine_version().unwrap(), Some("4.2"));
+        assert_eq!(config.excludes(), ["raw/"]);
+    }
+
+    #[test]
+    fn an_unknown_profile_names_what_chose_it_and_the_ones_defined() {
+        let (_dir, path) = config_file(PROFILES_CONFIG);
+
+        let err = WavedashConfig::with_overrides(&path, overrides(&[(ENV_PROFILE, "prod")]))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("WAVEDASH_PROFILE=prod selects [profiles.prod]"),
+            "{}",
+            err
+        );
+        assert!(err.contains("Defined: demo, staging."), "{}", err);
+
+        let env = overrides(&[]).with_profile_flag(Some("prod"));
+        let err = WavedashConfig::with_overrides(&path, env)
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("--profile prod selects"), "{}", err);
+
+        // With no file, there's nothing for the name to select from.
+        let env = overrides(&[(ENV_GAME_ID, "from_env"), (ENV_PROFILE, "demo")]);
+        let err = WavedashConfig::with_overrides(&missing_config(), env)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("no config file at"), "{}", err);
+    }
+
+    #[test]
+    fn a_profile_refuses_keys_it_cannot_overlay() {
+        let err = toml::from_str::<WavedashConfig>("[profiles.demo]\nconcurrency = 4\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unknown field `concurrency`"), "{}", err);
+    }
+
+    /// A workspace on disk: the root's wavedash.toml, and a member per
+    /// `(dir, contents)`.
+    fn workspace_dir(root: &str, members: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().expect("temp dir");
+        std::fs::write(dir.path().join("wavedash.toml"), root).expect("write root");
+        for (member, contents) in members {
+            let member = dir.path().join(member);
+            std::fs::create_dir_all(&member).expect("member dir");
+            std::fs::write(member.join("wavedash.toml"), contents).expect("write member");
+        }
+        dir
+    }
+
+    #[test]
+    fn workspace_members_are_listed_or_globbed_and_found_from_either_end() {
+        let dir = workspace_dir(
+            "[workspace]\nmembers = [\"games/*\", \"tools/editor\"]\n",
+            &[
+                ("games/puzzle", "game_id = \"p\""),
+                ("games/racer", "game_id = \"r\""),
+                ("tools/editor", "game_id = \"e\""),
+                ("tools/unlisted", "game_id = \"u\""),
+            ],
+        );
+        std::fs::create_dir_all(dir.path().join("games/assets")).unwrap();
+
+        let workspace = find_workspace(&dir.path().join("wavedash.toml"))
+            .unwrap()
+            .expect("the root roots a workspace");
+        let names: Vec<&str> = workspace.members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["puzzle", "racer", "editor"]);
+
+        // A member finds the same workspace; a directory it doesn't list doesn't.
+        let from_member = find_workspace(&dir.path().join("games/racer/wavedash.toml"))
+            .unwrap()
+            .expect("a member belongs to it");
+        assert_eq!(from_member.root, workspace.root);
+        assert!(
+            find_workspace(&dir.path().join("tools/unlisted/wavedash.toml"))
+                .unwrap()
+                .is_none()
+        );
+
+        let missing = workspace_dir("[workspace]\nmembers = [\"nowhere\"]\n", &[]);
+        let err = find_workspace(&missing.path().join("wavedash.toml")).unwrap_err();
+        assert!(err.to_string().contains("member nowhere"), "{}", err);
+    }
+
+    #[test]
+    fn a_member_inherits_shared_settings_but_not_its_identity() {
+        let dir = workspace_dir(
+            r#"
+                game_id = "root"
+                exclude = ["*.psd"]
+                concurrency = 4
+                symlinks = "follow"
+
+                [workspace]
+                members = ["games/*"]
+
+                [build]
+                command = "make web"
+
+                [budgets]
+                max_total = "100 MB"
+                max_file = "10 MB"
+            "#,
+            &[
+                (
+                    "games/puzzle",
+                    "game_id = \"puzzle\"\nupload_dir = \"dist\"\nexclude = [\"!cover.psd\"]\n\n[budgets]\nmax_file = \"20 MB\"\n",
+                ),
+                (
+                    "games/racer",
+                    "upload_dir = \"dist\"\nsymlinks = \"error\"\n\n[build]\ncommand = \"npm run build\"\n",
+                ),
+            ],
+        );
+        let load = |member: &str| {
+            WavedashConfig::with_overrides(
+                &dir.path().join(member).join("wavedash.toml"),
+                overrides(&[]),
+            )
+            .unwrap()
+        };
+
+        let puzzle = load("games/puzzle");
+        assert_eq!(puzzle.game_id().unwrap(), "puzzle");
+        assert_eq!(puzzle.excludes(), ["*.psd", "!cover.psd"]);
+        assert_eq!(puzzle.build_step().unwrap().unwrap().command, "make web");
+        assert_eq!(puzzle.budgets().max_total, Some(100 * 1024 * 1024));
+        assert_eq!(puzzle.budgets().max_file, Some(20 * 1024 * 1024));
+        assert_eq!(puzzle.symlinks(), SymlinkPolicy::Follow);
+        assert_eq!(
+            puzzle.upload_concurrency().unwrap(),
+            Some(Concurrency::Fixed(4))
+        );
+
+        let racer = load("games/racer");
+        assert_eq!(
+            racer.build_step().unwrap().unwrap().command,
+            "npm run build"
+        );
+        assert_eq!(racer.symlinks(), SymlinkPolicy::Error);
+        // The root's game_id is its own, not a default for its members.
+        let err = racer.game_id().unwrap_err().to_string();
+        assert!(err.starts_with("game_id is not set."), "{}", err);
+
+        // And a command run on a root with no game says what to do instead.
+        let root =
+            WavedashConfig::with_overrides(&dir.path().join("wavedash.toml"), overrides(&[]))
+                .unwrap();
+        assert_eq!(root.game_id().unwrap(), "root");
+        assert!(root
+            .upload_dir()
+            .unwrap_err()
+            .to_string()
+            .contains("pass --all or --package"));
+    }
+
+    #[test]
+    fn only_a_declared_workspace_can_break_a_plain_load() {
+        let load = |dir: &tempfile::TempDir, member: &str| {
+            WavedashConfig::with_overrides(
+                &dir.path().join(member).join("wavedash.toml"),
+                overrides(&[]),
+            )
+        };
+
+        // Someone else's broken wavedash.toml above the project isn't a root.
+        let unrelated = workspace_dir("game_id = \n", &[("game", "game_id = \"g\"")]);
+        assert_eq!(load(&unrelated, "game").unwrap().game_id().unwrap(), "g");
+
+        // A root with a member gone missing still gives the rest its settings,
+        // and only --all and --package are told about it.
+        let stale = workspace_dir(
+            "exclude = [\"*.psd\"]\n\n[workspace]\nmembers = [\"games/*\", \"gone\"]\n",
+            &[("games/puzzle", "game_id = \"p\"")],
+        );
+        assert_eq!(load(&stale, "games/puzzle").unwrap().excludes(), ["*.psd"]);
+        let err = find_workspace(&stale.path().join("games/puzzle/wavedash.toml")).unwrap_err();
+        assert!(err.to_string().contains("member gone"), "{}", err);
+
+        // But a root that says it's one and doesn't parse is an error.
+        let broken = workspace_dir(
+            "[workspace]\nmembers = [\"games/*\"\n",
+            &[("games/puzzle", "game_id = \"p\"")],
+        );
+        assert!(load(&broken, "games/puzzle").is_err());
+
+        assert!(declares_workspace("  [ workspace ]\nmembers = []\n"));
+        assert!(declares_workspace("workspace.members = [\"a\"]\n"));
+        assert!(declares_workspace("workspace = { members = [] }\n"));
+        assert!(!declares_workspace("[workspaces]\n"));
+        assert!(!declares_workspace("game_id = \"workspace\"\n"));
+    }
+
     #[test]
     fn upload_source_labels_are_the_ones_the_api_accepts() {
         assert_eq!(UploadSource::default(), UploadSource::Cli);
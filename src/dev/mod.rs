@@ -6,8 +6,11 @@ use serde::Deserialize;
 use walkdir::WalkDir;
 
 use crate::auth::{generate_state, AuthManager};
+use crate::build_step::run_build_step;
 use crate::config::{self, EngineKind, UploadSource, WavedashConfig};
 use crate::file_staging::FileStaging;
+use crate::git::GitProvenance;
+use crate::object_headers::HeaderRules;
 
 mod server;
 
@@ -24,6 +27,7 @@ async fn create_local_build(
     engine_version: Option<&str>,
     entrypoint: Option<&str>,
     upload_source: UploadSource,
+    git: Option<&GitProvenance>,
     api_key: &str,
 ) -> Result<CreateLocalBuildResponse> {
     let client = config::create_http_client()?;
@@ -42,6 +46,12 @@ async fn create_local_build(
     if let Some(ep) = entrypoint {
         request_body["entrypoint"] = serde_json::json!(ep);
     }
+    if let Some(git) = git {
+        request_body["git"] = serde_json::json!(git);
+        if let Some(subject) = &git.subject {
+            request_body["buildMessage"] = serde_json::json!(subject);
+        }
+    }
 
     let response = client
         .post(&url)
@@ -55,12 +65,13 @@ async fn create_local_build(
     Ok(response.json().await?)
 }
 
-const DEFAULT_CONFIG: &str = "./wavedash.toml";
+pub(crate) const DEFAULT_CONFIG: &str = "./wavedash.toml";
 
 pub async fn handle_dev(
     config_path: Option<PathBuf>,
     verbose: bool,
     no_open: bool,
+    no_build: bool,
     upload_source: UploadSource,
 ) -> Result<()> {
     let auth_manager = AuthManager::new()?;
@@ -80,6 +91,9 @@ pub async fn handle_dev(
     // runnable from overrides alone like every other command.
     let config_path = config_path.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG));
     let wavedash_config = WavedashConfig::load(&config_path)?;
+    if !no_build {
+        run_build_step(&wavedash_config, &config_path, false)?;
+    }
     let config_dir = config_parent_dir(&config_path)?;
     let upload_dir = config_dir.join(wavedash_config.upload_dir()?);
     if !upload_dir.exists() || !upload_dir.is_dir() {
@@ -101,6 +115,7 @@ pub async fn handle_dev(
     let entrypoint = wavedash_config.entrypoint()?.map(String::from);
     let api_host = config::get("api_host")?;
     let client = config::create_http_client()?;
+    let headers = HeaderRules::new(wavedash_config.header_overrides())?;
 
     let mut entry = String::new();
     let mut engine_entry = None;
@@ -137,6 +152,7 @@ pub async fn handle_dev(
         wavedash_config.engine_version()?,
         entrypoint.as_deref(),
         upload_source,
+        GitProvenance::detect(&config_dir).as_ref(),
         &api_key,
     )
     .await?;
@@ -205,6 +221,7 @@ pub async fn handle_dev(
             client,
             engine_entry,
             jwks: tokio::sync::OnceCell::new(),
+            headers,
         },
     )
     .await
@@ -23,6 +23,8 @@ use axum::{
 use serde::Deserialize;
 use tokio::net::TcpListener;
 
+use crate::object_headers::{HeaderRules, ObjectHeaders};
+
 const SDK_JS_VERSION: &str = include_str!("sdk-js-version");
 
 /// Classic parser-blocking IIFE (auto-runs setupWavedashSDK): the only way
@@ -66,6 +68,9 @@ pub struct ServeConfig {
     pub engine_entry: Option<EngineEntry>,
     /// Backend public keys (/.well-known/jwks.json), fetched once on demand.
     pub jwks: tokio::sync::OnceCell<jsonwebtoken::jwk::JwkSet>,
+    /// Same Content-Type/Content-Encoding the uploader stores, overrides and
+    /// all, so a file that loads here loads the same way once pushed.
+    pub headers: HeaderRules,
 }
 
 impl ServeConfig {
@@ -539,14 +544,18 @@ fn serve_file(cfg: &ServeConfig, url_path: &str, config: Option<&str>) -> Respon
         return respond(StatusCode::NOT_FOUND, TEXT, None, "Not Found");
     };
 
-    let (content_type, encoding) = content_type_and_encoding(url_path);
+    let ObjectHeaders {
+        content_type,
+        content_encoding: encoding,
+        ..
+    } = cfg.headers.headers_for(url_path.trim_start_matches('/'));
     if let Some(config) = config {
         if encoding.is_none() && content_type.starts_with("text/html") {
             let injected = inject_sdk(&String::from_utf8_lossy(&bytes), config);
             return respond(StatusCode::OK, HTML, None, injected);
         }
     }
-    respond(StatusCode::OK, content_type, encoding, bytes)
+    respond(StatusCode::OK, &content_type, encoding.as_deref(), bytes)
 }
 
 /// Every response carries COOP+COEP (cross-origin isolation → SharedArrayBuffer,
@@ -554,7 +563,7 @@ fn serve_file(cfg: &ServeConfig, url_path: &str, config: Option<&str>) -> Respon
 fn respond(
     status: StatusCode,
     content_type: &str,
-    encoding: Option<&'static str>,
+    encoding: Option<&str>,
     body: impl Into<Body>,
 ) -> Response {
     let mut builder = Response::builder()
@@ -644,34 +653,6 @@ fn resolve_path(upload_dir: &Path, url_path: &str) -> Option<PathBuf> {
     }
 }
 
-/// Unity/Godot emit `.gz`/`.br` files directly; strip the suffix to derive the
-/// real type and let the browser decompress transparently.
-fn content_type_and_encoding(path: &str) -> (&'static str, Option<&'static str>) {
-    if let Some(stripped) = path.strip_suffix(".gz") {
-        return (lookup_content_type(stripped), Some("gzip"));
-    }
-    if let Some(stripped) = path.strip_suffix(".br") {
-        return (lookup_content_type(stripped), Some("br"));
-    }
-    (lookup_content_type(path), None)
-}
-
-/// mime_guess plus the engine bundle formats it doesn't know. Unity's
-/// `.symbols.json` must be octet-stream (the loader fetches it as raw bytes).
-fn lookup_content_type(path: &str) -> &'static str {
-    let lower = path.to_ascii_lowercase();
-    if lower.ends_with(".symbols.json") {
-        return "application/octet-stream";
-    }
-    match lower.rsplit_once('.').map(|(_, ext)| ext) {
-        Some("unityweb" | "data" | "mem" | "bundle" | "pck") => "application/octet-stream",
-        Some("unity3d") => "application/vnd.unity",
-        _ => mime_guess::from_path(&lower)
-            .first_raw()
-            .unwrap_or("application/octet-stream"),
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,319 @@
+//! `wavedash build diff`: what changed between two builds, or between a build
+//! and the upload directory as it stands.
+//!
+//! Builds are compared through the manifests the server stored when they were
+//! pushed, so nothing is downloaded. The local side is scanned and hashed
+//! exactly the way `build push` would, excludes included. `--json` is meant for
+//! drafting release notes; `--max-delta` and `--max-file-delta` turn an
+//! unexpectedly large change into a failing exit code for CI.
+
+use anyhow::Result;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use super::build_info::fetch_builds;
+use super::uploader::{format_bytes, scan_directory};
+use crate::auth::require_api_key;
+use crate::config::{self, WavedashConfig};
+use crate::exclude::ExcludeRules;
+use crate::manifest::{BuildManifest, ManifestDiff};
+
+/// Accepted in place of a build ID: whichever build players currently get.
+pub const LIVE_BUILD: &str = "live";
+
+pub struct BuildDiffArgs<'a> {
+    pub game_id: &'a str,
+    pub config_path: PathBuf,
+    /// A build ID or [`LIVE_BUILD`].
+    pub from: &'a str,
+    /// A build ID or [`LIVE_BUILD`]; `None` compares against the upload
+    /// directory named in wavedash.toml.
+    pub to: Option<&'a str>,
+    pub verbose: bool,
+    pub json: bool,
+    /// Fail when the whole build grows or shrinks by more than this.
+    pub max_delta: Option<u64>,
+    /// Fail when any one file is added, removed or changes by more than this.
+    pub max_file_delta: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DiffReport {
+    from: String,
+    to: String,
+    from_bytes: u64,
+    to_bytes: u64,
+    size_delta: i64,
+    #[serde(flatten)]
+    diff: ManifestDiff,
+    /// The changes over `--max-delta` or `--max-file-delta`.
+    flagged: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuildManifestResponse {
+    #[serde(default)]
+    manifest: Option<BuildManifest>,
+}
+
+async fn fetch_build_manifest(
+    game_id: &str,
+    build_id: &str,
+    api_key: &str,
+) -> Result<BuildManifest> {
+    let client = config::create_http_client()?;
+    let api_host = config::get("api_host")?;
+    let url = format!(
+        "{}/api/games/{}/builds/{}/manifest",
+        api_host, game_id, build_id
+    );
+
+    let response = client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await?;
+    let response = config::check_api_response(response).await?;
+    let body: BuildManifestResponse = response.json().await?;
+    body.manifest.ok_or_else(|| {
+        anyhow::anyhow!(
+            "Build {} was uploaded before build manifests existed, so it can't be diffed",
+            build_id
+        )
+    })
+}
+
+/// The manifest for `build`, and how to label it in the report.
+async fn remote_side(game_id: &str, build: &str, api_key: &str) -> Result<(String, BuildManifest)> {
+    if build != LIVE_BUILD {
+        let manifest = fetch_build_manifest(game_id, build, api_key).await?;
+        return Ok((format!("build {}", build), manifest));
+    }
+    let live = fetch_builds(game_id, None, api_key)
+        .await?
+        .builds
+        .into_iter()
+        .find(|b| b.live)
+        .ok_or_else(|| anyhow::anyhow!("Game {} has no live build yet", game_id))?;
+    let manifest = fetch_build_manifest(game_id, &live.id, api_key).await?;
+    Ok((format!("build {} (live)", live.id), manifest))
+}
+
+fn local_side(config_path: PathBuf, verbose: bool) -> Result<(String, BuildManifest)> {
+    let wavedash_config = WavedashConfig::load(&config_path)?;
+    let (config_dir, upload_dir) = super::resolve_upload_dir(&config_path, &wavedash_config)?;
+    let rules = ExcludeRules::load(&upload_dir, config_dir, wavedash_config.excludes())?;
+    let scan = scan_directory(&upload_dir, &rules, wavedash_config.symlinks())?;
+    scan.warn_about_links();
+    if verbose {
+        eprintln!(
+            "Hashing {} files ({}) in {}",
+            scan.files.len(),
+            format_bytes(scan.total_bytes),
+            upload_dir.display()
+        );
+    }
+    let manifest = BuildManifest::from_scan(&scan.files)?;
+    Ok((upload_dir.display().to_string(), manifest))
+}
+
+pub async fn handle_build_diff(args: BuildDiffArgs<'_>) -> Result<()> {
+    let BuildDiffArgs {
+        game_id,
+        config_path,
+        from,
+        to,
+        verbose,
+        json,
+        max_delta,
+        max_file_delta,
+    } = args;
+
+    let api_key = require_api_key()?;
+    let (from_label, old) = remote_side(game_id, from, &api_key).await?;
+    let (to_label, new) = match to {
+        Some(build) => remote_side(game_id, build, &api_key).await?,
+        None => local_side(config_path, verbose)?,
+    };
+
+    let diff = old.diff(&new);
+    let report = DiffReport {
+        from: from_label,
+        to: to_label,
+        from_bytes: total_size(&old),
+        to_bytes: total_size(&new),
+        size_delta: diff.size_delta(),
+        flagged: flag_large_deltas(&diff, max_delta, max_file_delta),
+        diff,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+
+    if !report.flagged.is_empty() {
+        anyhow::bail!(
+            "The change is larger than allowed:\n  {}",
+            report.flagged.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+fn total_size(manifest: &BuildManifest) -> u64 {
+    manifest
+        .files
+        .iter()
+        .fold(0u64, |total, f| total.saturating_add(f.size))
+}
+
+/// `+1.20 MB`, `-300 B`; unsigned zero.
+fn format_delta(delta: i64) -> String {
+    let magnitude = format_bytes(delta.unsigned_abs());
+    match delta.signum() {
+        1 => format!("+{}", magnitude),
+        -1 => format!("-{}", magnitude),
+        _ => magnitude,
+    }
+}
+
+/// Growth and shrinkage both count: a build that lost half its size is as
+/// suspicious as one that doubled.
+fn flag_large_deltas(
+    diff: &ManifestDiff,
+    max_delta: Option<u64>,
+    max_file_delta: Option<u64>,
+) -> Vec<String> {
+    let mut flagged = Vec::new();
+    if let Some(max) = max_delta {
+        let delta = diff.size_delta();
+        if delta.unsigned_abs() > max {
+            flagged.push(format!(
+                "total size changed by {}, more than --max-delta {}",
+                format_delta(delta),
+                format_bytes(max)
+            ));
+        }
+    }
+    if let Some(max) = max_file_delta {
+        let changes = diff
+            .added
+            .iter()
+            .map(|f| (f.path.as_str(), f.size as i64))
+            .chain(
+                diff.removed
+                    .iter()
+                    .map(|f| (f.path.as_str(), -(f.size as i64))),
+            )
+            .chain(diff.modified.iter().map(|f| (f.path.as_str(), f.delta)));
+        for (path, delta) in changes {
+            if delta.unsigned_abs() > max {
+                flagged.push(format!(
+                    "{} changed by {}, more than --max-file-delta {}",
+                    path,
+                    format_delta(delta),
+                    format_bytes(max)
+                ));
+            }
+        }
+    }
+    flagged
+}
+
+fn print_report(report: &DiffReport) {
+    println!("Comparing {} → {}", report.from, report.to);
+    let diff = &report.diff;
+    if diff.is_empty() {
+        println!("No differences ({} files).", diff.unchanged);
+        return;
+    }
+
+    println!();
+    for file in &diff.added {
+        println!(
+            "{} {}  {}",
+            "+".green().bold(),
+            file.path,
+            format_bytes(file.size)
+        );
+    }
+    for file in &diff.removed {
+        println!(
+            "{} {}  {}",
+            "-".red().bold(),
+            file.path,
+            format_bytes(file.size)
+        );
+    }
+    for file in &diff.modified {
+        println!(
+            "{} {}  {} → {} ({})",
+            "~".yellow().bold(),
+            file.path,
+            format_bytes(file.old_size),
+            format_bytes(file.new_size),
+            format_delta(file.delta)
+        );
+    }
+    println!();
+    println!(
+        "{} added, {} removed, {} modified, {} unchanged. Total {} → {} ({}).",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.modified.len(),
+        diff.unchanged,
+        format_bytes(report.from_bytes),
+        format_bytes(report.to_bytes),
+        format_delta(report.size_delta)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ManifestFile;
+
+    fn manifest(files: &[(&str, u64, &str)]) -> BuildManifest {
+        BuildManifest {
+            files: files
+                .iter()
+                .map(|(path, size, sha256)| ManifestFile {
+                    path: path.to_string(),
+                    size: *size,
+                    sha256: sha256.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn large_deltas_are_flagged_in_either_direction() {
+        let old = manifest(&[("game.pck", 10_000, "a"), ("intro.webm", 5_000, "b")]);
+        let new = manifest(&[("game.pck", 10_100, "c"), ("readme.txt", 10, "d")]);
+        let diff = old.diff(&new);
+        assert_eq!(diff.size_delta(), 100 - 5_000 + 10);
+
+        assert!(flag_large_deltas(&diff, None, None).is_empty());
+        assert!(flag_large_deltas(&diff, Some(5_000), Some(5_000)).is_empty());
+
+        let flagged = flag_large_deltas(&diff, Some(1_000), Some(1_000));
+        assert_eq!(
+            flagged,
+            vec![
+                "total size changed by -4.78 KB, more than --max-delta 1000 B",
+                "intro.webm changed by -4.88 KB, more than --max-file-delta 1000 B",
+            ]
+        );
+    }
+
+    #[test]
+    fn deltas_carry_their_sign() {
+        assert_eq!(format_delta(2048), "+2.00 KB");
+        assert_eq!(format_delta(-300), "-300 B");
+        assert_eq!(format_delta(0), "0 B");
+    }
+}
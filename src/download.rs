@@ -0,0 +1,474 @@
+//! `wavedash build download`: mirroring an uploaded build back to disk, byte
+//! for byte, so a bug reported against a release can be chased in exactly the
+//! files that player was served rather than in a rebuild from source.
+
+use anyhow::{Context, Result};
+use futures::{stream, StreamExt, TryStreamExt};
+use opendal::layers::RetryLayer;
+use opendal::{EntryMode, Operator};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::AsyncWriteExt;
+
+use super::uploader::{
+    build_operator, format_bytes, ProgressReporter, DEFAULT_CONCURRENCY, DEFAULT_MAX_RETRIES,
+};
+use super::verify::MANIFEST_OBJECT;
+use super::{r2_config, R2Credentials};
+use crate::auth::require_api_key;
+use crate::config::{self, StorageTarget};
+use crate::manifest::{hash_file, BuildManifest, ManifestFile};
+
+/// Large objects are fetched in ranged requests of this size, so a dropped
+/// connection late in a multi-GB `.pck` only repeats the range it was in.
+const READ_CHUNK_SIZE: usize = 8 * 1024 * 1024; // 8 MiB
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadCredsResponse {
+    r2_key_prefix: String,
+    bucket_name: String,
+    credentials: R2Credentials,
+    endpoint: String,
+    /// `None` for builds uploaded before manifests existed.
+    #[serde(default)]
+    manifest: Option<BuildManifest>,
+    /// The build this one was pushed against. Its prefix only holds what the
+    /// push uploaded; every other file is a blob stored with the base, or
+    /// with the base's own base.
+    #[serde(default)]
+    base_build_id: Option<String>,
+}
+
+/// Read-only temporary credentials scoped to one build's key prefix.
+async fn get_read_credentials(
+    game_id: &str,
+    build_id: &str,
+    api_key: &str,
+) -> Result<ReadCredsResponse> {
+    let client = config::create_http_client()?;
+    let api_host = config::get("api_host")?;
+
+    let url = format!(
+        "{}/api/games/{}/builds/{}/create-read-r2-creds",
+        api_host, game_id, build_id
+    );
+
+    let response = client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Content-Type", "application/json")
+        .send()
+        .await?;
+
+    let response = config::check_api_response(response).await?;
+    Ok(response.json().await?)
+}
+
+/// One object to download: where it's stored, and where it goes.
+struct RemoteObject {
+    /// The build whose storage holds it, which for a file an incremental push
+    /// didn't upload is one of its bases.
+    operator: Operator,
+    key: String,
+    /// The file's path inside the build being downloaded. For a file stored
+    /// with the build itself, that's the key without the build prefix.
+    path: String,
+    size: u64,
+}
+
+/// A build's storage, opened with its own read credentials.
+struct StoredBuild {
+    creds: ReadCredsResponse,
+    prefix: String,
+    /// Every object under the prefix, by path.
+    objects: Vec<RemoteObject>,
+}
+
+async fn open_build(
+    game_id: &str,
+    build_id: &str,
+    api_key: &str,
+    storage: &StorageTarget,
+) -> Result<StoredBuild> {
+    let creds = get_read_credentials(game_id, build_id, api_key).await?;
+    let operator = build_operator(
+        &r2_config(&creds.credentials, &creds.endpoint),
+        &creds.bucket_name,
+        storage,
+    )?
+    .layer(
+        RetryLayer::new()
+            .with_jitter()
+            .with_max_times(DEFAULT_MAX_RETRIES as usize),
+    );
+    let prefix = format!("{}/", creds.r2_key_prefix.trim_end_matches('/'));
+    let objects = list_objects(&operator, &prefix).await?;
+    Ok(StoredBuild {
+        creds,
+        prefix,
+        objects,
+    })
+}
+
+pub struct BuildDownloadArgs<'a> {
+    pub game_id: &'a str,
+    pub build_id: &'a str,
+    pub out: &'a Path,
+    pub verbose: bool,
+}
+
+pub async fn handle_build_download(args: BuildDownloadArgs<'_>) -> Result<()> {
+    let BuildDownloadArgs {
+        game_id,
+        build_id,
+        out,
+        verbose,
+    } = args;
+    prepare_out_dir(out)?;
+
+    let api_key = require_api_key()?;
+    // Read from wherever a push with the same environment would have written.
+    let storage = config::storage_target_from_env()?.unwrap_or_default();
+    let StoredBuild {
+        creds,
+        prefix,
+        mut objects,
+    } = open_build(game_id, build_id, &api_key, &storage).await?;
+    let own = objects.len();
+    if let Some(manifest) = &creds.manifest {
+        let inherited = inherited_objects(
+            game_id, build_id, manifest, &objects, &creds, &api_key, &storage,
+        )
+        .await?;
+        objects.extend(inherited);
+    }
+    if objects.is_empty() {
+        anyhow::bail!("Build {} has no files in storage", build_id);
+    }
+    let total_bytes = objects
+        .iter()
+        .fold(0u64, |total, o| total.saturating_add(o.size));
+
+    if verbose {
+        println!(
+            "Downloading {} files ({} total) from bucket '{}' with prefix '{}'",
+            objects.len(),
+            format_bytes(total_bytes),
+            creds.bucket_name,
+            prefix
+        );
+        if objects.len() > own {
+            println!(
+                "{} of them are unchanged files stored with earlier builds",
+                objects.len() - own
+            );
+        }
+    }
+
+    let progress =
+        ProgressReporter::new(total_bytes, "Downloading", "Build downloaded successfully");
+    let downloaded = AtomicU64::new(0);
+    stream::iter(objects.iter().map(|object| {
+        let (progress, downloaded) = (&progress, &downloaded);
+        async move {
+            let path = local_path_for(out, &object.path)?;
+            download_object(object, &path, progress, downloaded).await
+        }
+    }))
+    .buffer_unordered(DEFAULT_CONCURRENCY)
+    .try_collect::<Vec<_>>()
+    .await?;
+    progress.finish();
+
+    match &creds.manifest {
+        Some(manifest) => {
+            let paths: Vec<&str> = objects.iter().map(|o| o.path.as_str()).collect();
+            let problems = verify_download(out, manifest, &paths)?;
+            if !problems.is_empty() {
+                for problem in &problems {
+                    eprintln!("  {}", problem);
+                }
+                anyhow::bail!(
+                    "{} of the downloaded files don't match build {}'s manifest",
+                    problems.len(),
+                    build_id
+                );
+            }
+            println!(
+                "✓ Verified {} files against the build's manifest",
+                manifest.files.len()
+            );
+        }
+        None => println!(
+            "Build {} has no manifest, so the files weren't verified.",
+            build_id
+        ),
+    }
+
+    println!("Saved build {} to {}", build_id, out.display());
+    Ok(())
+}
+
+/// The files `manifest` lists that aren't under the build's own prefix, found
+/// by walking its base builds. A push references the base's blob for any
+/// content the base held under any path, so each is matched by hash against
+/// the base's manifest, not by path. What no base holds is left out, for
+/// verification to report as missing.
+async fn inherited_objects(
+    game_id: &str,
+    build_id: &str,
+    manifest: &BuildManifest,
+    own: &[RemoteObject],
+    creds: &ReadCredsResponse,
+    api_key: &str,
+    storage: &StorageTarget,
+) -> Result<Vec<RemoteObject>> {
+    let stored: HashSet<&str> = own.iter().map(|o| o.path.as_str()).collect();
+    let mut pending: Vec<&ManifestFile> = manifest
+        .files
+        .iter()
+        .filter(|f| !stored.contains(f.path.as_str()))
+        .collect();
+
+    let mut inherited = Vec::new();
+    let mut visited = HashSet::from([build_id.to_string()]);
+    let mut next = creds.base_build_id.clone();
+    while let Some(base_id) = next.take() {
+        if pending.is_empty() {
+            break;
+        }
+        if !visited.insert(base_id.clone()) {
+            anyhow::bail!("Build {}'s base builds refer back to {}", build_id, base_id);
+        }
+        let base = open_build(game_id, &base_id, api_key, storage)
+            .await
+            .with_context(|| format!("Failed to open base build {}", base_id))?;
+        // A build without a manifest can't have been pushed against, so the
+        // chain can't go on past one.
+        let Some(base_manifest) = &base.creds.manifest else {
+            break;
+        };
+        let stored: HashMap<&str, &RemoteObject> =
+            base.objects.iter().map(|o| (o.path.as_str(), o)).collect();
+        let by_hash: HashMap<&str, &RemoteObject> = base_manifest
+            .files
+            .iter()
+            .filter_map(|f| Some((f.sha256.as_str(), *stored.get(f.path.as_str())?)))
+            .collect();
+        pending.retain(|file| match by_hash.get(file.sha256.as_str()) {
+            Some(object) => {
+                inherited.push(RemoteObject {
+                    operator: object.operator.clone(),
+                    key: object.key.clone(),
+                    path: file.path.clone(),
+                    size: object.size,
+                });
+                false
+            }
+            None => true,
+        });
+        next = base.creds.base_build_id.clone();
+    }
+    Ok(inherited)
+}
+
+/// Files land straight in `out`, so it has to be new or empty: anything
+/// already there would be indistinguishable from the build.
+fn prepare_out_dir(out: &Path) -> Result<()> {
+    if out.exists() {
+        if !out.is_dir() {
+            anyhow::bail!("{} exists and isn't a directory", out.display());
+        }
+        let mut entries =
+            std::fs::read_dir(out).with_context(|| format!("Failed to read {}", out.display()))?;
+        if entries.next().is_some() {
+            anyhow::bail!(
+                "{} isn't empty. Pass a new or empty directory to --out.",
+                out.display()
+            );
+        }
+    }
+    std::fs::create_dir_all(out).with_context(|| format!("Failed to create {}", out.display()))
+}
+
+async fn list_objects(operator: &Operator, prefix: &str) -> Result<Vec<RemoteObject>> {
+    let entries = operator
+        .list_with(prefix)
+        .recursive(true)
+        .await
+        .with_context(|| format!("Failed to list the build's files under '{}'", prefix))?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.metadata().mode() == EntryMode::FILE)
+        .filter_map(|entry| {
+            let path = entry.path().strip_prefix(prefix)?.to_string();
+            // Stored with the build, but not one of its files.
+            if path == MANIFEST_OBJECT {
+                return None;
+            }
+            Some(RemoteObject {
+                operator: operator.clone(),
+                key: entry.path().to_string(),
+                size: entry.metadata().content_length(),
+                path,
+            })
+        })
+        .collect())
+}
+
+/// Where the file at `path` inside the build goes under `out`. Keys come from
+/// the bucket, not from us, so one that would land outside `out` is refused
+/// rather than trusted.
+fn local_path_for(out: &Path, path: &str) -> Result<PathBuf> {
+    let mut local = out.to_path_buf();
+    for component in path.split('/') {
+        if component.is_empty()
+            || component == "."
+            || component == ".."
+            || component.contains('\\')
+            || Path::new(component).has_root()
+        {
+            anyhow::bail!("Refusing to download '{}': not a plain relative path", path);
+        }
+        local.push(component);
+    }
+    Ok(local)
+}
+
+async fn download_object(
+    object: &RemoteObject,
+    path: &Path,
+    progress: &ProgressReporter,
+    downloaded: &AtomicU64,
+) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let mut bytes = object
+        .operator
+        .reader_with(&object.key)
+        .chunk(READ_CHUNK_SIZE)
+        .await
+        .with_context(|| format!("Failed to open {}", object.key))?
+        .into_bytes_stream(..)
+        .await
+        .with_context(|| format!("Failed to read {}", object.key))?;
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+
+    while let Some(chunk) = bytes
+        .try_next()
+        .await
+        .with_context(|| format!("Failed to read {}", object.key))?
+    {
+        file.write_all(&chunk)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        let len = chunk.len() as u64;
+        progress.update(downloaded.fetch_add(len, Ordering::Relaxed) + len);
+    }
+    file.flush()
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Everything about the download that disagrees with `manifest`: files it
+/// lists that didn't arrive or arrived different, and files that arrived
+/// without being listed. Empty when the download is exactly the build.
+fn verify_download(
+    out: &Path,
+    manifest: &BuildManifest,
+    downloaded: &[&str],
+) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    for file in &manifest.files {
+        let local = local_path_for(out, &file.path)?;
+        if !local.is_file() {
+            problems.push(format!("{}: missing from the build's storage", file.path));
+            continue;
+        }
+        let (size, sha256) = hash_file(&local)?;
+        if size != file.size {
+            problems.push(format!(
+                "{}: {} bytes, the manifest says {}",
+                file.path, size, file.size
+            ));
+        } else if sha256 != file.sha256 {
+            problems.push(format!("{}: contents differ from the manifest", file.path));
+        }
+    }
+
+    let listed: HashSet<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+    for path in downloaded {
+        if !listed.contains(path) {
+            problems.push(format!("{}: not in the manifest", path));
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_that_would_escape_the_out_dir_are_refused() {
+        let out = Path::new("/tmp/out");
+        assert_eq!(
+            local_path_for(out, "Build/game.wasm").expect("plain path"),
+            out.join("Build").join("game.wasm")
+        );
+        assert!(local_path_for(out, "../etc/passwd").is_err());
+        assert!(local_path_for(out, "Build/../../x").is_err());
+        assert!(local_path_for(out, "/abs").is_err());
+        assert!(local_path_for(out, "a//b").is_err());
+    }
+
+    #[test]
+    fn verification_reports_missing_changed_and_unlisted_files() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let out = dir.path();
+        std::fs::write(out.join("index.html"), "hello").expect("write");
+        std::fs::write(out.join("game.js"), "world").expect("write");
+        std::fs::write(out.join("extra.txt"), "?").expect("write");
+
+        let (_, hello) = hash_file(&out.join("index.html")).expect("hash");
+        let manifest = BuildManifest {
+            files: vec![
+                ManifestFile {
+                    path: "index.html".to_string(),
+                    size: 5,
+                    sha256: hello.clone(),
+                },
+                ManifestFile {
+                    path: "game.js".to_string(),
+                    size: 5,
+                    sha256: hello.clone(),
+                },
+                ManifestFile {
+                    path: "game.pck".to_string(),
+                    size: 1,
+                    sha256: hello,
+                },
+            ],
+        };
+
+        let problems = verify_download(out, &manifest, &["index.html", "game.js", "extra.txt"])
+            .expect("verify");
+        assert_eq!(
+            problems,
+            vec![
+                "game.js: contents differ from the manifest",
+                "game.pck: missing from the build's storage",
+                "extra.txt: not in the manifest",
+            ]
+        );
+    }
+}
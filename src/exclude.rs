@@ -0,0 +1,168 @@
+//! Which files under `upload_dir` stay out of a build.
+//!
+//! Rules are gitignore-style patterns matched against paths relative to
+//! `upload_dir`, layered lowest-precedence first: a built-in set of junk no game
+//! wants shipped, then `exclude = [...]` from wavedash.toml, then a
+//! `.wavedashignore` next to wavedash.toml. As in git, the last matching rule
+//! wins, so `!*.map` in `.wavedashignore` brings source maps back.
+
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder, Glob};
+use ignore::Match;
+use std::path::{Path, PathBuf};
+
+pub const IGNORE_FILE_NAME: &str = ".wavedashignore";
+
+/// Excluded no matter what the project says, unless it negates them.
+/// Editor and OS droppings, VCS metadata, engine import caches, and source maps
+/// — which would publish the game's sources alongside it.
+const DEFAULT_EXCLUDES: &[&str] = &[
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+    "*.swp",
+    "*~",
+    ".git/",
+    ".svn/",
+    ".hg/",
+    ".import/",
+    ".godot/",
+    "*.map",
+    IGNORE_FILE_NAME,
+];
+
+/// Label for a rule's origin when it wasn't read from a file.
+const DEFAULT_SOURCE: &str = "built-in default";
+
+pub struct ExcludeRules {
+    matcher: Gitignore,
+}
+
+impl ExcludeRules {
+    /// Rules for a push of `upload_dir`, reading `.wavedashignore` from
+    /// `config_dir` when there is one.
+    pub fn load(upload_dir: &Path, config_dir: &Path, config_excludes: &[String]) -> Result<Self> {
+        let ignore_file = config_dir.join(IGNORE_FILE_NAME);
+        let ignore_file = ignore_file.is_file().then_some(ignore_file);
+        Self::build(upload_dir, config_excludes, ignore_file.as_deref())
+    }
+
+    fn build(
+        upload_dir: &Path,
+        config_excludes: &[String],
+        ignore_file: Option<&Path>,
+    ) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(upload_dir);
+        for pattern in DEFAULT_EXCLUDES {
+            builder
+                .add_line(None, pattern)
+                .expect("built-in exclude patterns are valid globs");
+        }
+        let config_source = PathBuf::from("wavedash.toml");
+        for pattern in config_excludes {
+            builder
+                .add_line(Some(config_source.clone()), pattern)
+                .with_context(|| {
+                    format!("Invalid exclude pattern '{}' in wavedash.toml", pattern)
+                })?;
+        }
+        if let Some(path) = ignore_file {
+            if let Some(err) = builder.add(path) {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        }
+        let matcher = builder
+            .build()
+            .context("Failed to compile exclude patterns")?;
+        Ok(Self { matcher })
+    }
+
+    /// The rule that excludes `relative`, described for `--verbose`, or `None`
+    /// when the path is uploaded. Directories are checked on their own, so a
+    /// caller walking the tree can skip an excluded directory without visiting
+    /// what's inside it.
+    pub fn matched(&self, relative: &Path, is_dir: bool) -> Option<String> {
+        describe(self.matcher.matched(relative, is_dir))
+    }
+
+    /// Like [`matched`](Self::matched) for a file, but also checks every
+    /// directory above it. For files that don't come from a walk, which would
+    /// have pruned an excluded directory before reaching them.
+    pub fn matched_file(&self, relative: &Path) -> Option<String> {
+        if relative.has_root() {
+            return None;
+        }
+        describe(self.matcher.matched_path_or_any_parents(relative, false))
+    }
+
+    /// True when `relative`, or any directory above it, is excluded. For
+    /// checking a single named file, like the entrypoint, without a walk.
+    pub fn excludes_path(&self, relative: &Path) -> bool {
+        self.matched_file(relative).is_some()
+    }
+}
+
+/// The rule behind an exclusion, and where it came from.
+fn describe(matched: Match<&Glob>) -> Option<String> {
+    match matched {
+        Match::Ignore(glob) => Some(format!(
+            "'{}' from {}",
+            glob.original(),
+            glob.from()
+                .map(|from| from.display().to_string())
+                .unwrap_or_else(|| DEFAULT_SOURCE.to_string())
+        )),
+        Match::None | Match::Whitelist(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(config_excludes: &[&str], ignore_file: Option<&str>) -> ExcludeRules {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = ignore_file.map(|contents| {
+            let path = dir.path().join(IGNORE_FILE_NAME);
+            std::fs::write(&path, contents).expect("write ignore file");
+            path
+        });
+        let excludes: Vec<String> = config_excludes.iter().map(|s| s.to_string()).collect();
+        ExcludeRules::build(Path::new("/build"), &excludes, path.as_deref()).expect("rules")
+    }
+
+    #[test]
+    fn defaults_skip_junk_and_say_so() {
+        let rules = rules(&[], None);
+        assert_eq!(
+            rules
+                .matched(Path::new("assets/.DS_Store"), false)
+                .as_deref(),
+            Some("'.DS_Store' from built-in default")
+        );
+        assert!(rules.matched(Path::new(".godot"), true).is_some());
+        assert!(rules.matched(Path::new("index.html"), false).is_none());
+    }
+
+    #[test]
+    fn each_rule_reports_the_file_it_came_from() {
+        let rules = rules(&["*.psd"], Some("# art sources\nraw/\n"));
+        assert_eq!(
+            rules.matched(Path::new("ui/logo.psd"), false).as_deref(),
+            Some("'*.psd' from wavedash.toml")
+        );
+        let from_file = rules.matched(Path::new("raw"), true).expect("excluded");
+        assert!(from_file.starts_with("'raw/' from "), "{}", from_file);
+        assert!(from_file.ends_with(IGNORE_FILE_NAME), "{}", from_file);
+        // A directory-only pattern leaves a file of the same name alone.
+        assert!(rules.matched(Path::new("raw"), false).is_none());
+    }
+
+    #[test]
+    fn the_ignore_file_can_bring_a_default_back() {
+        let rules = rules(&[], Some("!*.map\n"));
+        assert!(rules.matched(Path::new("game.js.map"), false).is_none());
+        assert!(rules.excludes_path(Path::new(".git/config")));
+        assert!(!rules.excludes_path(Path::new("game.js.map")));
+    }
+}
@@ -0,0 +1,397 @@
+//! `wavedash export godot`: a headless Web export of the Godot project next to
+//! wavedash.toml into `upload_dir`, so a CI job (or a `[build]` command) is one
+//! `wavedash` call rather than a script that finds Godot and knows its flags.
+//!
+//! The preset comes from the project's `export_presets.cfg`, the binary from
+//! `GODOT_BIN` or `PATH`, and the binary has to be the version `[godot]` names:
+//! a build exported by one Godot and booted by another's runtime is the kind of
+//! breakage that only shows up on wavedash.com.
+
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use crate::config::{self, EngineKind, WavedashConfig};
+
+/// Names the Godot binary to export with, ahead of searching `PATH`.
+pub const ENV_GODOT_BIN: &str = "GODOT_BIN";
+
+/// What the binary is called on `PATH`, in the order they're tried. Distro
+/// packages and the Steam build don't agree.
+const GODOT_BINARY_NAMES: &[&str] = &["godot", "godot4", "godot3"];
+
+/// Export errors quoted in the failure message, beyond which the rest are left
+/// in the output above it.
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// One `[preset.N]` from `export_presets.cfg`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ExportPreset {
+    name: String,
+    platform: String,
+}
+
+impl ExportPreset {
+    /// Godot 4 calls the platform "Web"; Godot 3 called it "HTML5".
+    fn is_web(&self) -> bool {
+        self.platform == "Web" || self.platform == "HTML5"
+    }
+}
+
+pub async fn handle_export_godot(config_path: PathBuf, preset: Option<String>) -> Result<()> {
+    let wavedash_config = WavedashConfig::load(&config_path)?;
+    if wavedash_config.engine_type()? != Some(EngineKind::Godot) {
+        anyhow::bail!(
+            "{} has no [godot] section. Add one with the Godot version the game is made with.",
+            config_path.display()
+        );
+    }
+    let version = wavedash_config
+        .engine_version()?
+        .ok_or_else(|| anyhow::anyhow!("[godot] has no version"))?;
+    let config_dir = config_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Config file has no parent directory"))?;
+    let project_dir = if config_dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        config_dir
+    };
+    if !project_dir.join("project.godot").is_file() {
+        anyhow::bail!(
+            "No project.godot in {}. wavedash.toml has to sit next to the Godot project to export it.",
+            project_dir.display()
+        );
+    }
+
+    let presets_path = project_dir.join("export_presets.cfg");
+    let presets = std::fs::read_to_string(&presets_path).with_context(|| {
+        format!(
+            "Failed to read {}. Add a Web preset in Godot's Project > Export dialog first.",
+            presets_path.display()
+        )
+    })?;
+    let preset = pick_web_preset(&parse_export_presets(&presets), preset.as_deref())?;
+
+    let godot = find_godot()?;
+    let found = godot_version(&godot)?;
+    if !version_matches(version, &found) {
+        anyhow::bail!(
+            "{} is Godot {}, but [godot] in {} says {}. Point {} at a Godot {} binary.",
+            godot.display(),
+            found,
+            config_path.display(),
+            version,
+            ENV_GODOT_BIN,
+            version
+        );
+    }
+
+    let upload_dir = config_dir.join(wavedash_config.upload_dir()?);
+    std::fs::create_dir_all(&upload_dir)
+        .with_context(|| format!("Failed to create {}", upload_dir.display()))?;
+    let upload_dir = upload_dir.canonicalize()?;
+    let output = upload_dir.join("index.html");
+    // A stale index.html would pass for a successful export below.
+    let _ = std::fs::remove_file(&output);
+
+    println!(
+        "Exporting preset \"{}\" with Godot {} to {}",
+        preset.name,
+        found,
+        upload_dir.display()
+    );
+    let mut command = Command::new(&godot);
+    command.arg("--path").arg(project_dir);
+    if major_version(&found) >= Some(4) {
+        command.args(["--headless", "--export-release"]);
+    } else {
+        command.args(["--no-window", "--export"]);
+    }
+    command.arg(&preset.name).arg(&output);
+    let (success, errors) =
+        run_streaming(command).with_context(|| format!("Failed to run {}", godot.display()))?;
+
+    if !success || !output.is_file() {
+        let mut message = format!("Godot couldn't export preset \"{}\"", preset.name);
+        if errors.is_empty() {
+            message.push_str(". See its output above.");
+        } else {
+            message.push(':');
+            for error in errors.iter().take(MAX_REPORTED_ERRORS) {
+                message.push_str("\n  ");
+                message.push_str(error);
+            }
+            if errors.len() > MAX_REPORTED_ERRORS {
+                message.push_str(&format!(
+                    "\n  ...and {} more above",
+                    errors.len() - MAX_REPORTED_ERRORS
+                ));
+            }
+        }
+        anyhow::bail!(message);
+    }
+    println!("✓ Exported to {}", output.display());
+    Ok(())
+}
+
+/// Every `[preset.N]` section, in file order. Their `[preset.N.options]`
+/// sections don't say anything a choice of preset depends on.
+fn parse_export_presets(contents: &str) -> Vec<ExportPreset> {
+    let mut presets = Vec::new();
+    let mut current: Option<ExportPreset> = None;
+    for line in contents.lines() {
+        let line = line.trim();
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            presets.extend(current.take());
+            let is_preset = section
+                .strip_prefix("preset.")
+                .is_some_and(|index| index.bytes().all(|b| b.is_ascii_digit()));
+            if is_preset {
+                current = Some(ExportPreset {
+                    name: String::new(),
+                    platform: String::new(),
+                });
+            }
+            continue;
+        }
+        let (Some(preset), Some((key, value))) = (&mut current, line.split_once('=')) else {
+            continue;
+        };
+        match key.trim() {
+            "name" => preset.name = unquote(value),
+            "platform" => preset.platform = unquote(value),
+            _ => {}
+        }
+    }
+    presets.extend(current);
+    presets
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    let inner = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    inner.replace("\\\"", "\"").replace("\\\\", "\\")
+}
+
+/// The preset named `wanted`, or else the project's only Web preset.
+fn pick_web_preset(presets: &[ExportPreset], wanted: Option<&str>) -> Result<ExportPreset> {
+    if let Some(wanted) = wanted {
+        let preset = presets
+            .iter()
+            .find(|p| p.name == wanted)
+            .ok_or_else(|| anyhow::anyhow!("export_presets.cfg has no preset \"{}\"", wanted))?;
+        if !preset.is_web() {
+            anyhow::bail!(
+                "Preset \"{}\" exports for {}, not Web",
+                preset.name,
+                preset.platform
+            );
+        }
+        return Ok(preset.clone());
+    }
+    let web: Vec<&ExportPreset> = presets.iter().filter(|p| p.is_web()).collect();
+    match web.as_slice() {
+        [] => anyhow::bail!(
+            "export_presets.cfg has no Web preset. Add one in Godot's Project > Export dialog."
+        ),
+        [preset] => Ok((*preset).clone()),
+        several => anyhow::bail!(
+            "export_presets.cfg has {} Web presets ({}). Pick one with --preset.",
+            several.len(),
+            several
+                .iter()
+                .map(|p| format!("\"{}\"", p.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// `GODOT_BIN` if set, else the first of [`GODOT_BINARY_NAMES`] on `PATH`.
+fn find_godot() -> Result<PathBuf> {
+    if let Some(bin) = std::env::var(ENV_GODOT_BIN)
+        .ok()
+        .and_then(config::non_blank)
+    {
+        return Ok(PathBuf::from(bin));
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    for dir in std::env::split_paths(&path) {
+        for name in GODOT_BINARY_NAMES {
+            let candidate = dir.join(format!("{}{}", name, std::env::consts::EXE_SUFFIX));
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+        }
+    }
+    anyhow::bail!(
+        "Couldn't find Godot on PATH (looked for {}). Set {} to the Godot binary.",
+        GODOT_BINARY_NAMES.join(", "),
+        ENV_GODOT_BIN
+    )
+}
+
+/// What `godot --version` says, e.g. `4.2.1.stable.official.b09f793f5`,
+/// trimmed to the numeric version at its front.
+fn godot_version(godot: &Path) -> Result<String> {
+    let output = Command::new(godot)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to run {} --version", godot.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with(|c: char| c.is_ascii_digit()))
+        .map(numeric_version)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} --version didn't print a version: {}",
+                godot.display(),
+                stdout.trim()
+            )
+        })?;
+    Ok(version)
+}
+
+/// The leading run of dot-separated numbers: `4.2.1` from `4.2.1.stable.official`.
+fn numeric_version(text: &str) -> String {
+    text.split('.')
+        .take_while(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn major_version(version: &str) -> Option<u32> {
+    version.split('.').next()?.parse().ok()
+}
+
+/// `[godot] version = "4.2"` accepts any 4.2.x; `"4.2.1"` only that release.
+/// A trailing `.0` is what Godot leaves off, so `4.2.0` matches a binary that
+/// reports `4.2`.
+fn version_matches(configured: &str, found: &str) -> bool {
+    let parts = |v: &str| -> Vec<u32> {
+        let mut parts: Vec<u32> = numeric_version(v)
+            .split('.')
+            .filter_map(|p| p.parse().ok())
+            .collect();
+        while parts.len() > 2 && parts.last() == Some(&0) {
+            parts.pop();
+        }
+        parts
+    };
+    let (configured, found) = (parts(configured), parts(found));
+    !configured.is_empty() && found.starts_with(&configured)
+}
+
+/// Run `command` with its output passed through as it comes, and return
+/// whether it succeeded along with every `ERROR:` line it printed.
+fn run_streaming(mut command: Command) -> Result<(bool, Vec<String>)> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let stdout = child.stdout.take().expect("piped stdout");
+    let stderr = child.stderr.take().expect("piped stderr");
+    let echo_out = echo_lines(stdout, std::io::stdout(), errors.clone());
+    let echo_err = echo_lines(stderr, std::io::stderr(), errors.clone());
+    let status = child.wait()?;
+    let _ = echo_out.join();
+    let _ = echo_err.join();
+    let errors = std::mem::take(&mut *errors.lock().expect("errors lock poisoned"));
+    Ok((status.success(), errors))
+}
+
+fn echo_lines(
+    from: impl Read + Send + 'static,
+    mut to: impl Write + Send + 'static,
+    errors: Arc<Mutex<Vec<String>>>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        for line in BufReader::new(from).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let _ = writeln!(to, "{}", line);
+            if let Some(error) = line.trim().strip_prefix("ERROR:") {
+                errors
+                    .lock()
+                    .expect("errors lock poisoned")
+                    .push(error.trim().to_string());
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESETS: &str = r#"
+[preset.0]
+
+name="Windows Desktop"
+platform="Windows Desktop"
+export_path="build/win/game.exe"
+
+[preset.0.options]
+
+custom_template/debug=""
+
+[preset.1]
+
+name="Web \"itch\""
+platform="Web"
+export_path="build/web/index.html"
+
+[preset.1.options]
+
+name="not a preset name"
+"#;
+
+    #[test]
+    fn the_web_preset_is_found_among_the_others() {
+        let presets = parse_export_presets(PRESETS);
+        assert_eq!(
+            presets,
+            [
+                ExportPreset {
+                    name: "Windows Desktop".to_string(),
+                    platform: "Windows Desktop".to_string(),
+                },
+                ExportPreset {
+                    name: "Web \"itch\"".to_string(),
+                    platform: "Web".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            pick_web_preset(&presets, None).unwrap().name,
+            "Web \"itch\""
+        );
+        assert!(pick_web_preset(&presets, Some("Windows Desktop")).is_err());
+        assert!(pick_web_preset(&presets, Some("Linux")).is_err());
+        assert!(pick_web_preset(&presets[..1], None).is_err());
+    }
+
+    #[test]
+    fn versions_match_as_far_as_the_config_is_specific() {
+        assert_eq!(numeric_version("4.2.1.stable.official.b09f793f5"), "4.2.1");
+        assert!(version_matches("4.2", "4.2.1"));
+        assert!(version_matches("4.2.1", "4.2.1"));
+        assert!(version_matches("4.2.0", "4.2"));
+        assert!(!version_matches("4.2", "4.3"));
+        assert!(!version_matches("4.2.1", "4.2.2"));
+        assert!(!version_matches("4", "3.5"));
+        assert_eq!(major_version("3.5.3"), Some(3));
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::Result;
+use std::collections::HashSet;
 use std::path::Path;
 
+use crate::archive;
 use crate::config::{self, EntrypointSource, WavedashConfig};
 
 /// A missing entrypoint has two very different causes, and the old single message
@@ -52,6 +54,28 @@ pub struct FileStaging;
 impl FileStaging {
     /// Validate required files exist in the upload directory
     pub fn prepare(upload_dir: &Path, wavedash_config: &WavedashConfig) -> Result<Self> {
+        Self::check(upload_dir, wavedash_config, |file| {
+            upload_dir.join(file).exists()
+        })
+    }
+
+    /// [`prepare`](Self::prepare) for a build packed in an archive, checked
+    /// against `entries`, the archive's file paths.
+    pub fn prepare_archive(
+        archive_path: &Path,
+        entries: &HashSet<String>,
+        wavedash_config: &WavedashConfig,
+    ) -> Result<Self> {
+        Self::check(archive_path, wavedash_config, |file| {
+            archive::entry_path(file).is_ok_and(|path| entries.contains(&path))
+        })
+    }
+
+    fn check(
+        upload_dir: &Path,
+        wavedash_config: &WavedashConfig,
+        exists: impl Fn(&str) -> bool,
+    ) -> Result<Self> {
         // Validate entrypoint exists and is an HTML or JS file
         if let Some((entrypoint_str, source)) = wavedash_config.entrypoint_with_source()? {
             let lower = entrypoint_str.to_ascii_lowercase();
@@ -62,16 +86,14 @@ impl FileStaging {
                 );
             }
 
-            let entrypoint_path = upload_dir.join(entrypoint_str);
-            if !entrypoint_path.exists() {
+            if !exists(entrypoint_str) {
                 return Err(missing_entrypoint_error(entrypoint_str, source, upload_dir));
             }
         }
 
         // Validate executable and loader_url files exist (for JSDOS/Ruffle/Ren'Py)
         for file in wavedash_config.executable_files_to_validate()? {
-            let file_path = upload_dir.join(file);
-            if !file_path.exists() {
+            if !exists(file) {
                 anyhow::bail!(
                     "'{}' not found in upload_dir ({}). The file must exist inside your upload_dir.",
                     file,
@@ -0,0 +1,1442 @@
+//! Where a build came from: the commit, branch and tag checked out in the git
+//! repo around the project, whether the tree had uncommitted changes, and the
+//! commit's subject line.
+//!
+//! Everything is read straight out of `.git` rather than by running git, so a
+//! push works the same on a machine or CI image without git installed, and
+//! nothing touches the network. Only what provenance needs is implemented:
+//! refs (loose and packed), objects (loose and packed, deltas included), the
+//! index, and enough of gitattributes to know which files a filter rewrites.
+//! SHA-256 repositories and alternates aren't supported; a repo this can't
+//! read costs the build its provenance, never the push.
+
+use anyhow::{Context, Result};
+use flate2::read::ZlibDecoder;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Sent with the build as `git`, and read back by `build list` and `show`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitProvenance {
+    /// The full SHA of the commit checked out.
+    pub commit: String,
+    /// `None` on a detached HEAD, which is how most CI checks out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// A tag on the commit; the first by name if there are several.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Tracked files were changed, staged or not, so the build may not match
+    /// the commit. Untracked files don't count, as with `git describe --dirty`,
+    /// and neither do ones a gitattributes `filter` (LFS, say) rewrites, which
+    /// can't be compared without running it.
+    #[serde(default)]
+    pub dirty: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+}
+
+impl GitProvenance {
+    /// The repo enclosing `dir`, or `None` outside one, or before the first
+    /// commit. A repo that can't be read is a warning, not an error: provenance
+    /// is worth having, not worth failing a push over.
+    pub fn detect(dir: &Path) -> Option<Self> {
+        match Self::read(dir) {
+            Ok(provenance) => provenance,
+            Err(e) => {
+                eprintln!("Warning: couldn't read git details: {:#}", e);
+                None
+            }
+        }
+    }
+
+    fn read(dir: &Path) -> Result<Option<Self>> {
+        let Some(repo) = Repo::discover(dir)? else {
+            return Ok(None);
+        };
+        let (branch, commit) = repo.head()?;
+        let Some(commit) = commit else {
+            return Ok(None);
+        };
+        let (kind, body) = repo.read_object(&commit)?;
+        if kind != ObjectKind::Commit {
+            anyhow::bail!("HEAD points at a {:?}, not a commit", kind);
+        }
+        let tree = commit_tree(&body)?;
+        Ok(Some(Self {
+            tag: repo.tag_for(&commit)?,
+            dirty: repo.is_dirty(&tree)?,
+            subject: commit_subject(&body),
+            commit,
+            branch,
+        }))
+    }
+
+    /// `a1b2c3d`, with a `*` when the tree was dirty.
+    pub fn short_commit(&self) -> String {
+        let short = &self.commit[..self.commit.len().min(7)];
+        if self.dirty {
+            format!("{}*", short)
+        } else {
+            short.to_string()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl ObjectKind {
+    fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "commit" => Ok(Self::Commit),
+            "tree" => Ok(Self::Tree),
+            "blob" => Ok(Self::Blob),
+            "tag" => Ok(Self::Tag),
+            other => anyhow::bail!("unknown object type '{}'", other),
+        }
+    }
+
+    fn from_pack_type(kind: u8) -> Result<Self> {
+        match kind {
+            1 => Ok(Self::Commit),
+            2 => Ok(Self::Tree),
+            3 => Ok(Self::Blob),
+            4 => Ok(Self::Tag),
+            other => anyhow::bail!("unknown pack object type {}", other),
+        }
+    }
+}
+
+const OFS_DELTA: u8 = 6;
+const REF_DELTA: u8 = 7;
+/// How many deltas deep an object may be stored. git's own limit is 4095,
+/// but it packs 50 deep unless told otherwise; anything past this is far more
+/// likely a pack whose deltas loop than one anybody wrote.
+const MAX_DELTA_DEPTH: usize = 1024;
+/// Mode bits of a submodule's entry, which has no file of its own to compare.
+const GITLINK_MODE: u32 = 0o160000;
+const SYMLINK_MODE: u32 = 0o120000;
+const DIR_MODE: u32 = 0o040000;
+
+/// A repository as laid out on disk. For a linked worktree, `git_dir` holds
+/// its own HEAD and index and `common_dir` everything shared.
+struct Repo {
+    git_dir: PathBuf,
+    common_dir: PathBuf,
+    work_tree: PathBuf,
+    /// Pack indexes, read once: comparing trees can look up thousands of
+    /// objects.
+    packs: OnceCell<Vec<PackIndex>>,
+}
+
+struct PackIndex {
+    /// The `.pack` the index is for.
+    pack: PathBuf,
+    index: Vec<u8>,
+}
+
+impl Repo {
+    /// The repo whose work tree contains `start`, found the way git finds it:
+    /// the nearest `.git` directory, or `.git` file pointing at one.
+    fn discover(start: &Path) -> Result<Option<Self>> {
+        // A bare `wavedash.toml` has an empty parent, which means here.
+        let start = if start.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            start
+        };
+        let start = start
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", start.display()))?;
+        for dir in start.ancestors() {
+            let dot_git = dir.join(".git");
+            let git_dir = if dot_git.is_dir() {
+                dot_git
+            } else if dot_git.is_file() {
+                let text = std::fs::read_to_string(&dot_git)
+                    .with_context(|| format!("Failed to read {}", dot_git.display()))?;
+                let target = text
+                    .trim()
+                    .strip_prefix("gitdir:")
+                    .ok_or_else(|| anyhow::anyhow!("{} isn't a gitdir link", dot_git.display()))?;
+                dir.join(target.trim())
+            } else {
+                continue;
+            };
+            let common_dir = match std::fs::read_to_string(git_dir.join("commondir")) {
+                Ok(common) => git_dir.join(common.trim()),
+                Err(_) => git_dir.clone(),
+            };
+            return Ok(Some(Self {
+                git_dir,
+                common_dir,
+                work_tree: dir.to_path_buf(),
+                packs: OnceCell::new(),
+            }));
+        }
+        Ok(None)
+    }
+
+    /// The branch HEAD is on, if any, and the commit it resolves to; `None`
+    /// for a branch with no commits yet.
+    fn head(&self) -> Result<(Option<String>, Option<String>)> {
+        let head = std::fs::read_to_string(self.git_dir.join("HEAD"))
+            .with_context(|| format!("Failed to read {}", self.git_dir.join("HEAD").display()))?;
+        let head = head.trim();
+        match head.strip_prefix("ref:") {
+            Some(name) => {
+                let name = name.trim();
+                let branch = name.strip_prefix("refs/heads/").map(String::from);
+                Ok((branch, self.resolve_ref(name)?))
+            }
+            None => Ok((None, Some(object_id(head)?))),
+        }
+    }
+
+    /// Follows symbolic refs; `None` for a ref that doesn't exist.
+    fn resolve_ref(&self, name: &str) -> Result<Option<String>> {
+        let mut name = name.to_string();
+        for _ in 0..10 {
+            let loose = [&self.git_dir, &self.common_dir]
+                .iter()
+                .map(|dir| dir.join(&name))
+                .find(|path| path.is_file());
+            let value = match loose {
+                Some(path) => std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?,
+                None => {
+                    return self
+                        .packed_refs()?
+                        .into_iter()
+                        .find(|r| r.name == name)
+                        .map(|r| object_id(&r.target))
+                        .transpose();
+                }
+            };
+            match value.trim().strip_prefix("ref:") {
+                Some(target) => name = target.trim().to_string(),
+                None => return Ok(Some(object_id(value.trim())?)),
+            }
+        }
+        anyhow::bail!("ref {} is a symbolic ref loop", name)
+    }
+
+    fn packed_refs(&self) -> Result<Vec<PackedRef>> {
+        match std::fs::read_to_string(self.common_dir.join("packed-refs")) {
+            Ok(text) => Ok(parse_packed_refs(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e).context("Failed to read packed-refs"),
+        }
+    }
+
+    /// The first tag, by name, that points at `commit` directly or through
+    /// an annotated tag.
+    fn tag_for(&self, commit: &str) -> Result<Option<String>> {
+        let mut tags: HashMap<String, String> = HashMap::new();
+        for packed in self.packed_refs()? {
+            if let Some(name) = packed.name.strip_prefix("refs/tags/") {
+                let target = packed.peeled.unwrap_or(packed.target);
+                tags.insert(name.to_string(), target);
+            }
+        }
+        // Loose refs shadow packed ones of the same name.
+        let loose_root = self.common_dir.join("refs").join("tags");
+        for entry in walkdir::WalkDir::new(&loose_root)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let Ok(name) = entry.path().strip_prefix(&loose_root) else {
+                continue;
+            };
+            let name = name.to_string_lossy().replace('\\', "/");
+            // A tag on an object that isn't here, as a shallow clone leaves
+            // them, can't be on the commit, which is.
+            let peeled = std::fs::read_to_string(entry.path())
+                .ok()
+                .and_then(|target| object_id(target.trim()).ok())
+                .and_then(|id| self.peel(&id).ok());
+            match peeled {
+                Some(target) => tags.insert(name, target),
+                None => tags.remove(&name),
+            };
+        }
+
+        let mut matching: Vec<String> = tags
+            .into_iter()
+            .filter(|(_, target)| target == commit)
+            .map(|(name, _)| name)
+            .collect();
+        matching.sort();
+        Ok(matching.into_iter().next())
+    }
+
+    /// What an annotated tag ultimately points at; other objects as they are.
+    fn peel(&self, id: &str) -> Result<String> {
+        let mut id = id.to_string();
+        for _ in 0..10 {
+            let (kind, body) = self.read_object(&id)?;
+            if kind != ObjectKind::Tag {
+                return Ok(id);
+            }
+            id = object_id(
+                &header_field(&body, "object")
+                    .ok_or_else(|| anyhow::anyhow!("tag {} has no object", id))?,
+            )?;
+        }
+        anyhow::bail!("tag {} is nested too deeply", id)
+    }
+
+    fn read_object(&self, id: &str) -> Result<(ObjectKind, Vec<u8>)> {
+        self.read_object_at_depth(id, 0)
+    }
+
+    /// [`Repo::read_object`] as the base of a delta `depth` deep.
+    fn read_object_at_depth(&self, id: &str, depth: usize) -> Result<(ObjectKind, Vec<u8>)> {
+        let loose = loose_object_path(&self.common_dir.join("objects"), id)?;
+        if loose.is_file() {
+            return read_loose_object(&loose);
+        }
+        let raw = hex_decode(id)?;
+        for pack in self.packs()? {
+            if let Some(offset) = find_in_pack_index(pack, &raw)? {
+                return self.read_packed_object(&pack.pack, offset, depth);
+            }
+        }
+        anyhow::bail!("object {} not found", id)
+    }
+
+    fn packs(&self) -> Result<&[PackIndex]> {
+        if let Some(packs) = self.packs.get() {
+            return Ok(packs);
+        }
+        let pack_dir = self.common_dir.join("objects").join("pack");
+        let mut packs = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(&pack_dir) {
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                if path.extension().is_some_and(|ext| ext == "idx") {
+                    let index = std::fs::read(&path)
+                        .with_context(|| format!("Failed to read {}", path.display()))?;
+                    packs.push(PackIndex {
+                        pack: path.with_extension("pack"),
+                        index,
+                    });
+                }
+            }
+        }
+        Ok(self.packs.get_or_init(|| packs))
+    }
+
+    fn read_packed_object(
+        &self,
+        pack: &Path,
+        offset: u64,
+        depth: usize,
+    ) -> Result<(ObjectKind, Vec<u8>)> {
+        let corrupt = || anyhow::anyhow!("corrupt delta in {}", pack.display());
+        if depth > MAX_DELTA_DEPTH {
+            anyhow::bail!("delta chain in {} is too deep", pack.display());
+        }
+        let mut file = BufReader::new(
+            File::open(pack).with_context(|| format!("Failed to open {}", pack.display()))?,
+        );
+        file.seek(SeekFrom::Start(offset))?;
+        let mut byte = read_byte(&mut file)?;
+        let kind = (byte >> 4) & 0b111;
+        // The size is the inflated size, which the decoder doesn't need.
+        while byte & 0x80 != 0 {
+            byte = read_byte(&mut file)?;
+        }
+        match kind {
+            OFS_DELTA => {
+                let mut byte = read_byte(&mut file)?;
+                let mut distance = u64::from(byte & 0x7f);
+                while byte & 0x80 != 0 {
+                    byte = read_byte(&mut file)?;
+                    distance = distance
+                        .checked_add(1)
+                        .and_then(|d| d.checked_mul(1 << 7))
+                        .ok_or_else(corrupt)?
+                        | u64::from(byte & 0x7f);
+                }
+                let delta = inflate(&mut file)?;
+                // The base comes before the delta, never at it.
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .filter(|_| distance > 0)
+                    .ok_or_else(corrupt)?;
+                let (kind, base) = self.read_packed_object(pack, base_offset, depth + 1)?;
+                Ok((kind, apply_delta(&base, &delta)?))
+            }
+            REF_DELTA => {
+                let mut base_id = [0u8; 20];
+                file.read_exact(&mut base_id)?;
+                let delta = inflate(&mut file)?;
+                let (kind, base) = self.read_object_at_depth(&hex_encode(&base_id), depth + 1)?;
+                Ok((kind, apply_delta(&base, &delta)?))
+            }
+            kind => Ok((ObjectKind::from_pack_type(kind)?, inflate(&mut file)?)),
+        }
+    }
+
+    /// True when the index differs from `head_tree` (staged changes), or the
+    /// work tree from the index (unstaged ones).
+    fn is_dirty(&self, head_tree: &str) -> Result<bool> {
+        let index = match std::fs::read(self.git_dir.join("index")) {
+            Ok(bytes) => parse_index(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Index::default(),
+            Err(e) => return Err(e).context("Failed to read the git index"),
+        };
+        if index.entries.iter().any(|e| e.stage != 0) {
+            return Ok(true);
+        }
+        Ok(self.index_differs_from_tree(&index, head_tree)?
+            || self.work_tree_differs_from_index(&index)?)
+    }
+
+    fn index_differs_from_tree(&self, index: &Index, head_tree: &str) -> Result<bool> {
+        // The index caches tree IDs for directories it hasn't touched since
+        // they were last written; a root that matches means nothing's staged.
+        if index.root_tree.as_deref() == Some(head_tree) {
+            return Ok(false);
+        }
+        let sparse: HashSet<&str> = index
+            .entries
+            .iter()
+            .filter(|e| e.mode == DIR_MODE)
+            .map(|e| e.path.trim_end_matches('/'))
+            .collect();
+        let mut committed = HashMap::new();
+        self.flatten_tree(head_tree, "", &sparse, &mut committed)?;
+        if committed.len() != index.entries.len() {
+            return Ok(true);
+        }
+        Ok(index
+            .entries
+            .iter()
+            .any(|entry| committed.get(entry.path.trim_end_matches('/')) != Some(&entry.id)))
+    }
+
+    /// Every file in `tree` as path → object ID, stopping at directories the
+    /// index holds as a single sparse entry.
+    fn flatten_tree(
+        &self,
+        tree: &str,
+        prefix: &str,
+        sparse: &HashSet<&str>,
+        out: &mut HashMap<String, String>,
+    ) -> Result<()> {
+        let (kind, body) = self.read_object(tree)?;
+        if kind != ObjectKind::Tree {
+            anyhow::bail!("{} is a {:?}, not a tree", tree, kind);
+        }
+        for entry in parse_tree(&body)? {
+            let path = format!("{}{}", prefix, entry.name);
+            if entry.mode == DIR_MODE && !sparse.contains(path.as_str()) {
+                self.flatten_tree(&entry.id, &format!("{}/", path), sparse, out)?;
+            } else {
+                out.insert(path, entry.id);
+            }
+        }
+        Ok(())
+    }
+
+    fn work_tree_differs_from_index(&self, index: &Index) -> Result<bool> {
+        let mut filtered: Option<FilteredPaths> = None;
+        for entry in &index.entries {
+            if entry.skip_worktree || entry.mode == GITLINK_MODE || entry.mode == DIR_MODE {
+                continue;
+            }
+            let path = self.work_tree.join(&entry.path);
+            let Ok(meta) = std::fs::symlink_metadata(&path) else {
+                return Ok(true);
+            };
+            if entry.stat_matches(&meta) {
+                continue;
+            }
+            // Stat info goes stale for plenty of reasons that aren't edits, a
+            // fresh copy or a `touch`; only the content decides.
+            if entry.mode == SYMLINK_MODE {
+                let target = std::fs::read_link(&path)?.to_string_lossy().into_owned();
+                if hash_object("blob", target.as_bytes()) != entry.id {
+                    return Ok(true);
+                }
+                continue;
+            }
+            if !meta.is_file() {
+                return Ok(true);
+            }
+            let (id, has_cr) = hash_file_blob(&path, false)?;
+            if id == entry.id {
+                continue;
+            }
+            // The index holds the file as git's filters left it. A filter
+            // such as LFS can't be undone without running it, so the file's
+            // state is unknown, which isn't the same as changed.
+            if filtered
+                .get_or_insert_with(|| FilteredPaths::load(self, index))
+                .contains(&entry.path)
+            {
+                continue;
+            }
+            // The CRLF conversion autocrlf and `eol` attributes do can be.
+            if !has_cr || hash_file_blob(&path, true)?.0 != entry.id {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// The paths gitattributes give a `filter`, `ident` or `working-tree-encoding`:
+/// what's in the work tree isn't what git hashed, and only the filter knows
+/// how to get from one to the other. Read from the `.gitattributes` files in
+/// the index and `info/attributes`, each as a gitignore-style matcher over its
+/// directory, with the deeper file winning as git's precedence has it.
+struct FilteredPaths {
+    /// Directory prefix, with its trailing `/`, and the matcher for it,
+    /// shallowest first and `info/attributes` last.
+    matchers: Vec<(String, Gitignore)>,
+}
+
+impl FilteredPaths {
+    fn load(repo: &Repo, index: &Index) -> Self {
+        let mut sources: Vec<(String, PathBuf)> = index
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let dir = entry.path.strip_suffix(".gitattributes")?;
+                (dir.is_empty() || dir.ends_with('/'))
+                    .then(|| (dir.to_string(), repo.work_tree.join(&entry.path)))
+            })
+            .collect();
+        sources.sort_by_key(|(dir, _)| dir.matches('/').count());
+        sources.push((
+            String::new(),
+            repo.common_dir.join("info").join("attributes"),
+        ));
+
+        // An attributes file that can't be read or has a bad pattern in it
+        // only loses its own say.
+        let matchers = sources
+            .into_iter()
+            .filter_map(|(dir, path)| {
+                let text = std::fs::read_to_string(path).ok()?;
+                let mut builder = GitignoreBuilder::new(repo.work_tree.join(&dir));
+                for line in text.lines() {
+                    if let Some(pattern) = filter_pattern(line) {
+                        let _ = builder.add_line(None, &pattern);
+                    }
+                }
+                Some((dir, builder.build().ok()?))
+            })
+            .collect();
+        Self { matchers }
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        let mut filtered = false;
+        for (dir, matcher) in &self.matchers {
+            let Some(relative) = path.strip_prefix(dir.as_str()) else {
+                continue;
+            };
+            match matcher.matched(relative, false) {
+                Match::Ignore(_) => filtered = true,
+                Match::Whitelist(_) => filtered = false,
+                Match::None => {}
+            }
+        }
+        filtered
+    }
+}
+
+/// A gitattributes line as a gitignore pattern: the pattern itself when the
+/// line sets a filtering attribute, `!` and the pattern when it only unsets
+/// one, `None` when it doesn't touch them.
+fn filter_pattern(line: &str) -> Option<String> {
+    let mut words = line.split_whitespace();
+    let pattern = words.next()?;
+    if pattern.starts_with('#') || pattern.starts_with('!') || pattern.starts_with("[attr]") {
+        return None;
+    }
+    let mut sets = None;
+    for attribute in words {
+        let (name, unset) = match attribute.strip_prefix(['-', '!']) {
+            Some(name) => (name, true),
+            None => (attribute, false),
+        };
+        let name = name.split('=').next().unwrap_or_default();
+        if matches!(name, "filter" | "ident" | "working-tree-encoding") {
+            sets = Some(sets.unwrap_or(false) || !unset);
+        }
+    }
+    match sets? {
+        true => Some(pattern.to_string()),
+        false => Some(format!("!{}", pattern)),
+    }
+}
+
+struct PackedRef {
+    name: String,
+    target: String,
+    /// What an annotated tag points at, from the `^` line after it.
+    peeled: Option<String>,
+}
+
+fn parse_packed_refs(text: &str) -> Vec<PackedRef> {
+    let mut refs: Vec<PackedRef> = Vec::new();
+    for line in text.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some(peeled) = line.strip_prefix('^') {
+            if let Some(last) = refs.last_mut() {
+                last.peeled = Some(peeled.trim().to_string());
+            }
+            continue;
+        }
+        if let Some((target, name)) = line.split_once(' ') {
+            refs.push(PackedRef {
+                name: name.trim().to_string(),
+                target: target.to_string(),
+                peeled: None,
+            });
+        }
+    }
+    refs
+}
+
+/// A 40-digit hex object ID, lowercased.
+fn object_id(text: &str) -> Result<String> {
+    if text.len() == 40 && text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(text.to_ascii_lowercase())
+    } else {
+        anyhow::bail!("'{}' isn't a SHA-1 object ID", text)
+    }
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode(id: &str) -> Result<[u8; 20]> {
+    let id = object_id(id)?;
+    let mut raw = [0u8; 20];
+    for (byte, digits) in raw.iter_mut().zip(id.as_bytes().chunks_exact(2)) {
+        let digits = std::str::from_utf8(digits).expect("checked as hex");
+        *byte = u8::from_str_radix(digits, 16).expect("checked as hex");
+    }
+    Ok(raw)
+}
+
+/// Where the loose object `id` is kept under `objects`.
+fn loose_object_path(objects: &Path, id: &str) -> Result<PathBuf> {
+    let id = object_id(id)?;
+    let (dir, file) = id.split_at(2);
+    Ok(objects.join(dir).join(file))
+}
+
+fn hash_object(kind: &str, content: &[u8]) -> String {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY);
+    context.update(format!("{} {}\0", kind, content.len()).as_bytes());
+    context.update(content);
+    hex_encode(context.finish().as_ref())
+}
+
+const HASH_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The blob ID of the file at `path`, hashed as it's read rather than read
+/// whole, and whether it has a CR in it. With `crlf_to_lf`, the ID of the
+/// file as autocrlf would store it, every CRLF turned into LF. That takes a
+/// pass to count the bytes first, since the length leads the hash.
+fn hash_file_blob(path: &Path, crlf_to_lf: bool) -> Result<(String, bool)> {
+    let len = if crlf_to_lf {
+        let mut len = 0u64;
+        read_blob_chunks(path, true, |chunk| len += chunk.len() as u64)?;
+        len
+    } else {
+        std::fs::metadata(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .len()
+    };
+    let mut context = ring::digest::Context::new(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY);
+    context.update(format!("blob {}\0", len).as_bytes());
+    let has_cr = read_blob_chunks(path, crlf_to_lf, |chunk| context.update(chunk))?;
+    Ok((hex_encode(context.finish().as_ref()), has_cr))
+}
+
+/// Hands `each` the file at `path` a chunk at a time, CRLFs turned into LFs
+/// with `crlf_to_lf`, and says whether it had a CR in it.
+fn read_blob_chunks(path: &Path, crlf_to_lf: bool, mut each: impl FnMut(&[u8])) -> Result<bool> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut buffer = vec![0u8; HASH_CHUNK_SIZE];
+    let mut converted = Vec::new();
+    let (mut has_cr, mut pending_cr) = (false, false);
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        let chunk = &buffer[..read];
+        has_cr |= chunk.contains(&b'\r');
+        if !crlf_to_lf {
+            each(chunk);
+            continue;
+        }
+        // A CR is held back until the next byte says whether it ends a line,
+        // which can be in the next chunk.
+        converted.clear();
+        for &byte in chunk {
+            if pending_cr && byte != b'\n' {
+                converted.push(b'\r');
+            }
+            pending_cr = byte == b'\r';
+            if !pending_cr {
+                converted.push(byte);
+            }
+        }
+        each(&converted);
+    }
+    if pending_cr {
+        each(b"\r");
+    }
+    Ok(has_cr)
+}
+
+fn read_byte(reader: &mut impl Read) -> Result<u8> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn inflate(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(reader)
+        .read_to_end(&mut out)
+        .context("Failed to inflate a git object")?;
+    Ok(out)
+}
+
+fn read_loose_object(path: &Path) -> Result<(ObjectKind, Vec<u8>)> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut data = inflate(&mut BufReader::new(file))?;
+    let nul = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| anyhow::anyhow!("{} has no object header", path.display()))?;
+    let header = String::from_utf8_lossy(&data[..nul]).into_owned();
+    let kind = header.split(' ').next().unwrap_or_default();
+    let kind = ObjectKind::from_name(kind)?;
+    data.drain(..=nul);
+    Ok((kind, data))
+}
+
+/// Where `id` sits in the pack a version 2 `.idx` file indexes.
+fn find_in_pack_index(pack: &PackIndex, id: &[u8; 20]) -> Result<Option<u64>> {
+    let index = &pack.index;
+    let path = pack.pack.with_extension("idx");
+    let corrupt = || anyhow::anyhow!("{} is truncated or corrupt", path.display());
+    let u32_at = |at: usize| -> Result<u32> {
+        let bytes = index.get(at..at + 4).ok_or_else(corrupt)?;
+        Ok(u32::from_be_bytes(bytes.try_into().expect("4 bytes")))
+    };
+    if index.get(..4) != Some(b"\xfftOc".as_slice()) || u32_at(4)? != 2 {
+        anyhow::bail!("{} isn't a version 2 pack index", path.display());
+    }
+    const FANOUT: usize = 8;
+    let count = u32_at(FANOUT + 255 * 4)? as usize;
+    let first = id[0] as usize;
+    let mut low = if first == 0 {
+        0
+    } else {
+        u32_at(FANOUT + (first - 1) * 4)? as usize
+    };
+    let mut high = u32_at(FANOUT + first * 4)? as usize;
+
+    let ids = FANOUT + 256 * 4;
+    while low < high {
+        let mid = (low + high) / 2;
+        let candidate = index
+            .get(ids + mid * 20..ids + mid * 20 + 20)
+            .ok_or_else(corrupt)?;
+        match candidate.cmp(id.as_slice()) {
+            std::cmp::Ordering::Less => low = mid + 1,
+            std::cmp::Ordering::Greater => high = mid,
+            std::cmp::Ordering::Equal => {
+                let offsets = ids + count * 20 + count * 4;
+                let offset = u32_at(offsets + mid * 4)?;
+                if offset & 0x8000_0000 == 0 {
+                    return Ok(Some(u64::from(offset)));
+                }
+                let large = offsets + count * 4 + (offset & 0x7fff_ffff) as usize * 8;
+                let bytes = index.get(large..large + 8).ok_or_else(corrupt)?;
+                return Ok(Some(u64::from_be_bytes(bytes.try_into().expect("8 bytes"))));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Rebuild an object from its delta base and a pack delta.
+fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let corrupt = || anyhow::anyhow!("corrupt delta");
+    let mut pos = 0;
+    let varint = |pos: &mut usize| -> Result<usize> {
+        let mut value = 0usize;
+        let mut shift = 0u32;
+        loop {
+            let byte = *delta.get(*pos).ok_or_else(corrupt)?;
+            *pos += 1;
+            let bits = usize::from(byte & 0x7f);
+            if shift >= usize::BITS || (bits << shift) >> shift != bits {
+                return Err(corrupt());
+            }
+            value |= bits << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    };
+    let base_size = varint(&mut pos)?;
+    let target_size = varint(&mut pos)?;
+    if base_size != base.len() {
+        anyhow::bail!(
+            "delta expects a {} byte base, got {}",
+            base_size,
+            base.len()
+        );
+    }
+
+    // The sizes are only as trustworthy as the pack; a corrupt one mustn't
+    // get to reserve more than the delta could plausibly produce.
+    let mut out = Vec::with_capacity(target_size.min(base.len().saturating_add(delta.len())));
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            let mut field = |bits: u8, bytes: usize| -> Result<usize> {
+                let mut value = 0usize;
+                for i in 0..bytes {
+                    if bits & (1 << i) != 0 {
+                        value |= usize::from(*delta.get(pos).ok_or_else(corrupt)?) << (8 * i);
+                        pos += 1;
+                    }
+                }
+                Ok(value)
+            };
+            let offset = field(op & 0x0f, 4)?;
+            let size = match field((op >> 4) & 0x07, 3)? {
+                0 => 0x10000,
+                size => size,
+            };
+            let end = offset.checked_add(size).ok_or_else(corrupt)?;
+            out.extend_from_slice(base.get(offset..end).ok_or_else(corrupt)?);
+        } else if op != 0 {
+            let size = usize::from(op);
+            out.extend_from_slice(delta.get(pos..pos + size).ok_or_else(corrupt)?);
+            pos += size;
+        } else {
+            return Err(corrupt());
+        }
+    }
+    if out.len() != target_size {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
+
+/// The value of `name` among an object's header lines, which end at the first
+/// blank line.
+fn header_field(body: &[u8], name: &str) -> Option<String> {
+    let text = String::from_utf8_lossy(body);
+    text.lines()
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .map(String::from)
+}
+
+fn commit_tree(body: &[u8]) -> Result<String> {
+    object_id(&header_field(body, "tree").ok_or_else(|| anyhow::anyhow!("commit has no tree"))?)
+}
+
+/// The first paragraph of the message on one line, as `git log --format=%s`
+/// shows it.
+fn commit_subject(body: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(body);
+    let (_, message) = text.split_once("\n\n")?;
+    let subject = message
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| line.is_empty())
+        .take_while(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!subject.is_empty()).then_some(subject)
+}
+
+struct TreeEntry {
+    mode: u32,
+    name: String,
+    id: String,
+}
+
+fn parse_tree(body: &[u8]) -> Result<Vec<TreeEntry>> {
+    let corrupt = || anyhow::anyhow!("corrupt tree object");
+    let mut entries = Vec::new();
+    let mut rest = body;
+    while !rest.is_empty() {
+        let nul = rest.iter().position(|&b| b == 0).ok_or_else(corrupt)?;
+        let space = rest[..nul]
+            .iter()
+            .position(|&b| b == b' ')
+            .ok_or_else(corrupt)?;
+        let mode = std::str::from_utf8(&rest[..space])?;
+        let mode = u32::from_str_radix(mode, 8)?;
+        let name = String::from_utf8_lossy(&rest[space + 1..nul]).into_owned();
+        let id = rest.get(nul + 1..nul + 21).ok_or_else(corrupt)?;
+        entries.push(TreeEntry {
+            mode,
+            name,
+            id: hex_encode(id),
+        });
+        rest = &rest[nul + 21..];
+    }
+    Ok(entries)
+}
+
+#[derive(Default)]
+struct Index {
+    entries: Vec<IndexEntry>,
+    /// The root of the cached tree extension, when it's still valid.
+    root_tree: Option<String>,
+}
+
+struct IndexEntry {
+    mtime: (u32, u32),
+    size: u32,
+    mode: u32,
+    id: String,
+    stage: u16,
+    skip_worktree: bool,
+    path: String,
+}
+
+impl IndexEntry {
+    fn stat_matches(&self, meta: &std::fs::Metadata) -> bool {
+        if meta.len() as u32 != self.size {
+            return false;
+        }
+        let Some(modified) = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        else {
+            return false;
+        };
+        let (secs, nanos) = self.mtime;
+        // Nanoseconds are only compared when git recorded them.
+        modified.as_secs() as u32 == secs && (nanos == 0 || modified.subsec_nanos() == nanos)
+    }
+}
+
+/// The entries of an index file, versions 2 to 4, and its cached root tree.
+fn parse_index(bytes: &[u8]) -> Result<Index> {
+    let corrupt = || anyhow::anyhow!("the git index is truncated or corrupt");
+    let u32_at = |at: usize| -> Result<u32> {
+        let b = bytes.get(at..at + 4).ok_or_else(corrupt)?;
+        Ok(u32::from_be_bytes(b.try_into().expect("4 bytes")))
+    };
+    let u16_at = |at: usize| -> Result<u16> {
+        let b = bytes.get(at..at + 2).ok_or_else(corrupt)?;
+        Ok(u16::from_be_bytes(b.try_into().expect("2 bytes")))
+    };
+    if bytes.get(..4) != Some(b"DIRC".as_slice()) {
+        return Err(corrupt());
+    }
+    let version = u32_at(4)?;
+    if !(2..=4).contains(&version) {
+        anyhow::bail!("git index version {} isn't supported", version);
+    }
+    let count = u32_at(8)? as usize;
+
+    // An entry takes at least 62 bytes, whatever the header claims.
+    let mut entries = Vec::with_capacity(count.min(bytes.len() / 62));
+    let mut pos = 12;
+    let mut previous = String::new();
+    for _ in 0..count {
+        let start = pos;
+        let flags = u16_at(start + 60)?;
+        let mut skip_worktree = false;
+        pos = start + 62;
+        if version >= 3 && flags & 0x4000 != 0 {
+            skip_worktree = u16_at(pos)? & 0x4000 != 0;
+            pos += 2;
+        }
+        let path = if version == 4 {
+            // Each path is the previous one, less some bytes, plus a suffix.
+            let mut byte = *bytes.get(pos).ok_or_else(corrupt)?;
+            pos += 1;
+            let mut strip = usize::from(byte & 0x7f);
+            while byte & 0x80 != 0 {
+                byte = *bytes.get(pos).ok_or_else(corrupt)?;
+                pos += 1;
+                strip = strip
+                    .checked_add(1)
+                    .and_then(|s| s.checked_mul(1 << 7))
+                    .ok_or_else(corrupt)?
+                    | usize::from(byte & 0x7f);
+            }
+            let rest = bytes.get(pos..).ok_or_else(corrupt)?;
+            let nul = rest.iter().position(|&b| b == 0).ok_or_else(corrupt)?;
+            let keep = previous.len().checked_sub(strip).ok_or_else(corrupt)?;
+            let mut path = previous.as_bytes()[..keep].to_vec();
+            path.extend_from_slice(&rest[..nul]);
+            pos += nul + 1;
+            String::from_utf8_lossy(&path).into_owned()
+        } else {
+            let rest = bytes.get(pos..).ok_or_else(corrupt)?;
+            let nul = rest.iter().position(|&b| b == 0).ok_or_else(corrupt)?;
+            let path = String::from_utf8_lossy(&rest[..nul]).into_owned();
+            // Entries are NUL-padded to a multiple of eight bytes.
+            pos = start + (pos + nul - start + 8) / 8 * 8;
+            path
+        };
+        entries.push(IndexEntry {
+            mtime: (u32_at(start + 8)?, u32_at(start + 12)?),
+            mode: u32_at(start + 24)?,
+            size: u32_at(start + 36)?,
+            id: hex_encode(bytes.get(start + 40..start + 60).ok_or_else(corrupt)?),
+            stage: (flags >> 12) & 0b11,
+            skip_worktree,
+            path: path.clone(),
+        });
+        previous = path;
+    }
+
+    // Extensions follow the entries, up to the trailing checksum.
+    let mut root_tree = None;
+    while pos + 8 + 20 <= bytes.len() {
+        let signature = &bytes[pos..pos + 4];
+        let size = u32_at(pos + 4)? as usize;
+        let data = bytes.get(pos + 8..pos + 8 + size).ok_or_else(corrupt)?;
+        if signature == b"TREE" {
+            root_tree = cached_root_tree(data);
+        }
+        pos += 8 + size;
+    }
+    Ok(Index { entries, root_tree })
+}
+
+/// The root's ID from a `TREE` extension: an empty path, then an entry count
+/// that's negative when the cache is invalid.
+fn cached_root_tree(data: &[u8]) -> Option<String> {
+    let nul = data.iter().position(|&b| b == 0)?;
+    if nul != 0 {
+        return None;
+    }
+    let newline = data.iter().position(|&b| b == b'\n')?;
+    let counts = std::str::from_utf8(&data[1..newline]).ok()?;
+    let entry_count: i64 = counts.split(' ').next()?.parse().ok()?;
+    if entry_count < 0 {
+        return None;
+    }
+    data.get(newline + 1..newline + 21).map(hex_encode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    fn write_object(git_dir: &Path, kind: &str, content: &[u8]) -> String {
+        let id = hash_object(kind, content);
+        let path = loose_object_path(&git_dir.join("objects"), &id).expect("id");
+        std::fs::create_dir_all(path.parent().expect("dir")).expect("mkdir");
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(format!("{} {}\0", kind, content.len()).as_bytes())
+            .expect("header");
+        encoder.write_all(content).expect("content");
+        std::fs::write(path, encoder.finish().expect("zlib")).expect("write");
+        id
+    }
+
+    /// An object as stored in a test pack: whole, or as a delta against the
+    /// object at `base_at` in the pack.
+    enum Packed<'a> {
+        Whole(u8, &'a [u8]),
+        OfsDelta { base_at: usize, delta: Vec<u8> },
+    }
+
+    fn deflate(content: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(content).expect("deflate");
+        encoder.finish().expect("zlib")
+    }
+
+    /// A pack of `objects`, each with the ID it's indexed under, and its
+    /// version 2 `.idx`.
+    fn write_pack(git_dir: &Path, objects: &[(String, Packed)]) {
+        let mut pack = b"PACK".to_vec();
+        pack.extend_from_slice(&2u32.to_be_bytes());
+        pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+        let mut offsets = Vec::new();
+        for (_, object) in objects {
+            let offset = pack.len();
+            let (kind, size, body) = match object {
+                Packed::Whole(kind, content) => (*kind, content.len(), deflate(content)),
+                Packed::OfsDelta { delta, .. } => (OFS_DELTA, delta.len(), deflate(delta)),
+            };
+            // Type and size: four bits of size in the first byte, then seven
+            // a byte.
+            let mut byte = (kind << 4) | (size & 0x0f) as u8;
+            let mut rest = size >> 4;
+            while rest != 0 {
+                pack.push(byte | 0x80);
+                byte = (rest & 0x7f) as u8;
+                rest >>= 7;
+            }
+            pack.push(byte);
+            if let Packed::OfsDelta { base_at, .. } = object {
+                // Big-endian, with one added to every byte but the last. A
+                // delta may name itself, as only a corrupt pack would.
+                let mut distance: usize = offset - offsets.get(*base_at).unwrap_or(&offset);
+                let mut encoded = vec![(distance & 0x7f) as u8];
+                while distance >> 7 != 0 {
+                    distance = (distance >> 7) - 1;
+                    encoded.insert(0, 0x80 | (distance & 0x7f) as u8);
+                }
+                pack.extend_from_slice(&encoded);
+            }
+            pack.extend_from_slice(&body);
+            offsets.push(offset);
+        }
+        pack.extend_from_slice(&[0; 20]);
+
+        let mut sorted: Vec<([u8; 20], usize)> = objects
+            .iter()
+            .zip(&offsets)
+            .map(|((id, _), offset)| (hex_decode(id).expect("id"), *offset))
+            .collect();
+        sorted.sort();
+        let mut index = b"\xfftOc".to_vec();
+        index.extend_from_slice(&2u32.to_be_bytes());
+        for first in 0..=255u8 {
+            let count = sorted.iter().filter(|(id, _)| id[0] <= first).count();
+            index.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+        for (id, _) in &sorted {
+            index.extend_from_slice(id);
+        }
+        // CRCs, which reading never checks.
+        index.resize(index.len() + sorted.len() * 4, 0);
+        for (_, offset) in &sorted {
+            index.extend_from_slice(&(*offset as u32).to_be_bytes());
+        }
+        index.extend_from_slice(&[0; 40]);
+
+        let dir = git_dir.join("objects/pack");
+        std::fs::create_dir_all(&dir).expect("mkdir");
+        std::fs::write(dir.join("pack-test.pack"), pack).expect("write pack");
+        std::fs::write(dir.join("pack-test.idx"), index).expect("write idx");
+    }
+
+    /// A delta that copies the start `target` shares with `base` and inserts
+    /// the rest.
+    fn delta_for(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let varint = |out: &mut Vec<u8>, mut value: usize| loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                break;
+            }
+            out.push(byte | 0x80);
+        };
+        let mut delta = Vec::new();
+        varint(&mut delta, base.len());
+        varint(&mut delta, target.len());
+        let shared = base.iter().zip(target).take_while(|(a, b)| a == b).count();
+        // Copy from offset 0, with a one byte size.
+        assert!(shared > 0 && shared < 256);
+        delta.extend_from_slice(&[0x90, shared as u8]);
+        for chunk in target[shared..].chunks(0x7f) {
+            delta.push(chunk.len() as u8);
+            delta.extend_from_slice(chunk);
+        }
+        delta
+    }
+
+    /// A version 2 index holding regular files, in path order, stat info
+    /// included.
+    fn write_index(git_dir: &Path, work_tree: &Path, files: &[(&str, &str)]) {
+        let mut index = b"DIRC".to_vec();
+        index.extend_from_slice(&2u32.to_be_bytes());
+        index.extend_from_slice(&(files.len() as u32).to_be_bytes());
+        for (path, id) in files {
+            let meta = std::fs::metadata(work_tree.join(path)).expect("stat");
+            let mtime = meta
+                .modified()
+                .expect("mtime")
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("after epoch");
+            let mut entry = Vec::new();
+            for value in [0, 0, mtime.as_secs() as u32, mtime.subsec_nanos(), 0, 0] {
+                entry.extend_from_slice(&value.to_be_bytes());
+            }
+            for value in [0o100644, 0, 0, meta.len() as u32] {
+                entry.extend_from_slice(&value.to_be_bytes());
+            }
+            entry.extend_from_slice(&hex_decode(id).expect("id"));
+            entry.extend_from_slice(&(path.len() as u16).to_be_bytes());
+            entry.extend_from_slice(path.as_bytes());
+            // NUL-padded to a multiple of eight, with at least one NUL.
+            entry.resize((entry.len() + 8) / 8 * 8, 0);
+            index.extend_from_slice(&entry);
+        }
+        index.extend_from_slice(&[0; 20]);
+        std::fs::write(git_dir.join("index"), index).expect("write index");
+    }
+
+    #[test]
+    fn a_repo_reports_its_branch_commit_tag_and_changes() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path();
+        let git_dir = root.join(".git");
+        std::fs::create_dir_all(git_dir.join("refs/heads")).expect("mkdir");
+        std::fs::create_dir_all(root.join("game")).expect("mkdir");
+        std::fs::write(root.join("game.js"), "hello").expect("write");
+
+        let blob = write_object(&git_dir, "blob", b"hello");
+        let mut tree = b"100644 game.js\0".to_vec();
+        tree.extend_from_slice(&hex_decode(&blob).expect("id"));
+        let tree = write_object(&git_dir, "tree", &tree);
+        let commit = write_object(
+            &git_dir,
+            "commit",
+            format!(
+                "tree {}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nFix the jump\narc\n\nDetails.\n",
+                tree
+            )
+            .as_bytes(),
+        );
+        std::fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").expect("write");
+        std::fs::write(git_dir.join("refs/heads/main"), format!("{}\n", commit)).expect("write");
+        std::fs::write(
+            git_dir.join("packed-refs"),
+            format!(
+                "# pack-refs with: peeled\n{} refs/tags/v2\n{} refs/tags/v1\n^{}\n",
+                "0".repeat(40),
+                "1".repeat(40),
+                commit
+            ),
+        )
+        .expect("write");
+        // A shallow clone's tag on a commit it doesn't have is passed over.
+        std::fs::create_dir_all(git_dir.join("refs/tags")).expect("mkdir");
+        std::fs::write(
+            git_dir.join("refs/tags/v0"),
+            format!("{}\n", "2".repeat(40)),
+        )
+        .expect("write");
+        write_index(&git_dir, root, &[("game.js", &blob)]);
+
+        // Found from a directory inside the work tree.
+        let provenance = GitProvenance::read(&root.join("game"))
+            .expect("read")
+            .expect("a repo");
+        assert_eq!(
+            provenance,
+            GitProvenance {
+                commit: commit.clone(),
+                branch: Some("main".to_string()),
+                tag: Some("v1".to_string()),
+                dirty: false,
+                subject: Some("Fix the jump arc".to_string()),
+            }
+        );
+
+        std::fs::write(root.join("game.js"), "HELLO").expect("write");
+        let provenance = GitProvenance::read(root).expect("read").expect("a repo");
+        assert!(provenance.dirty);
+        assert_eq!(provenance.short_commit(), format!("{}*", &commit[..7]));
+
+        // Detached, as CI checks out.
+        std::fs::write(root.join("game.js"), "hello").expect("write");
+        std::fs::write(git_dir.join("HEAD"), format!("{}\n", commit)).expect("write");
+        let provenance = GitProvenance::read(root).expect("read").expect("a repo");
+        assert_eq!(provenance.branch, None);
+        assert!(!provenance.dirty);
+    }
+
+    #[test]
+    fn crlf_copies_and_filtered_files_are_not_changes() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path();
+        let git_dir = root.join(".git");
+        std::fs::create_dir_all(git_dir.join("objects")).expect("mkdir");
+        std::fs::create_dir_all(root.join("assets")).expect("mkdir");
+        let files = [
+            ("assets/.gitattributes", "*.pck filter=lfs diff=lfs -text\n"),
+            (
+                "assets/level.pck",
+                "version https://git-lfs.github.com/spec/v1\n",
+            ),
+            ("game.js", "jump();\nland();\n"),
+            ("main.pck", "packed\n"),
+        ];
+        let mut entries = Vec::new();
+        for (path, content) in files {
+            std::fs::write(root.join(path), content).expect("write");
+            entries.push((path, hash_object("blob", content.as_bytes())));
+        }
+        let entries: Vec<(&str, &str)> = entries.iter().map(|(p, id)| (*p, id.as_str())).collect();
+        write_index(&git_dir, root, &entries);
+        let repo = Repo::discover(root).expect("discover").expect("a repo");
+        let changed = || {
+            let index =
+                parse_index(&std::fs::read(git_dir.join("index")).expect("read")).expect("index");
+            repo.work_tree_differs_from_index(&index).expect("compare")
+        };
+        assert!(!changed());
+
+        // Checked out with autocrlf, and LFS content in place of the pointer.
+        std::fs::write(root.join("game.js"), "jump();\r\nland();\r\n").expect("write");
+        std::fs::write(root.join("assets/level.pck"), [0u8, 1, 2, 3, 4]).expect("write");
+        assert!(!changed());
+
+        // The attributes only cover their own directory.
+        std::fs::write(root.join("main.pck"), "repacked\n").expect("write");
+        assert!(changed());
+        std::fs::write(root.join("main.pck"), "packed\n").expect("write");
+
+        std::fs::write(root.join("game.js"), "jump();\r\nfall();\r\n").expect("write");
+        assert!(changed());
+    }
+
+    #[test]
+    fn packed_objects_and_offset_deltas_are_read() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path();
+        let git_dir = root.join(".git");
+        std::fs::create_dir_all(git_dir.join("refs/heads")).expect("mkdir");
+        std::fs::write(root.join("game.js"), "hello").expect("write");
+
+        let blob = hash_object("blob", b"hello");
+        let mut tree = b"100644 game.js\0".to_vec();
+        tree.extend_from_slice(&hex_decode(&blob).expect("id"));
+        let tree_id = hash_object("tree", &tree);
+        let parent = format!(
+            "tree {}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nAdd the jump\n",
+            tree_id
+        );
+        let parent_id = hash_object("commit", parent.as_bytes());
+        let commit = format!(
+            "tree {}\nparent {}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nFix the jump arc\n",
+            tree_id, parent_id
+        );
+        let commit_id = hash_object("commit", commit.as_bytes());
+        // As git packs history: the newest commit whole, the older one as a
+        // delta against it.
+        write_pack(
+            &git_dir,
+            &[
+                (blob.clone(), Packed::Whole(3, b"hello")),
+                (tree_id.clone(), Packed::Whole(2, &tree)),
+                (commit_id.clone(), Packed::Whole(1, commit.as_bytes())),
+                (
+                    parent_id.clone(),
+                    Packed::OfsDelta {
+                        base_at: 2,
+                        delta: delta_for(commit.as_bytes(), parent.as_bytes()),
+                    },
+                ),
+            ],
+        );
+        std::fs::write(git_dir.join("HEAD"), format!("{}\n", parent_id)).expect("write");
+        write_index(&git_dir, root, &[("game.js", &blob)]);
+
+        let repo = Repo::discover(root).expect("discover").expect("a repo");
+        let (kind, body) = repo.read_object(&parent_id).expect("delta");
+        assert_eq!(kind, ObjectKind::Commit);
+        assert_eq!(body, parent.as_bytes());
+
+        let provenance = GitProvenance::read(root).expect("read").expect("a repo");
+        assert_eq!(provenance.commit, parent_id);
+        assert_eq!(provenance.subject.as_deref(), Some("Add the jump"));
+        assert!(!provenance.dirty);
+    }
+
+    #[test]
+    fn a_corrupt_repo_is_an_error_not_a_panic() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let git_dir = dir.path().join(".git");
+        let blob = hash_object("blob", b"hello");
+        // A delta whose base is itself.
+        write_pack(
+            &git_dir,
+            &[(
+                blob.clone(),
+                Packed::OfsDelta {
+                    base_at: 0,
+                    delta: vec![5, 5, 5, b'h', b'e', b'l'],
+                },
+            )],
+        );
+        let repo = Repo::discover(dir.path())
+            .expect("discover")
+            .expect("a repo");
+        assert!(repo.read_object(&blob).is_err());
+        assert!(repo.read_object("not an id").is_err());
+        assert!(repo.read_object("é").is_err());
+
+        // Sizes far past what the delta holds, and a size that overflows.
+        let mut huge = vec![0];
+        huge.extend_from_slice(&[0xff; 8]);
+        huge.push(0x7f);
+        assert!(apply_delta(b"", &huge).is_err());
+        assert!(apply_delta(b"", &[0xff; 11]).is_err());
+
+        // An index claiming billions of entries it doesn't have.
+        let mut index = b"DIRC".to_vec();
+        index.extend_from_slice(&2u32.to_be_bytes());
+        index.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert!(parse_index(&index).is_err());
+
+        // A tree entry whose space comes after its NUL.
+        assert!(parse_tree(b"100644\0name x").is_err());
+    }
+
+    #[test]
+    fn deltas_copy_from_the_base_and_insert_the_rest() {
+        let base = b"the quick brown fox";
+        // A 19 byte base and a 17 byte target.
+        let mut delta = vec![19, 17];
+        delta.extend_from_slice(&[0x90, 10]); // copy offset 0, size 10
+        delta.extend_from_slice(&[3, b'r', b'e', b'd']); // insert "red"
+        delta.extend_from_slice(&[0x91, 15, 4]); // copy offset 15, size 4
+        assert_eq!(
+            apply_delta(base, &delta).expect("delta"),
+            b"the quick red fox"
+        );
+        assert!(apply_delta(b"short", &delta).is_err());
+    }
+}
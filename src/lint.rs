@@ -0,0 +1,568 @@
+//! `wavedash build lint`: the web-build mistakes that work locally and break
+//! once the game is served from wavedash.com.
+//!
+//! The play CDN serves every build under its own path prefix, from storage that
+//! is case-sensitive. So a `/Build/game.js` reference that a local server
+//! resolves from its root, or a `Player.PNG` that macOS finds as `player.png`,
+//! turns into a 404 there. The lint reads the HTML entrypoint and every JS and
+//! CSS file for asset references and checks each one against the files the push
+//! would upload. `build push` runs it too, as a warning.
+//!
+//! There's no parser behind this: references are the quoted strings and CSS
+//! `url(...)` values that end in a known asset extension. That misses paths
+//! built at runtime, and is tuned to stay quiet rather than to catch them all.
+
+use anyhow::Result;
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::uploader::{build_object_key, scan_archive, scan_directory, FileSource, ScannedFile};
+use crate::config::WavedashConfig;
+use crate::exclude::ExcludeRules;
+
+/// Larger text files are skipped rather than read: they're data, not code
+/// anyone wrote references into by hand.
+const MAX_SOURCE_BYTES: u64 = 16 * 1024 * 1024; // 16 MiB
+
+const SOURCE_EXTENSIONS: &[&str] = &["js", "mjs", "css"];
+
+/// What a string has to end in to be taken for a reference to a file.
+const ASSET_EXTENSIONS: &[&str] = &[
+    "html", "htm", "js", "mjs", "css", "json", "wasm", "pck", "data", "unityweb", "png", "jpg",
+    "jpeg", "gif", "webp", "avif", "svg", "ico", "bmp", "mp3", "ogg", "opus", "wav", "m4a", "aac",
+    "mp4", "webm", "ttf", "otf", "woff", "woff2", "glb", "gltf", "ktx2", "basis", "atlas", "fnt",
+    "xml", "br", "gz", "zip",
+];
+
+pub struct BuildLintArgs {
+    pub config_path: PathBuf,
+    pub json: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum LintKind {
+    AbsolutePath,
+    CaseMismatch,
+    Missing,
+    UnsafeFilename,
+}
+
+impl LintKind {
+    fn label(self) -> &'static str {
+        match self {
+            LintKind::AbsolutePath => "absolute path",
+            LintKind::CaseMismatch => "case mismatch",
+            LintKind::Missing => "missing file",
+            LintKind::UnsafeFilename => "unsafe filename",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(super) struct LintIssue {
+    kind: LintKind,
+    /// The file the reference is in, or the badly named file itself.
+    file: String,
+    message: String,
+}
+
+impl std::fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.file, self.message)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LintReport<'a> {
+    upload_dir: PathBuf,
+    issues: &'a [LintIssue],
+}
+
+pub async fn handle_build_lint(args: BuildLintArgs) -> Result<()> {
+    let BuildLintArgs { config_path, json } = args;
+
+    let wavedash_config = WavedashConfig::load(&config_path)?;
+    let (config_dir, upload_dir, archive) =
+        super::resolve_push_source(&config_path, &wavedash_config, None)?;
+    let rules = ExcludeRules::load(&upload_dir, config_dir, wavedash_config.excludes())?;
+    let scan = match archive {
+        Some(archive) => {
+            let listing = archive.list()?;
+            scan_archive(
+                Arc::new(archive),
+                &listing,
+                &rules,
+                wavedash_config.symlinks(),
+            )?
+        }
+        None => scan_directory(&upload_dir, &rules, wavedash_config.symlinks())?,
+    };
+    scan.warn_about_links();
+    if scan.files.is_empty() {
+        anyhow::bail!("No files found in {}", upload_dir.display());
+    }
+
+    let issues = lint_build(&scan.files, wavedash_config.entrypoint()?)?;
+    if json {
+        let report = LintReport {
+            upload_dir: upload_dir.canonicalize()?,
+            issues: &issues,
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if issues.is_empty() {
+        println!("✓ No problems found in {} files", scan.files.len());
+    } else {
+        for issue in &issues {
+            println!("{}  {}", issue.kind.label().yellow().bold(), issue);
+        }
+    }
+
+    if !issues.is_empty() {
+        anyhow::bail!(
+            "{} problem{} that will likely break the game on wavedash.com",
+            issues.len(),
+            if issues.len() == 1 { "" } else { "s" }
+        );
+    }
+    Ok(())
+}
+
+/// The lint as `build push` runs it: a warning on stderr that doesn't stop the
+/// push, since a reference the lint can't follow may still work.
+pub(super) fn warn_before_push(files: &[ScannedFile], entrypoint: Option<&str>) -> Result<()> {
+    const SHOWN: usize = 10;
+
+    let issues = lint_build(files, entrypoint)?;
+    if issues.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "{} Found {} problem{} that will likely break the game on wavedash.com:",
+        "Warning:".yellow().bold(),
+        issues.len(),
+        if issues.len() == 1 { "" } else { "s" }
+    );
+    for issue in issues.iter().take(SHOWN) {
+        eprintln!("  {}", issue);
+    }
+    if issues.len() > SHOWN {
+        eprintln!(
+            "  ...and {} more. Run `wavedash build lint` to see them all.",
+            issues.len() - SHOWN
+        );
+    }
+    Ok(())
+}
+
+/// Everything wrong with the build in `files`, sorted by kind and file.
+/// `entrypoint` is the page the game boots from; JS references resolve
+/// against its directory, the way the browser resolves them.
+pub(super) fn lint_build(
+    files: &[ScannedFile],
+    entrypoint: Option<&str>,
+) -> Result<Vec<LintIssue>> {
+    let paths: Vec<String> = files
+        .iter()
+        .map(|f| build_object_key("", &f.relative_path))
+        .collect();
+    let build = BuildFiles::new(&paths);
+    let page_dir = entrypoint.map_or("", |entrypoint| {
+        parent_of(entrypoint.trim_start_matches("./"))
+    });
+
+    let mut issues = Vec::new();
+    for path in &paths {
+        if let Some(unsafe_chars) = unsafe_chars(path) {
+            issues.push(LintIssue {
+                kind: LintKind::UnsafeFilename,
+                file: path.clone(),
+                message: format!(
+                    "has characters that aren't safe in a URL ({}); rename it using letters, digits, '-', '_' and '.'",
+                    unsafe_chars
+                ),
+            });
+        }
+    }
+
+    for (path, text) in read_sources(files, &paths, entrypoint)? {
+        // Pages and stylesheets resolve against themselves. A script resolves
+        // against the page that loaded it, but engines also load files next
+        // to their own script, so either counts.
+        let own_dir = parent_of(&path);
+        let bases: Vec<&str> = if is_script(&path) {
+            vec![page_dir, own_dir]
+        } else {
+            vec![own_dir]
+        };
+        for reference in references_in(&text) {
+            if let Some(issue) = build.check(&path, &reference, &bases) {
+                issues.push(issue);
+            }
+        }
+    }
+
+    issues.sort_by(|a, b| a.kind.cmp(&b.kind).then(a.file.cmp(&b.file)));
+    Ok(issues)
+}
+
+/// The build's file paths, looked up exactly and by case.
+struct BuildFiles<'a> {
+    exact: HashSet<&'a str>,
+    by_lowercase: HashMap<String, &'a str>,
+}
+
+impl<'a> BuildFiles<'a> {
+    fn new(paths: &'a [String]) -> Self {
+        Self {
+            exact: paths.iter().map(String::as_str).collect(),
+            by_lowercase: paths
+                .iter()
+                .map(|path| (path.to_lowercase(), path.as_str()))
+                .collect(),
+        }
+    }
+
+    /// The file `path` only matches ignoring case, if there is one.
+    fn case_match(&self, path: &str) -> Option<&'a str> {
+        self.by_lowercase.get(&path.to_lowercase()).copied()
+    }
+
+    /// A path some file in the build ends with, past a directory boundary. A
+    /// script that sets its own base path (`load.setPath("assets")`) refers
+    /// to `assets/player.png` as just `player.png`.
+    fn suffix_match(&self, reference: &str) -> bool {
+        let suffix = format!("/{}", reference);
+        self.exact.iter().any(|path| path.ends_with(&suffix))
+    }
+
+    fn check(&self, source: &str, reference: &str, bases: &[&str]) -> Option<LintIssue> {
+        let issue = |kind, message| {
+            Some(LintIssue {
+                kind,
+                file: source.to_string(),
+                message,
+            })
+        };
+
+        if let Some(rooted) = reference.strip_prefix('/') {
+            let suggestion = normalize("", rooted)
+                .filter(|target| self.exact.contains(target.as_str()))
+                .map(|target| format!("; use '{}'", relative_to(parent_of(source), &target)))
+                .unwrap_or_default();
+            return issue(
+                LintKind::AbsolutePath,
+                format!(
+                    "'{}' is an absolute path, which won't resolve under the play CDN's prefix{}",
+                    reference, suggestion
+                ),
+            );
+        }
+
+        let candidates: Vec<String> = bases
+            .iter()
+            .filter_map(|base| normalize(base, reference))
+            .collect();
+        if candidates.iter().any(|c| self.exact.contains(c.as_str())) {
+            return None;
+        }
+        if let Some(actual) = candidates.iter().find_map(|c| self.case_match(c)) {
+            return issue(
+                LintKind::CaseMismatch,
+                format!(
+                    "'{}' only matches '{}' when case is ignored, and the CDN doesn't ignore it",
+                    reference, actual
+                ),
+            );
+        }
+        if is_script(source) && self.suffix_match(reference) {
+            return None;
+        }
+        let Some(resolved) = candidates.first() else {
+            return issue(
+                LintKind::Missing,
+                format!("'{}' points outside the build", reference),
+            );
+        };
+        issue(
+            LintKind::Missing,
+            format!("'{}' is not in the build ({})", reference, resolved),
+        )
+    }
+}
+
+/// The text of the files the lint reads: the entrypoint and every JS and CSS
+/// file. Files in an archive are read in one pass over it.
+fn read_sources(
+    files: &[ScannedFile],
+    paths: &[String],
+    entrypoint: Option<&str>,
+) -> Result<Vec<(String, String)>> {
+    let entrypoint = entrypoint.map(|e| e.trim_start_matches("./"));
+    let wanted: Vec<(&ScannedFile, &String)> = files
+        .iter()
+        .zip(paths)
+        .filter(|(file, path)| {
+            file.size <= MAX_SOURCE_BYTES
+                && (Some(path.as_str()) == entrypoint || has_extension(path, SOURCE_EXTENSIONS))
+        })
+        .collect();
+
+    let mut sources = Vec::with_capacity(wanted.len());
+    let archive = wanted.iter().find_map(|(file, _)| match &file.source {
+        FileSource::Archive(archive) => Some(archive.clone()),
+        FileSource::Disk(_) => None,
+    });
+    match archive {
+        Some(archive) => {
+            let wanted: HashSet<&str> = wanted.iter().map(|(_, path)| path.as_str()).collect();
+            archive.for_each_entry(|entry, reader| {
+                if wanted.contains(entry.path.as_str()) {
+                    sources.push((entry.path.clone(), read_text(reader)?));
+                }
+                Ok(())
+            })?;
+        }
+        None => {
+            for (file, path) in wanted {
+                sources.push((path.clone(), file.read_with(read_text)?));
+            }
+        }
+    }
+    Ok(sources)
+}
+
+fn read_text(reader: &mut dyn Read) -> Result<String> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Every distinct string in `text` that looks like a reference to a file in
+/// the build, in the order they appear.
+fn references_in(text: &str) -> Vec<String> {
+    let bytes = text.as_bytes();
+    let mut raw = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'"' | b'\'' | b'`') => {
+                let start = i + 1;
+                let mut end = start;
+                let mut closed = false;
+                while end < bytes.len() {
+                    match bytes[end] {
+                        b'\\' => end += 2,
+                        b'\n' if quote != b'`' => break,
+                        c if c == quote => {
+                            closed = true;
+                            break;
+                        }
+                        _ => end += 1,
+                    }
+                }
+                if closed {
+                    raw.push(&text[start..end]);
+                }
+                // An unclosed quote (an apostrophe in a comment, usually)
+                // gives up on the rest of its line rather than rescanning it.
+                i = end + 1;
+            }
+            b'u' if bytes[i..].starts_with(b"url(") => {
+                let start = i + 4;
+                match bytes[start..].iter().position(|&b| b == b')') {
+                    Some(len) => {
+                        let value = text[start..start + len].trim();
+                        raw.push(value.trim_matches(|c| c == '"' || c == '\''));
+                        i = start + len + 1;
+                    }
+                    None => i = start,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    let mut seen = HashSet::new();
+    raw.into_iter()
+        .filter_map(as_reference)
+        .filter(|reference| seen.insert(reference.clone()))
+        .collect()
+}
+
+/// `raw` as a path into the build, or `None` when it's a URL, a pattern, or
+/// doesn't name a file of a type the lint knows.
+fn as_reference(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    if raw.is_empty()
+        || raw.starts_with('#')
+        || raw.starts_with("//")
+        || raw.contains(|c: char| c.is_whitespace() || "*<>{}$\\|".contains(c))
+    {
+        return None;
+    }
+    // `https:`, `data:`, `blob:`: anything with a scheme isn't ours.
+    if let Some(colon) = raw.find(':') {
+        if !raw[..colon].contains('/') {
+            return None;
+        }
+    }
+    let path = raw.split(['?', '#']).next().unwrap_or_default();
+    if !has_extension(path, ASSET_EXTENSIONS) {
+        return None;
+    }
+    let decoded = urlencoding::decode(path).map_or_else(|_| path.to_string(), |d| d.into_owned());
+    Some(decoded)
+}
+
+/// `reference` resolved against the directory `base`, with `.` and `..`
+/// applied; `None` when it climbs out of the build.
+fn normalize(base: &str, reference: &str) -> Option<String> {
+    let mut parts: Vec<&str> = base.split('/').filter(|p| !p.is_empty()).collect();
+    for part in reference.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// How a file in `from_dir` refers to `target` without a leading `/`.
+fn relative_to(from_dir: &str, target: &str) -> String {
+    let depth = from_dir.split('/').filter(|p| !p.is_empty()).count();
+    format!("{}{}", "../".repeat(depth), target)
+}
+
+fn parent_of(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+fn has_extension(path: &str, extensions: &[&str]) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match name.rsplit_once('.') {
+        // `.png` on its own is a dotfile, or an extension check, not a file.
+        Some((stem, ext)) if !stem.is_empty() => {
+            extensions.contains(&ext.to_ascii_lowercase().as_str())
+        }
+        _ => false,
+    }
+}
+
+fn is_script(path: &str) -> bool {
+    has_extension(path, &["js", "mjs"])
+}
+
+/// The characters in `path` that a URL would have to escape, or `None` when
+/// it's letters, digits and `-._~` throughout.
+fn unsafe_chars(path: &str) -> Option<String> {
+    let mut found: Vec<char> = Vec::new();
+    for c in path.chars() {
+        let safe = c.is_ascii_alphanumeric() || "-._~/".contains(c);
+        if !safe && !found.contains(&c) {
+            found.push(c);
+        }
+    }
+    (!found.is_empty()).then(|| {
+        found
+            .iter()
+            .map(|c| format!("{:?}", c))
+            .collect::<Vec<_>>()
+            .join(", ")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_are_quoted_paths_and_css_urls_with_asset_extensions() {
+        let text = r#"
+            <script src="Build/game.js?v=3"></script>
+            <link href='https://fonts.example.com/x.css'>
+            // don't trip over this apostrophe
+            load("sprites/hero%20idle.png"); load(`a.png`); "image/png"; ".png"
+            .bg { background: url(img/sky.webp) } .x { background: url("data:image/png;base64,AA") }
+            fetch("/abs/levels.json"); "*.png"; "${base}.png"
+        "#;
+        assert_eq!(
+            references_in(text),
+            vec![
+                "Build/game.js",
+                "sprites/hero idle.png",
+                "a.png",
+                "img/sky.webp",
+                "/abs/levels.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_lint_reports_each_kind_of_problem() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path();
+        let files = vec![
+            ScannedFile::written(
+                root,
+                "index.html",
+                r#"<script src="/Build/loader.js"></script><img src="Player.PNG"><img src="gone.png">"#,
+            ),
+            ScannedFile::written(
+                root,
+                "Build/loader.js",
+                r#"load("Build/game.wasm"); load("hero.png")"#,
+            ),
+            ScannedFile::written(root, "Build/game.wasm", ""),
+            ScannedFile::written(root, "assets/hero.png", ""),
+            ScannedFile::written(root, "player.png", ""),
+            ScannedFile::written(root, "css/site.css", "body { background: url(../bg.jpg) }"),
+            ScannedFile::written(root, "bg.jpg", ""),
+            ScannedFile::written(root, "my level #2.json", "{}"),
+        ];
+
+        let issues = lint_build(&files, Some("index.html")).expect("lint");
+        let summary: Vec<(LintKind, &str)> = issues
+            .iter()
+            .map(|issue| (issue.kind, issue.file.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (LintKind::AbsolutePath, "index.html"),
+                (LintKind::CaseMismatch, "index.html"),
+                (LintKind::Missing, "index.html"),
+                (LintKind::UnsafeFilename, "my level #2.json"),
+            ]
+        );
+        assert!(
+            issues[0].message.ends_with("use 'Build/loader.js'"),
+            "got: {}",
+            issues[0].message
+        );
+        assert!(
+            issues[1].message.contains("'player.png'"),
+            "got: {}",
+            issues[1].message
+        );
+    }
+
+    #[test]
+    fn paths_resolve_relative_to_their_base() {
+        assert_eq!(
+            normalize("css", "../img/a.png").as_deref(),
+            Some("img/a.png")
+        );
+        assert_eq!(normalize("", "./a.png").as_deref(), Some("a.png"));
+        assert_eq!(normalize("", "../a.png"), None);
+        assert_eq!(relative_to("sub/dir", "a.js"), "../../a.js");
+    }
+}
@@ -1,16 +1,24 @@
 mod achievements;
+mod archive;
 mod auth;
 mod browser;
+mod build_step;
 mod builds;
 mod clear_playtest_data;
 mod config;
 mod dev;
+mod exclude;
+mod export;
 mod file_staging;
+mod git;
 mod init;
+mod manifest;
+mod object_headers;
 mod publish;
 mod stats;
 mod updater;
 mod welcome;
+mod workspace;
 
 use achievements::{
     handle_achievement_create, handle_achievement_delete, handle_achievement_list,
@@ -18,19 +26,26 @@ use achievements::{
 };
 use anyhow::Result;
 use auth::{login_with_browser, AuthManager, AuthSource};
-use builds::handle_build_push;
+use builds::{
+    handle_build_analyze, handle_build_delete, handle_build_diff, handle_build_download,
+    handle_build_lint, handle_build_list, handle_build_prune, handle_build_push, handle_build_show,
+    BuildAnalyzeArgs, BuildDiffArgs, BuildDownloadArgs, BuildLintArgs, BuildPruneArgs,
+    BuildPushArgs,
+};
 use clap::{Parser, Subcommand};
 use clear_playtest_data::{handle_clear_playtest_data, ClearPlaytestDataArgs};
 use colored::Colorize;
 use config::{resolve_game_id, UploadSource};
-use dev::handle_dev;
+use dev::{handle_dev, DEFAULT_CONFIG};
+use export::handle_export_godot;
 use init::{
     handle_init, handle_project_create, handle_project_list, handle_team_create, handle_team_list,
 };
-use publish::{handle_publish, PublishArgs};
+use publish::{handle_publish, PublishArgs, ReleaseNoteArgs};
 use stats::{handle_stat_create, handle_stat_delete, handle_stat_update};
 use std::io::{IsTerminal, Read};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use workspace::WorkspaceArgs;
 
 fn mask_token(token: &str) -> String {
     if token.len() > 10 {
@@ -59,6 +74,13 @@ fn parse_non_empty_arg(value: &str) -> Result<String, String> {
 struct Cli {
     #[arg(long, global = true, help = "Enable verbose output")]
     verbose: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Overlay [profiles.<NAME>] from wavedash.toml (override with WAVEDASH_PROFILE)",
+        value_parser = parse_non_empty_arg
+    )]
+    profile: Option<String>,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -75,6 +97,11 @@ enum Commands {
         #[command(subcommand)]
         action: BuildCommands,
     },
+    #[command(about = "Export the project for the web with its engine's own tools")]
+    Export {
+        #[command(subcommand)]
+        action: ExportCommands,
+    },
     Dev {
         #[arg(
             short = 'c',
@@ -87,6 +114,11 @@ enum Commands {
             help = "Don't automatically open the browser; just print the local URL"
         )]
         no_open: bool,
+        #[arg(
+            long = "no-build",
+            help = "Serve upload_dir as it is, without running the [build] command"
+        )]
+        no_build: bool,
         #[arg(
             long = "upload-source",
             value_enum,
@@ -94,6 +126,8 @@ enum Commands {
             help = "Attribute the build to the tool running the CLI instead of the CLI itself"
         )]
         upload_source: Option<UploadSource>,
+        #[command(flatten)]
+        workspace: WorkspaceArgs,
     },
     #[command(
         about = "Publish an uploaded build to wavedash.com",
@@ -112,18 +146,8 @@ enum Commands {
             default_value = "./wavedash.toml"
         )]
         config: PathBuf,
-        #[arg(long, help = "Release title")]
-        title: Option<String>,
-        #[arg(long, help = "Release summary")]
-        summary: Option<String>,
-        #[arg(long, help = "Added change item", action = clap::ArgAction::Append, num_args = 1)]
-        added: Vec<String>,
-        #[arg(long, help = "Removed change item", action = clap::ArgAction::Append, num_args = 1)]
-        removed: Vec<String>,
-        #[arg(long, help = "Fixed change item", action = clap::ArgAction::Append, num_args = 1)]
-        fixed: Vec<String>,
-        #[arg(long, help = "Adjusted change item", action = clap::ArgAction::Append, num_args = 1)]
-        adjusted: Vec<String>,
+        #[command(flatten)]
+        notes: ReleaseNoteArgs,
         #[arg(
             long = "yes",
             short = 'y',
@@ -266,6 +290,323 @@ enum BuildCommands {
             help = "Attribute the build to the tool running the CLI instead of the CLI itself"
         )]
         upload_source: Option<UploadSource>,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Push the build inside this .zip or .tar.gz instead of upload_dir"
+        )]
+        archive: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Upload every file, even if the previous build already has it"
+        )]
+        full: bool,
+        #[arg(
+            long,
+            conflicts_with = "full",
+            help = "Finish the interrupted push for this game instead of starting a new build"
+        )]
+        resume: bool,
+        #[arg(
+            long = "no-build",
+            help = "Push upload_dir as it is, without running the [build] command. A push with --archive or --resume never runs it"
+        )]
+        no_build: bool,
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = builds::uploader::DEFAULT_MAX_RETRIES,
+            help = "Retries per file for transient upload errors"
+        )]
+        retries: u32,
+        #[arg(
+            long,
+            value_name = "N|auto",
+            value_parser = config::parse_concurrency,
+            help = "Requests in flight at once, or \"auto\" to adapt to the connection (defaults to concurrency in wavedash.toml. override with WAVEDASH_UPLOAD_CONCURRENCY)"
+        )]
+        concurrency: Option<config::Concurrency>,
+        #[arg(
+            long = "max-rate",
+            value_name = "RATE",
+            value_parser = config::parse_byte_rate,
+            help = "Cap upload bandwidth, e.g. 5MB/s (override with WAVEDASH_MAX_UPLOAD_RATE)"
+        )]
+        max_rate: Option<u64>,
+        #[arg(
+            long,
+            value_name = "TARGET",
+            value_parser = config::parse_storage_target,
+            hide = true,
+            help = "Store the build's objects in memory, fs:<dir> or s3:<endpoint> instead of R2, for testing; debug builds only (override with WAVEDASH_STORAGE)"
+        )]
+        storage: Option<config::StorageTarget>,
+        #[arg(
+            long,
+            conflicts_with = "dry_run",
+            help = "Check every uploaded file's size and ETag in the bucket before marking the build complete"
+        )]
+        verify: bool,
+        #[arg(
+            long,
+            conflicts_with = "dry_run",
+            help = "Publish the build as soon as it's uploaded, without asking. Takes the same release note flags as `wavedash publish`"
+        )]
+        publish: bool,
+        #[command(flatten)]
+        notes: ReleaseNoteArgs,
+        #[arg(
+            long,
+            conflicts_with = "resume",
+            help = "Resolve the config and list what would be uploaded, without contacting wavedash"
+        )]
+        dry_run: bool,
+        #[arg(long, requires = "dry_run", help = "Output the dry-run report as JSON")]
+        json: bool,
+        #[command(flatten)]
+        workspace: WorkspaceArgs,
+    },
+    #[command(about = "List a game's builds, newest first")]
+    List {
+        #[arg(
+            long = "game-id",
+            value_parser = parse_non_empty_arg,
+            help = "Game ID (defaults to game_id in wavedash.toml. override with WAVEDASH_GAME_ID)"
+        )]
+        game_id: Option<String>,
+        #[arg(
+            short = 'c',
+            long = "config",
+            help = "Path to wavedash.toml config file",
+            default_value = "./wavedash.toml"
+        )]
+        config: PathBuf,
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = builds::DEFAULT_LIST_LIMIT,
+            help = "How many builds to show"
+        )]
+        limit: usize,
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+        #[command(flatten)]
+        workspace: WorkspaceArgs,
+    },
+    #[command(about = "Show one build's details and its playtest URL")]
+    Show {
+        #[arg(help = "Build ID, as printed by `build push` or `build list`")]
+        build_id: String,
+        #[arg(
+            long = "game-id",
+            value_parser = parse_non_empty_arg,
+            help = "Game ID (defaults to game_id in wavedash.toml. override with WAVEDASH_GAME_ID)"
+        )]
+        game_id: Option<String>,
+        #[arg(
+            short = 'c',
+            long = "config",
+            help = "Path to wavedash.toml config file",
+            default_value = "./wavedash.toml"
+        )]
+        config: PathBuf,
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+    },
+    #[command(
+        about = "Report what's taking up space in the upload directory, and fail if it's over the [budgets] in wavedash.toml"
+    )]
+    Analyze {
+        #[arg(
+            short = 'c',
+            long = "config",
+            help = "Path to wavedash.toml config file",
+            default_value = "./wavedash.toml"
+        )]
+        config: PathBuf,
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 10,
+            help = "How many of the largest files to list"
+        )]
+        top: usize,
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+        #[command(flatten)]
+        workspace: WorkspaceArgs,
+    },
+    #[command(
+        about = "Check the upload directory for references that break on wavedash.com: missing files, case mismatches, absolute paths and unsafe filenames"
+    )]
+    Lint {
+        #[arg(
+            short = 'c',
+            long = "config",
+            help = "Path to wavedash.toml config file",
+            default_value = "./wavedash.toml"
+        )]
+        config: PathBuf,
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+        #[command(flatten)]
+        workspace: WorkspaceArgs,
+    },
+    #[command(
+        about = "Compare two builds, or a build and the upload directory: files added, removed and modified"
+    )]
+    Diff {
+        #[arg(help = "Build ID to compare from, or `live` for the build players currently get")]
+        from: String,
+        #[arg(
+            help = "Build ID to compare to, or `live`. Omit to compare against the upload directory"
+        )]
+        to: Option<String>,
+        #[arg(
+            long = "game-id",
+            value_parser = parse_non_empty_arg,
+            help = "Game ID (defaults to game_id in wavedash.toml. override with WAVEDASH_GAME_ID)"
+        )]
+        game_id: Option<String>,
+        #[arg(
+            short = 'c',
+            long = "config",
+            help = "Path to wavedash.toml config file",
+            default_value = "./wavedash.toml"
+        )]
+        config: PathBuf,
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+        #[arg(
+            long = "max-delta",
+            value_name = "SIZE",
+            value_parser = config::parse_byte_size,
+            help = "Fail if the total size changes by more than this (e.g. 5MB)"
+        )]
+        max_delta: Option<u64>,
+        #[arg(
+            long = "max-file-delta",
+            value_name = "SIZE",
+            value_parser = config::parse_byte_size,
+            help = "Fail if any one file is added, removed or changes by more than this"
+        )]
+        max_file_delta: Option<u64>,
+    },
+    #[command(about = "Download an uploaded build's files, exactly as they're served")]
+    Download {
+        #[arg(help = "Build ID, as printed by `build push` or `build list`")]
+        build_id: String,
+        #[arg(
+            short = 'o',
+            long = "out",
+            value_name = "DIR",
+            help = "Directory to download into (must be new or empty)"
+        )]
+        out: PathBuf,
+        #[arg(
+            long = "game-id",
+            value_parser = parse_non_empty_arg,
+            help = "Game ID (defaults to game_id in wavedash.toml. override with WAVEDASH_GAME_ID)"
+        )]
+        game_id: Option<String>,
+        #[arg(
+            short = 'c',
+            long = "config",
+            help = "Path to wavedash.toml config file",
+            default_value = "./wavedash.toml"
+        )]
+        config: PathBuf,
+    },
+    #[command(about = "Delete a build and its uploaded files")]
+    Delete {
+        #[arg(help = "Build ID, as printed by `build push` or `build list`")]
+        build_id: String,
+        #[arg(
+            long = "game-id",
+            value_parser = parse_non_empty_arg,
+            help = "Game ID (defaults to game_id in wavedash.toml. override with WAVEDASH_GAME_ID)"
+        )]
+        game_id: Option<String>,
+        #[arg(
+            short = 'c',
+            long = "config",
+            help = "Path to wavedash.toml config file",
+            default_value = "./wavedash.toml"
+        )]
+        config: PathBuf,
+        #[arg(
+            long = "yes",
+            short = 'y',
+            visible_alias = "force",
+            help = "Skip confirmation (required when non-interactive)"
+        )]
+        yes: bool,
+    },
+    #[command(
+        about = "Delete old builds, keeping the newest and never the live one",
+        group = clap::ArgGroup::new("retention").args(["keep", "older_than"]).required(true).multiple(true)
+    )]
+    Prune {
+        #[arg(
+            long = "game-id",
+            value_parser = parse_non_empty_arg,
+            help = "Game ID (defaults to game_id in wavedash.toml. override with WAVEDASH_GAME_ID)"
+        )]
+        game_id: Option<String>,
+        #[arg(
+            short = 'c',
+            long = "config",
+            help = "Path to wavedash.toml config file",
+            default_value = "./wavedash.toml"
+        )]
+        config: PathBuf,
+        #[arg(long, value_name = "N", help = "Always keep the N newest builds")]
+        keep: Option<usize>,
+        #[arg(
+            long = "older-than",
+            value_name = "AGE",
+            value_parser = builds::parse_age,
+            help = "Only delete builds older than this (e.g. 30d, 12h, 2w)"
+        )]
+        older_than: Option<std::time::Duration>,
+        #[arg(
+            long,
+            help = "Prune builds created by `wavedash dev` instead of uploaded ones"
+        )]
+        dev: bool,
+        #[arg(long, help = "List the builds that would be deleted, and stop")]
+        dry_run: bool,
+        #[arg(
+            long = "yes",
+            short = 'y',
+            visible_alias = "force",
+            help = "Skip confirmation (required when non-interactive)"
+        )]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExportCommands {
+    #[command(
+        about = "Export the Godot project next to wavedash.toml into upload_dir with a headless Godot (GODOT_BIN or godot on PATH)"
+    )]
+    Godot {
+        #[arg(
+            short = 'c',
+            long = "config",
+            help = "Path to wavedash.toml config file",
+            default_value = "./wavedash.toml"
+        )]
+        config: PathBuf,
+        #[arg(
+            long,
+            value_parser = parse_non_empty_arg,
+            help = "Export preset to use (defaults to the project's only Web preset)"
+        )]
+        preset: Option<String>,
+        #[command(flatten)]
+        workspace: WorkspaceArgs,
     },
 }
 
@@ -386,6 +727,8 @@ enum AchievementCommands {
         config: PathBuf,
         #[arg(long, help = "Output as JSON")]
         json: bool,
+        #[command(flatten)]
+        workspace: WorkspaceArgs,
     },
     #[command(about = "Create a new achievement for a game")]
     Create {
@@ -483,6 +826,81 @@ enum AchievementCommands {
     },
 }
 
+/// What `--all` and `--package` need from a command that takes them.
+struct WorkspaceRun<'a> {
+    args: &'a WorkspaceArgs,
+    /// The root or a member; either finds the workspace.
+    config: &'a Path,
+    /// `--game-id`, where the command has one.
+    game_id: Option<&'a str>,
+    output: workspace::Output,
+}
+
+impl Commands {
+    fn workspace_run(&self) -> Option<WorkspaceRun<'_>> {
+        let grouped = |json: &bool| workspace::Output::Grouped { json: *json };
+        let run = |args, config, game_id, output| WorkspaceRun {
+            args,
+            config,
+            game_id,
+            output,
+        };
+        match self {
+            Commands::Build { action } => match action {
+                BuildCommands::Push {
+                    config,
+                    json,
+                    workspace,
+                    ..
+                }
+                | BuildCommands::Lint {
+                    config,
+                    json,
+                    workspace,
+                }
+                | BuildCommands::Analyze {
+                    config,
+                    json,
+                    workspace,
+                    ..
+                } => Some(run(workspace, config, None, grouped(json))),
+                BuildCommands::List {
+                    game_id,
+                    config,
+                    json,
+                    workspace,
+                    ..
+                } => Some(run(workspace, config, game_id.as_deref(), grouped(json))),
+                _ => None,
+            },
+            Commands::Export {
+                action:
+                    ExportCommands::Godot {
+                        config, workspace, ..
+                    },
+            } => Some(run(workspace, config, None, grouped(&false))),
+            Commands::Dev {
+                config, workspace, ..
+            } => Some(run(
+                workspace,
+                config.as_deref().unwrap_or(Path::new(DEFAULT_CONFIG)),
+                None,
+                workspace::Output::Streamed,
+            )),
+            Commands::Achievement {
+                action:
+                    AchievementCommands::List {
+                        game_id,
+                        config,
+                        json,
+                        workspace,
+                    },
+            } => Some(run(workspace, config, game_id.as_deref(), grouped(json))),
+            _ => None,
+        }
+    }
+}
+
 fn env_flag_enabled(name: &str) -> bool {
     std::env::var(name)
         .map(|value| {
@@ -517,6 +935,9 @@ async fn main() {
 
 async fn run() -> Result<()> {
     let cli = Cli::parse();
+    if let Some(profile) = cli.profile {
+        config::select_profile(profile);
+    }
 
     // Bare `wavedash` (no subcommand) is the home screen: show the splash and
     // point at --help.
@@ -531,19 +952,38 @@ async fn run() -> Result<()> {
         welcome::show_first_run_if_needed();
     }
 
-    // Check for updates in background, but skip commands with their own focused prompts.
-    let update_handle = if matches!(
-        command,
-        Commands::Update
-            | Commands::Auth {
-                action: AuthCommands::Login { .. },
-            }
-    ) {
+    // Check for updates in background, but skip commands with their own focused
+    // prompts, and workspace members: the run that started them already checked.
+    let update_handle = if std::env::var_os(workspace::ENV_MEMBER).is_some()
+        || matches!(
+            command,
+            Commands::Update
+                | Commands::Auth {
+                    action: AuthCommands::Login { .. },
+                }
+        ) {
         None
     } else {
         Some(updater::check_for_update())
     };
 
+    // `--all` and `--package` run this same command once per member, in
+    // processes of their own, so nothing below runs here.
+    if let Some(target) = command.workspace_run() {
+        if target.args.is_set() && target.game_id.is_some() {
+            anyhow::bail!(
+                "--game-id names a single game, so it can't be combined with --all or --package"
+            );
+        }
+        if let Some(members) = target.args.members(target.config)? {
+            let result = workspace::run_members(&members, target.output).await;
+            if let Some(handle) = update_handle {
+                let _ = handle.join();
+            }
+            return result;
+        }
+    }
+
     match command {
         Commands::Init => {
             handle_init().await?;
@@ -631,25 +1071,178 @@ async fn run() -> Result<()> {
                 config,
                 message,
                 upload_source,
+                archive,
+                full,
+                resume,
+                no_build,
+                retries,
+                concurrency,
+                max_rate,
+                storage,
+                verify,
+                publish,
+                notes,
+                dry_run,
+                json,
+                workspace: _,
             } => {
-                handle_build_push(
-                    config,
-                    cli.verbose,
+                if !publish && !notes.is_empty() {
+                    anyhow::bail!(
+                        "--title, --summary, --added, --removed, --fixed and --adjusted are release notes for --publish"
+                    );
+                }
+                handle_build_push(BuildPushArgs {
+                    config_path: config,
+                    verbose: cli.verbose,
                     message,
-                    upload_source.unwrap_or_default(),
-                )
+                    upload_source: upload_source.unwrap_or_default(),
+                    archive,
+                    full,
+                    resume,
+                    no_build,
+                    retries,
+                    concurrency,
+                    max_rate,
+                    storage,
+                    verify,
+                    publish: publish.then_some(notes),
+                    dry_run,
+                    json,
+                })
+                .await?;
+            }
+            BuildCommands::List {
+                game_id,
+                config,
+                limit,
+                json,
+                workspace: _,
+            } => {
+                let game_id = resolve_game_id(game_id.as_deref(), &config)?;
+                handle_build_list(&game_id, limit, json).await?;
+            }
+            BuildCommands::Show {
+                build_id,
+                game_id,
+                config,
+                json,
+            } => {
+                let game_id = resolve_game_id(game_id.as_deref(), &config)?;
+                handle_build_show(&game_id, &build_id, json).await?;
+            }
+            BuildCommands::Lint {
+                config,
+                json,
+                workspace: _,
+            } => {
+                handle_build_lint(BuildLintArgs {
+                    config_path: config,
+                    json,
+                })
+                .await?;
+            }
+            BuildCommands::Analyze {
+                config,
+                top,
+                json,
+                workspace: _,
+            } => {
+                handle_build_analyze(BuildAnalyzeArgs {
+                    config_path: config,
+                    verbose: cli.verbose,
+                    json,
+                    top,
+                })
                 .await?;
             }
+            BuildCommands::Diff {
+                from,
+                to,
+                game_id,
+                config,
+                json,
+                max_delta,
+                max_file_delta,
+            } => {
+                let game_id = resolve_game_id(game_id.as_deref(), &config)?;
+                handle_build_diff(BuildDiffArgs {
+                    game_id: &game_id,
+                    config_path: config,
+                    from: &from,
+                    to: to.as_deref(),
+                    verbose: cli.verbose,
+                    json,
+                    max_delta,
+                    max_file_delta,
+                })
+                .await?;
+            }
+            BuildCommands::Download {
+                build_id,
+                out,
+                game_id,
+                config,
+            } => {
+                let game_id = resolve_game_id(game_id.as_deref(), &config)?;
+                handle_build_download(BuildDownloadArgs {
+                    game_id: &game_id,
+                    build_id: &build_id,
+                    out: &out,
+                    verbose: cli.verbose,
+                })
+                .await?;
+            }
+            BuildCommands::Delete {
+                build_id,
+                game_id,
+                config,
+                yes,
+            } => {
+                let game_id = resolve_game_id(game_id.as_deref(), &config)?;
+                handle_build_delete(&game_id, &build_id, yes).await?;
+            }
+            BuildCommands::Prune {
+                game_id,
+                config,
+                keep,
+                older_than,
+                dev,
+                dry_run,
+                yes,
+            } => {
+                let game_id = resolve_game_id(game_id.as_deref(), &config)?;
+                handle_build_prune(BuildPruneArgs {
+                    game_id: &game_id,
+                    keep,
+                    older_than,
+                    dev,
+                    dry_run,
+                    yes,
+                })
+                .await?;
+            }
+        },
+        Commands::Export { action } => match action {
+            ExportCommands::Godot {
+                config,
+                preset,
+                workspace: _,
+            } => {
+                handle_export_godot(config, preset).await?;
+            }
         },
         Commands::Dev {
             config,
             no_open,
+            no_build,
             upload_source,
+            workspace: _,
         } => {
             handle_dev(
                 config,
                 cli.verbose,
                 no_open,
+                no_build,
                 upload_source.unwrap_or_default(),
             )
             .await?;
@@ -657,23 +1250,13 @@ async fn run() -> Result<()> {
         Commands::Publish {
             config,
             build_id,
-            title,
-            summary,
-            added,
-            removed,
-            fixed,
-            adjusted,
+            notes,
             yes,
         } => {
             handle_publish(PublishArgs {
                 config_path: config,
                 build_id,
-                title,
-                summary,
-                added,
-                removed,
-                fixed,
-                adjusted,
+                notes,
                 yes,
             })
             .await?;
@@ -730,6 +1313,7 @@ async fn run() -> Result<()> {
                     game_id,
                     config,
                     json,
+                    workspace: _,
                 } => {
                     let game_id = resolve_game_id(game_id.as_deref(), &config)?;
                     handle_achievement_list(&game_id, json).await?;
@@ -930,6 +1514,91 @@ mod tests {
         }
     }
 
+    #[test]
+    fn build_push_json_only_applies_to_a_dry_run() {
+        assert!(Cli::try_parse_from(["wavedash", "build", "push", "--json"]).is_err());
+        assert!(
+            Cli::try_parse_from(["wavedash", "build", "push", "--dry-run", "--resume"]).is_err()
+        );
+
+        let cli = Cli::try_parse_from(["wavedash", "build", "push", "--dry-run", "--json"])
+            .expect("dry run with JSON output should parse");
+        match cli.command {
+            Some(Commands::Build {
+                action: BuildCommands::Push { dry_run, json, .. },
+            }) => assert!(dry_run && json),
+            _ => panic!("parsed the wrong command"),
+        }
+    }
+
+    #[test]
+    fn build_push_takes_publish_release_notes() {
+        assert!(
+            Cli::try_parse_from(["wavedash", "build", "push", "--publish", "--dry-run"]).is_err()
+        );
+
+        let cli = Cli::try_parse_from([
+            "wavedash",
+            "build",
+            "push",
+            "--publish",
+            "--title",
+            "v1.2",
+            "--fixed",
+            "crash on load",
+            "--fixed",
+            "audio pops",
+        ])
+        .expect("push with release notes should parse");
+        match cli.command {
+            Some(Commands::Build {
+                action: BuildCommands::Push { publish, notes, .. },
+            }) => {
+                assert!(publish);
+                assert_eq!(notes.title.as_deref(), Some("v1.2"));
+                assert_eq!(notes.fixed, vec!["crash on load", "audio pops"]);
+                assert!(!notes.is_empty());
+            }
+            _ => panic!("parsed the wrong command"),
+        }
+    }
+
+    #[test]
+    fn build_prune_needs_a_retention_rule() {
+        assert!(Cli::try_parse_from(["wavedash", "build", "prune", "--yes"]).is_err());
+        assert!(
+            Cli::try_parse_from(["wavedash", "build", "prune", "--older-than", "30"]).is_err()
+        );
+
+        let cli = Cli::try_parse_from([
+            "wavedash",
+            "build",
+            "prune",
+            "--keep",
+            "5",
+            "--older-than",
+            "30d",
+            "--dev",
+        ])
+        .expect("prune with both rules should parse");
+        match cli.command {
+            Some(Commands::Build {
+                action:
+                    BuildCommands::Prune {
+                        keep,
+                        older_than,
+                        dev,
+                        ..
+                    },
+            }) => {
+                assert_eq!(keep, Some(5));
+                assert_eq!(older_than, Some(std::time::Duration::from_secs(30 * 86_400)));
+                assert!(dev);
+            }
+            _ => panic!("parsed the wrong command"),
+        }
+    }
+
     #[test]
     fn upload_source_is_hidden_and_only_offers_the_godot_plugin() {
         fn walk(cmd: &clap::Command, path: &[String], found: &mut Vec<String>) {
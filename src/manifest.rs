@@ -0,0 +1,302 @@
+//! Per-file content hashes for a build.
+//!
+//! A build's manifest is the list of every file it contains, keyed by the same
+//! `/`-separated relative path its object key ends in, with the file's size and
+//! SHA-256. The server keeps the manifest with the build row, which is what lets
+//! the next push ask "what did the last build already have?" and upload only
+//! the blobs whose content it hasn't seen. Unchanged files are referenced
+//! server-side by hash rather than sent again.
+
+use anyhow::{Context, Result};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+use crate::builds::uploader::{build_object_key, FileSource, ScannedFile};
+
+const HASH_BUFFER_SIZE: usize = 1024 * 1024; // 1 MiB
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildManifest {
+    pub files: Vec<ManifestFile>,
+}
+
+impl BuildManifest {
+    /// Hash every scanned file. Sorted by path so two scans of the same tree
+    /// produce the same manifest regardless of walk order.
+    pub fn from_scan(scanned_files: &[ScannedFile]) -> Result<Self> {
+        let mut files = Vec::with_capacity(scanned_files.len());
+        let mut packed = HashSet::new();
+        let mut archive = None;
+        for f in scanned_files {
+            let path = build_object_key("", &f.relative_path);
+            match &f.source {
+                FileSource::Disk(local) => {
+                    let (size, sha256) = hash_file(local)?;
+                    files.push(ManifestFile { path, size, sha256 });
+                }
+                FileSource::Archive(source) => {
+                    archive = Some(source);
+                    packed.insert(path);
+                }
+            }
+        }
+
+        // Reaching an entry in a `.tar.gz` means decompressing everything
+        // before it, so archived files are hashed in one pass, not one by one.
+        if let Some(archive) = archive {
+            archive.for_each_entry(|entry, reader| {
+                if packed.remove(&entry.path) {
+                    let (size, sha256) = hash_reader(reader, &entry.path)?;
+                    files.push(ManifestFile {
+                        path: entry.path.clone(),
+                        size,
+                        sha256,
+                    });
+                }
+                Ok(())
+            })?;
+            if let Some(path) = packed.iter().next() {
+                anyhow::bail!("{} is not in {}", path, archive.path().display());
+            }
+        }
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self { files })
+    }
+
+    /// True when both manifests hold the same paths with the same content —
+    /// pushing one over the other would produce an identical build.
+    pub fn same_content_as(&self, other: &BuildManifest) -> bool {
+        let key = |m: &BuildManifest| {
+            m.files
+                .iter()
+                .map(|f| (f.path.clone(), f.sha256.clone()))
+                .collect::<HashSet<_>>()
+        };
+        self.files.len() == other.files.len() && key(self) == key(other)
+    }
+
+    /// The paths whose content `base` doesn't already hold under *any* path.
+    /// Matching on the hash alone is what makes a renamed or duplicated file
+    /// free: the server can reference the blob it already stored.
+    pub fn paths_missing_from(&self, base: &BuildManifest) -> HashSet<String> {
+        let known: HashSet<&str> = base.files.iter().map(|f| f.sha256.as_str()).collect();
+        self.files
+            .iter()
+            .filter(|f| !known.contains(f.sha256.as_str()))
+            .map(|f| f.path.clone())
+            .collect()
+    }
+}
+
+/// What changed going from one manifest to another, by path. A file that moved
+/// shows up as removed at its old path and added at its new one.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestDiff {
+    pub added: Vec<ManifestFile>,
+    pub removed: Vec<ManifestFile>,
+    pub modified: Vec<ModifiedFile>,
+    pub unchanged: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModifiedFile {
+    pub path: String,
+    pub old_size: u64,
+    pub new_size: u64,
+    /// `new_size - old_size`; negative when the file shrank.
+    pub delta: i64,
+}
+
+impl ManifestDiff {
+    /// How much bigger (or, negative, smaller) the whole build got.
+    pub fn size_delta(&self) -> i64 {
+        let added: i64 = self.added.iter().map(|f| f.size as i64).sum();
+        let removed: i64 = self.removed.iter().map(|f| f.size as i64).sum();
+        let modified: i64 = self.modified.iter().map(|f| f.delta).sum();
+        added - removed + modified
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+impl BuildManifest {
+    /// Every path whose presence or content differs between `self` and
+    /// `newer`, each list sorted by path.
+    pub fn diff(&self, newer: &BuildManifest) -> ManifestDiff {
+        let old: HashMap<&str, &ManifestFile> =
+            self.files.iter().map(|f| (f.path.as_str(), f)).collect();
+        let new: HashMap<&str, &ManifestFile> =
+            newer.files.iter().map(|f| (f.path.as_str(), f)).collect();
+
+        let mut diff = ManifestDiff::default();
+        for file in &newer.files {
+            match old.get(file.path.as_str()) {
+                None => diff.added.push(file.clone()),
+                Some(before) if before.sha256 != file.sha256 => diff.modified.push(ModifiedFile {
+                    path: file.path.clone(),
+                    old_size: before.size,
+                    new_size: file.size,
+                    delta: file.size as i64 - before.size as i64,
+                }),
+                Some(_) => diff.unchanged += 1,
+            }
+        }
+        diff.removed = self
+            .files
+            .iter()
+            .filter(|f| !new.contains_key(f.path.as_str()))
+            .cloned()
+            .collect();
+
+        diff.added.sort_by(|a, b| a.path.cmp(&b.path));
+        diff.removed.sort_by(|a, b| a.path.cmp(&b.path));
+        diff.modified.sort_by(|a, b| a.path.cmp(&b.path));
+        diff
+    }
+}
+
+/// Size and lowercase-hex SHA-256 of a file, streamed so a multi-GB `.pck`
+/// never has to fit in memory.
+pub fn hash_file(path: &Path) -> Result<(u64, String)> {
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    hash_reader(&mut file, &path.display().to_string())
+}
+
+/// [`hash_file`] for contents that aren't a file of their own, like an archive
+/// entry. `label` names them in errors.
+pub fn hash_reader(reader: &mut dyn Read, label: &str) -> Result<(u64, String)> {
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+    let mut size = 0u64;
+    loop {
+        let read = reader
+            .read(&mut buffer)
+            .with_context(|| format!("Failed to read {}", label))?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+        size += read as u64;
+    }
+    Ok((size, to_hex(context.finish().as_ref())))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, sha256: &str) -> ManifestFile {
+        ManifestFile {
+            path: path.to_string(),
+            size: 1,
+            sha256: sha256.to_string(),
+        }
+    }
+
+    #[test]
+    fn hashes_match_the_sha256_of_the_contents() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("hello.txt");
+        std::fs::write(&path, "hello").expect("write file");
+
+        let (size, sha256) = hash_file(&path).expect("hash");
+        assert_eq!(size, 5);
+        assert_eq!(
+            sha256,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+
+    #[test]
+    fn only_content_the_base_build_lacks_needs_uploading() {
+        let base = BuildManifest {
+            files: vec![file("index.html", "aa"), file("game.pck", "bb")],
+        };
+        let next = BuildManifest {
+            files: vec![
+                file("index.html", "aa"),
+                // Moved, same bytes: the server already has the blob.
+                file("data/game.pck", "bb"),
+                file("game.js", "cc"),
+            ],
+        };
+
+        let missing = next.paths_missing_from(&base);
+        assert_eq!(missing, HashSet::from(["game.js".to_string()]));
+        assert!(!next.same_content_as(&base));
+    }
+
+    #[test]
+    fn diff_sorts_paths_into_added_removed_and_modified() {
+        let sized = |path: &str, size: u64, sha256: &str| ManifestFile {
+            size,
+            ..file(path, sha256)
+        };
+        let old = BuildManifest {
+            files: vec![
+                sized("index.html", 10, "aa"),
+                sized("game.wasm", 1000, "bb"),
+                sized("old.png", 50, "cc"),
+            ],
+        };
+        let new = BuildManifest {
+            files: vec![
+                sized("game.wasm", 1200, "bd"),
+                sized("index.html", 10, "aa"),
+                sized("new.png", 70, "dd"),
+            ],
+        };
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec![sized("new.png", 70, "dd")]);
+        assert_eq!(diff.removed, vec![sized("old.png", 50, "cc")]);
+        assert_eq!(
+            diff.modified,
+            vec![ModifiedFile {
+                path: "game.wasm".to_string(),
+                old_size: 1000,
+                new_size: 1200,
+                delta: 200,
+            }]
+        );
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.size_delta(), 70 - 50 + 200);
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn identical_trees_are_the_same_content_in_any_order() {
+        let a = BuildManifest {
+            files: vec![file("a", "1"), file("b", "2")],
+        };
+        let b = BuildManifest {
+            files: vec![file("b", "2"), file("a", "1")],
+        };
+        assert!(a.same_content_as(&b));
+
+        let renamed = BuildManifest {
+            files: vec![file("a", "1"), file("c", "2")],
+        };
+        assert!(!a.same_content_as(&renamed));
+    }
+}
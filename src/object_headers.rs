@@ -0,0 +1,165 @@
+//! The Content-Type, Content-Encoding and Cache-Control a build's files are
+//! served with. `wavedash dev` serves local files with the same mapping the
+//! uploader stores on each object, so a precompressed Unity build that loads
+//! locally loads the same way once pushed.
+
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::config::HeaderOverride;
+
+/// Objects live under a per-build prefix and are never rewritten, so browsers
+/// and the CDN can keep them for as long as they like.
+const DEFAULT_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectHeaders {
+    pub content_type: String,
+    pub content_encoding: Option<String>,
+    pub cache_control: String,
+}
+
+/// The extension mapping plus the `[[headers]]` entries from wavedash.toml.
+#[derive(Default)]
+pub struct HeaderRules {
+    overrides: Vec<(Gitignore, HeaderOverride)>,
+}
+
+impl HeaderRules {
+    pub fn new(overrides: &[HeaderOverride]) -> Result<Self> {
+        let overrides = overrides
+            .iter()
+            .map(|entry| {
+                let mut builder = GitignoreBuilder::new("");
+                builder.add_line(None, &entry.pattern).with_context(|| {
+                    format!(
+                        "Invalid [[headers]] match '{}' in wavedash.toml",
+                        entry.pattern
+                    )
+                })?;
+                let matcher = builder
+                    .build()
+                    .context("Failed to compile [[headers]] patterns")?;
+                Ok((matcher, entry.clone()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { overrides })
+    }
+
+    /// Headers for the object at `path`, relative to the upload directory and
+    /// `/`-separated the way object keys are.
+    pub fn headers_for(&self, path: &str) -> ObjectHeaders {
+        let (content_type, content_encoding) = content_type_and_encoding(path);
+        let mut headers = ObjectHeaders {
+            content_type: content_type.to_string(),
+            content_encoding: content_encoding.map(str::to_string),
+            cache_control: DEFAULT_CACHE_CONTROL.to_string(),
+        };
+        for (matcher, entry) in &self.overrides {
+            if !matcher.matched(path, false).is_ignore() {
+                continue;
+            }
+            if let Some(content_type) = &entry.content_type {
+                headers.content_type = content_type.clone();
+            }
+            if let Some(encoding) = &entry.content_encoding {
+                headers.content_encoding = Some(encoding.clone());
+            }
+            if let Some(cache_control) = &entry.cache_control {
+                headers.cache_control = cache_control.clone();
+            }
+        }
+        headers
+    }
+}
+
+/// Unity/Godot emit `.gz`/`.br` files directly; strip the suffix to derive the
+/// real type and let the browser decompress transparently.
+fn content_type_and_encoding(path: &str) -> (&'static str, Option<&'static str>) {
+    if let Some(stripped) = path.strip_suffix(".gz") {
+        return (lookup_content_type(stripped), Some("gzip"));
+    }
+    if let Some(stripped) = path.strip_suffix(".br") {
+        return (lookup_content_type(stripped), Some("br"));
+    }
+    (lookup_content_type(path), None)
+}
+
+/// mime_guess plus the engine bundle formats it doesn't know. Unity's
+/// `.symbols.json` must be octet-stream (the loader fetches it as raw bytes).
+fn lookup_content_type(path: &str) -> &'static str {
+    let lower = path.to_ascii_lowercase();
+    if lower.ends_with(".symbols.json") {
+        return "application/octet-stream";
+    }
+    match lower.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("unityweb" | "data" | "mem" | "bundle" | "pck") => "application/octet-stream",
+        Some("unity3d") => "application/vnd.unity",
+        _ => mime_guess::from_path(&lower)
+            .first_raw()
+            .unwrap_or("application/octet-stream"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(pattern: &str) -> HeaderOverride {
+        HeaderOverride {
+            pattern: pattern.to_string(),
+            content_type: None,
+            cache_control: None,
+            content_encoding: None,
+        }
+    }
+
+    #[test]
+    fn precompressed_engine_files_keep_their_real_type() {
+        let rules = HeaderRules::new(&[]).expect("rules");
+
+        let wasm = rules.headers_for("Build/game.wasm.br");
+        assert_eq!(wasm.content_type, "application/wasm");
+        assert_eq!(wasm.content_encoding.as_deref(), Some("br"));
+
+        let data = rules.headers_for("Build/game.data.unityweb.gz");
+        assert_eq!(data.content_type, "application/octet-stream");
+        assert_eq!(data.content_encoding.as_deref(), Some("gzip"));
+
+        let pck = rules.headers_for("game.pck");
+        assert_eq!(pck.content_type, "application/octet-stream");
+        assert_eq!(pck.content_encoding, None);
+        assert_eq!(pck.cache_control, DEFAULT_CACHE_CONTROL);
+    }
+
+    #[test]
+    fn overrides_apply_field_by_field_and_later_entries_win() {
+        let rules = HeaderRules::new(&[
+            HeaderOverride {
+                cache_control: Some("no-cache".to_string()),
+                ..entry("*.html")
+            },
+            HeaderOverride {
+                content_encoding: Some("br".to_string()),
+                ..entry("Build/*.unityweb")
+            },
+            HeaderOverride {
+                cache_control: Some("max-age=60".to_string()),
+                ..entry("index.html")
+            },
+        ])
+        .expect("rules");
+
+        let index = rules.headers_for("index.html");
+        assert_eq!(index.content_type, "text/html");
+        assert_eq!(index.cache_control, "max-age=60");
+        assert_eq!(
+            rules.headers_for("menu/help.html").cache_control,
+            "no-cache"
+        );
+
+        let data = rules.headers_for("Build/game.data.unityweb");
+        assert_eq!(data.content_encoding.as_deref(), Some("br"));
+        assert_eq!(data.cache_control, DEFAULT_CACHE_CONTROL);
+    }
+}
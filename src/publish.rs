@@ -28,22 +28,46 @@ struct PublishRequest {
 }
 
 #[derive(Debug, Deserialize)]
-struct PublishResponse {
+pub(crate) struct PublishResponse {
     #[serde(rename = "releaseId")]
     release_id: String,
     #[serde(rename = "gameSlug")]
     game_slug: String,
 }
 
-pub struct PublishArgs {
-    pub config_path: PathBuf,
-    pub build_id: String,
+/// The release note flags, shared by `publish` and `build push --publish`.
+#[derive(Debug, Default, clap::Args)]
+pub struct ReleaseNoteArgs {
+    #[arg(long, help = "Release title")]
     pub title: Option<String>,
+    #[arg(long, help = "Release summary")]
     pub summary: Option<String>,
+    #[arg(long, help = "Added change item", action = clap::ArgAction::Append, num_args = 1)]
     pub added: Vec<String>,
+    #[arg(long, help = "Removed change item", action = clap::ArgAction::Append, num_args = 1)]
     pub removed: Vec<String>,
+    #[arg(long, help = "Fixed change item", action = clap::ArgAction::Append, num_args = 1)]
     pub fixed: Vec<String>,
+    #[arg(long, help = "Adjusted change item", action = clap::ArgAction::Append, num_args = 1)]
     pub adjusted: Vec<String>,
+}
+
+impl ReleaseNoteArgs {
+    /// True when none of the flags were given, blank or not.
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.summary.is_none()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.fixed.is_empty()
+            && self.adjusted.is_empty()
+    }
+}
+
+pub struct PublishArgs {
+    pub config_path: PathBuf,
+    pub build_id: String,
+    pub notes: ReleaseNoteArgs,
     pub yes: bool,
 }
 
@@ -63,14 +87,15 @@ fn append_changes(changes: &mut Vec<ReleaseChange>, kind: &'static str, items: V
     );
 }
 
-fn build_release_notes(
-    title: Option<String>,
-    summary: Option<String>,
-    added: Vec<String>,
-    removed: Vec<String>,
-    fixed: Vec<String>,
-    adjusted: Vec<String>,
-) -> Option<ReleaseNotes> {
+fn build_release_notes(notes: ReleaseNoteArgs) -> Option<ReleaseNotes> {
+    let ReleaseNoteArgs {
+        title,
+        summary,
+        added,
+        removed,
+        fixed,
+        adjusted,
+    } = notes;
     let title = trim_optional(title);
     let summary = trim_optional(summary);
 
@@ -101,12 +126,7 @@ pub async fn handle_publish(args: PublishArgs) -> Result<()> {
     let PublishArgs {
         config_path,
         build_id,
-        title,
-        summary,
-        added,
-        removed,
-        fixed,
-        adjusted,
+        notes,
         yes,
     } = args;
 
@@ -141,6 +161,18 @@ pub async fn handle_publish(args: PublishArgs) -> Result<()> {
         }
     }
 
+    let result = publish_build(game_id, &build_id, notes, &api_key).await?;
+    print_release(&build_id, &result)
+}
+
+/// Make `build_id` live, with `notes` as its release notes. No confirmation:
+/// that's for the caller to have asked for already.
+pub(crate) async fn publish_build(
+    game_id: &str,
+    build_id: &str,
+    notes: ReleaseNoteArgs,
+    api_key: &str,
+) -> Result<PublishResponse> {
     let client = config::create_http_client()?;
     let api_host = config::get("api_host")?;
     let url = format!(
@@ -148,7 +180,7 @@ pub async fn handle_publish(args: PublishArgs) -> Result<()> {
         api_host, game_id, build_id
     );
 
-    let notes = build_release_notes(title, summary, added, removed, fixed, adjusted);
+    let notes = build_release_notes(notes);
 
     let response = client
         .post(&url)
@@ -159,12 +191,13 @@ pub async fn handle_publish(args: PublishArgs) -> Result<()> {
         .await?;
 
     let response = config::check_api_response(response).await?;
-    let result: PublishResponse = response.json().await?;
+    Ok(response.json().await?)
+}
 
+pub(crate) fn print_release(build_id: &str, result: &PublishResponse) -> Result<()> {
     let site_host = config::get("open_browser_website_host")?;
     println!("✓ Published build {}", build_id);
     println!("Release ID: {}", result.release_id);
     println!("View at: {}/games/{}", site_host, result.game_slug);
-
     Ok(())
 }
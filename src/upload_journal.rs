@@ -0,0 +1,259 @@
+//! On-disk record of an in-flight `build push`, so an upload that dies partway
+//! through can be finished by `build push --resume` instead of starting over
+//! under a new build id.
+//!
+//! One file per game under `wavedash_dir()/upload-journals`, written as JSON
+//! lines: a header describing the build, then one line per object key as its
+//! upload completes. Appending a line per key means a crash can cost at most the
+//! line being written, and a torn last line is simply ignored on the way back in
+//! — that file gets uploaded again, which is harmless.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::R2Credentials;
+use crate::config;
+use crate::manifest::BuildManifest;
+
+/// Everything `--resume` needs to carry on with the same build row. The
+/// credentials are short-lived, but they're still secrets, so the file gets the
+/// same owner-only permissions as `credentials.json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalHeader {
+    pub game_id: String,
+    pub game_build_id: String,
+    pub uuid: String,
+    pub r2_key_prefix: String,
+    pub bucket_name: String,
+    pub endpoint: String,
+    pub credentials: R2Credentials,
+    /// Unix seconds after which `credentials` are no longer worth trying.
+    pub expires_at: u64,
+    /// Canonical path of the directory being pushed, so a resume from a
+    /// different checkout is caught instead of mixing two trees into one build.
+    pub upload_dir: PathBuf,
+    pub manifest: BuildManifest,
+    /// Manifest paths this push uploads. Smaller than the manifest when the
+    /// push was incremental.
+    pub pending: Vec<String>,
+}
+
+pub struct UploadJournal {
+    path: PathBuf,
+    file: Mutex<File>,
+    pub header: JournalHeader,
+    completed: HashSet<String>,
+}
+
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn journal_path(game_id: &str) -> Result<PathBuf> {
+    Ok(config::wavedash_dir()?
+        .join("upload-journals")
+        .join(format!("{}.jsonl", game_id)))
+}
+
+impl UploadJournal {
+    /// Start a journal for a new build, replacing whatever an earlier push of
+    /// the same game left behind.
+    pub fn create(header: JournalHeader) -> Result<Self> {
+        Self::create_at(journal_path(&header.game_id)?, header)
+    }
+
+    /// The interrupted push for `game_id`, if one was left behind.
+    pub fn open(game_id: &str) -> Result<Option<Self>> {
+        Self::open_at(journal_path(game_id)?)
+    }
+
+    fn create_at(path: PathBuf, header: JournalHeader) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        // The mode only applies to a file the open creates, so an old journal
+        // goes first rather than being truncated with whatever mode it has.
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| {
+                    format!("Failed to replace upload journal {}", path.display())
+                })
+            }
+            _ => {}
+        }
+        let mut options = OpenOptions::new();
+        options.create(true).write(true).truncate(true);
+        // Owner-only from the moment it exists, before the credentials land
+        // in it.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&path)
+            .with_context(|| format!("Failed to create upload journal {}", path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        file.sync_data()?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            header,
+            completed: HashSet::new(),
+        })
+    }
+
+    fn open_at(path: PathBuf) -> Result<Option<Self>> {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read upload journal {}", path.display()))
+            }
+        };
+
+        let mut lines = BufReader::new(file).lines();
+        let header_line = lines.next().transpose()?.unwrap_or_default();
+        let header: JournalHeader = serde_json::from_str(&header_line).with_context(|| {
+            format!(
+                "Upload journal {} is unreadable. Delete it and push again.",
+                path.display()
+            )
+        })?;
+        // A line that doesn't parse can only be the one a crash cut short.
+        let completed = lines
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str::<String>(&line).ok())
+            .collect();
+
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to reopen upload journal {}", path.display()))?;
+
+        Ok(Some(Self {
+            path,
+            file: Mutex::new(file),
+            header,
+            completed,
+        }))
+    }
+
+    pub fn is_completed(&self, key: &str) -> bool {
+        self.completed.contains(key)
+    }
+
+    /// Record one finished object. Flushed before returning, so a key is only
+    /// ever on disk once the object it names is in the bucket.
+    pub fn record(&self, key: &str) -> Result<()> {
+        let mut file = self.file.lock().expect("journal lock poisoned");
+        writeln!(file, "{}", serde_json::to_string(key)?)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Drop the journal once the server has been told the build is complete.
+    pub fn remove(self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e).with_context(|| {
+                format!("Failed to remove upload journal {}", self.path.display())
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> JournalHeader {
+        JournalHeader {
+            game_id: "game".to_string(),
+            game_build_id: "build-1".to_string(),
+            uuid: "uuid-1".to_string(),
+            r2_key_prefix: "games/game/builds/uuid-1".to_string(),
+            bucket_name: "bucket".to_string(),
+            endpoint: "https://r2.invalid".to_string(),
+            credentials: R2Credentials {
+                access_key_id: "id".to_string(),
+                secret_access_key: "secret".to_string(),
+                session_token: "token".to_string(),
+            },
+            expires_at: 0,
+            upload_dir: PathBuf::from("/games/thing/build"),
+            manifest: BuildManifest::default(),
+            pending: vec!["index.html".to_string(), "game.pck".to_string()],
+        }
+    }
+
+    #[test]
+    fn completed_keys_survive_a_reopen() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("game.jsonl");
+
+        let journal = UploadJournal::create_at(path.clone(), header()).expect("create");
+        journal
+            .record("games/game/builds/uuid-1/index.html")
+            .expect("record");
+        drop(journal);
+
+        let reopened = UploadJournal::open_at(path).expect("open").expect("exists");
+        assert_eq!(reopened.header.game_build_id, "build-1");
+        assert!(reopened.is_completed("games/game/builds/uuid-1/index.html"));
+        assert!(!reopened.is_completed("games/game/builds/uuid-1/game.pck"));
+    }
+
+    /// The write a crash interrupted leaves half a line; that file is simply
+    /// treated as not uploaded yet.
+    #[test]
+    fn a_torn_last_line_is_ignored() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("game.jsonl");
+
+        let journal = UploadJournal::create_at(path.clone(), header()).expect("create");
+        journal.record("done").expect("record");
+        drop(journal);
+        let mut file = OpenOptions::new().append(true).open(&path).expect("append");
+        write!(file, "\"half-writ").expect("write torn line");
+        drop(file);
+
+        let reopened = UploadJournal::open_at(path).expect("open").expect("exists");
+        assert!(reopened.is_completed("done"));
+        assert_eq!(reopened.completed.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_journal_is_owner_only_even_over_an_old_one() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("game.jsonl");
+        std::fs::write(&path, "old").expect("write");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).expect("chmod");
+
+        UploadJournal::create_at(path.clone(), header()).expect("create");
+        let mode = std::fs::metadata(&path).expect("stat").permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn no_journal_is_not_an_error() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        assert!(UploadJournal::open_at(dir.path().join("missing.jsonl"))
+            .expect("open")
+            .is_none());
+    }
+}
@@ -1,20 +1,61 @@
-use std::io::IsTerminal;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Read};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
+use colored::Colorize;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
 use futures::{stream, StreamExt, TryStreamExt};
 use indicatif::{ProgressBar, ProgressStyle};
-use opendal::services::S3;
+use opendal::layers::{RetryInterceptor, RetryLayer};
+use opendal::services::{Fs, Memory, S3};
 use opendal::Operator;
+use ring::rand::{SecureRandom, SystemRandom};
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
+use tokio::sync::{mpsc, Semaphore};
 use walkdir::WalkDir;
 
-const DEFAULT_CONCURRENCY: usize = 10;
+use crate::archive::{Archive, ArchiveListing};
+use crate::config::{Concurrency, StorageTarget, SymlinkPolicy};
+use crate::exclude::ExcludeRules;
+use crate::object_headers::{HeaderRules, ObjectHeaders};
+
+pub(super) const DEFAULT_CONCURRENCY: usize = 10;
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+const CREDENTIAL_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
 const WRITE_BUFFER_SIZE: usize = 8 * 1024 * 1024; // 8 MiB
+/// Files above this go up as S3 multipart uploads, parts in parallel.
+const MULTIPART_THRESHOLD: u64 = 64 * 1024 * 1024; // 64 MiB
+const MIN_PART_SIZE: u64 = 8 * 1024 * 1024; // 8 MiB
+/// S3's ceiling on a single part.
+const MAX_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024; // 5 GiB
+/// Parts per file the part size grows to stay under. S3 allows 10,000, but
+/// every part is a request, and a few hundred large parts finish sooner than
+/// thousands of small ones.
+const TARGET_MAX_PARTS: u64 = 1000;
+/// Where auto concurrency starts, and as low as it backs off to. Also the most
+/// permits one multipart file or batch of tiny files takes under auto, so they
+/// always fit the budget however far it shrinks.
+const AUTO_MIN_CONCURRENCY: usize = 8;
+const AUTO_MAX_CONCURRENCY: usize = 64;
+const AUTO_STEP: usize = 4;
+/// How long auto concurrency measures before deciding whether to move.
+const AUTO_WINDOW: Duration = Duration::from_secs(2);
+/// What a request costs, counted in bytes it could have sent instead. Without
+/// it, a stretch of tiny files reads to auto concurrency as a slow network.
+const REQUEST_COST_BYTES: u64 = 64 * 1024;
+/// Files up to this size are uploaded in batches, which take the permits for
+/// their requests together and keep several in flight, one permit each. A
+/// tiny PUT is all round trip and no bandwidth, so thousands of them queueing
+/// for the budget one file at a time would spend longer waiting than sending.
+const TINY_FILE_BYTES: u64 = 64 * 1024;
+const TINY_BATCH_FILES: usize = 32;
+const TINY_BATCH_PARALLELISM: usize = 4;
 
 #[derive(Debug)]
 pub struct R2Config {
@@ -24,28 +65,406 @@ pub struct R2Config {
     pub endpoint: String,
 }
 
-#[derive(Debug)]
+/// Where a scanned file's bytes come from.
+#[derive(Debug, Clone)]
+pub enum FileSource {
+    Disk(PathBuf),
+    /// The entry at the file's `relative_path` in this archive.
+    Archive(Arc<Archive>),
+}
+
+#[derive(Debug, Clone)]
 pub struct ScannedFile {
-    pub local_path: PathBuf,
+    pub source: FileSource,
     pub relative_path: PathBuf,
+    pub size: u64,
+}
+
+impl ScannedFile {
+    /// Calls `f` with the file's contents. An archive entry is looked up on its
+    /// own, which in a `.tar.gz` means decompressing up to it, so anything that
+    /// reads every file should use [`Archive::for_each_entry`] instead.
+    pub fn read_with<T>(&self, f: impl FnOnce(&mut dyn Read) -> Result<T>) -> Result<T> {
+        match &self.source {
+            FileSource::Disk(path) => {
+                let mut file = std::fs::File::open(path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                f(&mut file)
+            }
+            FileSource::Archive(archive) => {
+                archive.read_entry(&build_object_key("", &self.relative_path), f)
+            }
+        }
+    }
+
+    /// `contents` written to `relative` under `root`, as a scan of `root`
+    /// would find it. For tests of what reads scanned files.
+    #[cfg(test)]
+    pub fn written(root: &Path, relative: &str, contents: impl AsRef<[u8]>) -> Self {
+        let local_path = root.join(relative);
+        std::fs::create_dir_all(local_path.parent().expect("a parent")).expect("mkdir");
+        std::fs::write(&local_path, contents.as_ref()).expect("write");
+        ScannedFile {
+            source: FileSource::Disk(local_path),
+            relative_path: PathBuf::from(relative),
+            size: contents.as_ref().len() as u64,
+        }
+    }
 }
 
 #[derive(Debug)]
 struct ManifestEntry {
-    path: PathBuf,
+    source: FileSource,
+    /// The file's path inside the build, which is also its archive entry name.
+    path: String,
     key: String,
+    size: u64,
+    headers: ObjectHeaders,
+}
+
+impl ManifestEntry {
+    /// The file on disk, or the entry inside its archive, for messages.
+    fn describe(&self) -> String {
+        match &self.source {
+            FileSource::Disk(path) => path.display().to_string(),
+            FileSource::Archive(archive) => {
+                format!("{} in {}", self.path, archive.path().display())
+            }
+        }
+    }
 }
 
-/// Reports upload progress either as an animated TTY progress bar (for humans)
+/// Chunks of one archive entry, read on a blocking thread. One chunk in the
+/// channel is enough to keep the upload busy without holding much of a large
+/// entry in memory.
+type EntryChunks = mpsc::Receiver<std::io::Result<Vec<u8>>>;
+
+/// A file waiting to be uploaded, with its contents if they're already being
+/// read.
+type QueuedEntry = (ManifestEntry, Option<Body>);
+type QueuedEntries = BoxStream<'static, QueuedEntry>;
+
+/// Where [`write_file`] pulls an object's bytes from.
+enum Body {
+    File(File),
+    Chunks(EntryChunks),
+}
+
+impl Body {
+    /// A fresh read of the whole file, for an attempt that has nothing handed
+    /// to it already.
+    async fn open(entry: &ManifestEntry) -> Result<Self> {
+        match &entry.source {
+            FileSource::Disk(path) => {
+                Ok(Body::File(File::open(path).await.with_context(|| {
+                    format!("Failed to open {}", path.display())
+                })?))
+            }
+            FileSource::Archive(archive) => {
+                let (archive, path) = (archive.clone(), entry.path.clone());
+                let (tx, rx) = mpsc::channel(1);
+                tokio::task::spawn_blocking(move || {
+                    let result = archive.read_entry(&path, |reader| send_chunks(reader, &tx));
+                    if let Err(err) = result {
+                        // Harmless if send_chunks already forwarded it: the
+                        // upload stops at the first error.
+                        let _ = tx.blocking_send(Err(std::io::Error::other(format!("{:#}", err))));
+                    }
+                });
+                Ok(Body::Chunks(rx))
+            }
+        }
+    }
+
+    /// The next run of bytes, or `None` at the end of the file.
+    async fn next_chunk(&mut self, buffer: &mut [u8]) -> std::io::Result<Option<Vec<u8>>> {
+        match self {
+            Body::File(file) => {
+                let read = file.read(buffer).await?;
+                Ok((read > 0).then(|| buffer[..read].to_vec()))
+            }
+            Body::Chunks(rx) => rx.recv().await.transpose(),
+        }
+    }
+}
+
+/// Feed `reader` into `tx` in upload-sized chunks. A read error goes to the
+/// upload as well, so a truncated entry is never taken for a whole one. Stops
+/// quietly when the receiving upload has gone away, so a failed upload doesn't
+/// stall the pass over the archive.
+fn send_chunks(reader: &mut dyn Read, tx: &mpsc::Sender<std::io::Result<Vec<u8>>>) -> Result<()> {
+    loop {
+        let mut buffer = vec![0u8; WRITE_BUFFER_SIZE];
+        let read = match reader.read(&mut buffer) {
+            Ok(read) => read,
+            Err(err) => {
+                let forwarded = std::io::Error::new(err.kind(), err.to_string());
+                let _ = tx.blocking_send(Err(forwarded));
+                return Err(err.into());
+            }
+        };
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.truncate(read);
+        if tx.blocking_send(Ok(buffer)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// The progress bar for a whole upload and the counts behind it, shared by
+/// every file in flight.
+struct UploadProgress {
+    reporter: ProgressReporter,
+    uploaded_bytes: AtomicU64,
+    total_bytes: u64,
+    /// Attempts that failed and were tried again, which auto concurrency
+    /// reads as the network saying "too much".
+    retries: AtomicU64,
+}
+
+impl UploadProgress {
+    fn add(&self, bytes: u64) {
+        let new_total = self.uploaded_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.reporter.update(new_total.min(self.total_bytes));
+    }
+
+    /// Undo what a failed attempt reported, so the retry doesn't count twice.
+    fn take_back(&self, bytes: u64) {
+        let rolled_back = self.uploaded_bytes.fetch_sub(bytes, Ordering::Relaxed) - bytes;
+        self.reporter.update(rolled_back.min(self.total_bytes));
+    }
+}
+
+/// Which way auto concurrency last moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Up,
+    Down,
+}
+
+/// Auto concurrency's decisions, one per [`AUTO_WINDOW`]: a hill climb on
+/// throughput, which keeps moving the way that helped and turns around when a
+/// move didn't, and halves the limit whenever requests failed.
+#[derive(Debug)]
+struct AutoTuner {
+    limit: usize,
+    /// Throughput over the last window, with each request counted as
+    /// [`REQUEST_COST_BYTES`]. `None` after a backoff, since the window
+    /// before it isn't anything to compare against.
+    last_rate: Option<f64>,
+    last_move: Direction,
+}
+
+impl AutoTuner {
+    fn new() -> Self {
+        Self {
+            limit: AUTO_MIN_CONCURRENCY,
+            last_rate: None,
+            last_move: Direction::Up,
+        }
+    }
+
+    /// The limit for the next window, from the rate and failed attempts
+    /// measured over the last one.
+    fn next(&mut self, rate: f64, errors: u64) -> usize {
+        if errors > 0 {
+            self.limit = (self.limit / 2).max(AUTO_MIN_CONCURRENCY);
+            self.last_rate = None;
+            self.last_move = Direction::Down;
+            return self.limit;
+        }
+        // Within 10% counts as no better, which is noise more often than not.
+        let direction = match self.last_rate {
+            None => Direction::Up,
+            Some(last) if rate > last * 1.1 => self.last_move,
+            Some(_) => match self.last_move {
+                Direction::Up => Direction::Down,
+                Direction::Down => Direction::Up,
+            },
+        };
+        self.limit = match direction {
+            Direction::Up => (self.limit + AUTO_STEP).min(AUTO_MAX_CONCURRENCY),
+            Direction::Down => self
+                .limit
+                .saturating_sub(AUTO_STEP)
+                .max(AUTO_MIN_CONCURRENCY),
+        };
+        self.last_rate = Some(rate);
+        self.last_move = direction;
+        self.limit
+    }
+}
+
+/// What auto concurrency has measured since its last decision.
+struct AutoWindow {
+    tuner: AutoTuner,
+    started: Instant,
+    bytes_at_start: u64,
+    retries_at_start: u64,
+    requests: u64,
+}
+
+/// The requests an upload may have in flight, as semaphore permits: a fixed
+/// number, or under auto, resized at the end of every [`AUTO_WINDOW`].
+struct ConcurrencyBudget {
+    permits: Arc<Semaphore>,
+    /// Permits to start with.
+    initial: usize,
+    auto: Option<std::sync::Mutex<AutoWindow>>,
+    verbose: bool,
+}
+
+impl ConcurrencyBudget {
+    fn new(concurrency: Concurrency, verbose: bool) -> Self {
+        let (initial, auto) = match concurrency {
+            Concurrency::Fixed(n) => (n.max(1), None),
+            Concurrency::Auto => (
+                AUTO_MIN_CONCURRENCY,
+                Some(std::sync::Mutex::new(AutoWindow {
+                    tuner: AutoTuner::new(),
+                    started: Instant::now(),
+                    bytes_at_start: 0,
+                    retries_at_start: 0,
+                    requests: 0,
+                })),
+            ),
+        };
+        Self {
+            permits: Arc::new(Semaphore::new(initial)),
+            initial,
+            auto,
+            verbose,
+        }
+    }
+
+    /// The most permits there can ever be.
+    fn max_slots(&self) -> usize {
+        match self.auto {
+            Some(_) => AUTO_MAX_CONCURRENCY,
+            None => self.initial,
+        }
+    }
+
+    /// Counts one finished request, and under auto, decides at the end of a
+    /// window whether the limit should move.
+    fn request_done(&self, progress: &UploadProgress) {
+        let Some(auto) = &self.auto else {
+            return;
+        };
+        let mut window = auto.lock().expect("auto window lock poisoned");
+        window.requests += 1;
+        let elapsed = window.started.elapsed();
+        if elapsed < AUTO_WINDOW {
+            return;
+        }
+
+        let bytes = progress.uploaded_bytes.load(Ordering::Relaxed);
+        let retries = progress.retries.load(Ordering::Relaxed);
+        let work = bytes.saturating_sub(window.bytes_at_start)
+            + window.requests.saturating_mul(REQUEST_COST_BYTES);
+        let rate = work as f64 / elapsed.as_secs_f64();
+        let old = window.tuner.limit;
+        let errors = retries.saturating_sub(window.retries_at_start);
+        let new = window.tuner.next(rate, errors);
+        window.started = Instant::now();
+        window.bytes_at_start = bytes;
+        window.retries_at_start = retries;
+        window.requests = 0;
+        drop(window);
+
+        if new != old && self.verbose {
+            println!("Concurrency {} → {}", old, new);
+        }
+        self.resize(old, new);
+    }
+
+    /// Growing takes effect at once. Shrinking takes permits out of
+    /// circulation as requests in flight hand them back.
+    fn resize(&self, old: usize, new: usize) {
+        if new > old {
+            self.permits.add_permits(new - old);
+        } else if new < old {
+            let owed = (old - new) - self.permits.forget_permits(old - new);
+            if owed > 0 {
+                let permits = self.permits.clone();
+                tokio::spawn(async move {
+                    if let Ok(permit) = permits.acquire_many_owned(owed as u32).await {
+                        permit.forget();
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// A token bucket for `--max-rate`, shared by every file in flight. Bytes are
+/// taken before they're written. A chunk bigger than what's in the bucket
+/// overdraws it and its writer waits out the debt, so the rate holds however
+/// large the chunks are; the bucket holds a second's worth, which is as much
+/// as an upload can burst after a pause.
+struct RateLimiter {
+    bytes_per_sec: f64,
+    bucket: std::sync::Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// Negative while overdrawn.
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    fn new(bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1) as f64;
+        Self {
+            bytes_per_sec,
+            bucket: std::sync::Mutex::new(Bucket {
+                tokens: bytes_per_sec,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    async fn take(&self, bytes: u64) {
+        let wait = self.reserve(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take `bytes` from the bucket as of `now`, and say how long to wait
+    /// before sending them.
+    fn reserve(&self, bytes: u64, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().expect("rate limiter lock poisoned");
+        let refill =
+            now.saturating_duration_since(bucket.refilled).as_secs_f64() * self.bytes_per_sec;
+        bucket.tokens = (bucket.tokens + refill).min(self.bytes_per_sec) - bytes as f64;
+        bucket.refilled = now;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.bytes_per_sec)
+        }
+    }
+}
+
+/// Reports transfer progress either as an animated TTY progress bar (for humans)
 /// or as plain-text percentage lines (for piped/LLM consumers).
-struct ProgressReporter {
+pub(super) struct ProgressReporter {
     tty_bar: Option<ProgressBar>,
     total_bytes: u64,
     last_percent: AtomicU64,
+    started: Instant,
+    /// "Uploading", "Downloading": what the plain-text lines say is happening.
+    action: &'static str,
+    /// "Build uploaded successfully": what `finish` says happened.
+    done: &'static str,
 }
 
 impl ProgressReporter {
-    fn new(total_bytes: u64) -> Self {
+    pub(super) fn new(total_bytes: u64, action: &'static str, done: &'static str) -> Self {
         if std::io::stderr().is_terminal() {
             let pb = ProgressBar::new(total_bytes);
             pb.set_style(
@@ -59,25 +478,32 @@ impl ProgressReporter {
                 tty_bar: Some(pb),
                 total_bytes,
                 last_percent: AtomicU64::new(0),
+                started: Instant::now(),
+                action,
+                done,
             }
         } else {
-            println!("Uploading: 0% (0 B / {})", format_bytes(total_bytes));
+            println!("{}: 0% (0 B / {})", action, format_bytes(total_bytes));
             Self {
                 tty_bar: None,
                 total_bytes,
                 last_percent: AtomicU64::new(0),
+                started: Instant::now(),
+                action,
+                done,
             }
         }
     }
 
-    fn update(&self, uploaded_bytes: u64) {
+    pub(super) fn update(&self, uploaded_bytes: u64) {
         let clamped = uploaded_bytes.min(self.total_bytes);
         if let Some(pb) = &self.tty_bar {
             pb.set_position(clamped);
             pb.set_message(format!(
-                "{} / {}",
+                "{} / {}{}",
                 format_bytes(clamped),
                 format_bytes(self.total_bytes),
+                self.rate_and_eta(clamped),
             ));
         } else if self.total_bytes > 0 {
             let percent = ((clamped as f64 / self.total_bytes as f64) * 100.0) as u64;
@@ -85,68 +511,355 @@ impl ProgressReporter {
             let prev = self.last_percent.fetch_max(percent, Ordering::Relaxed);
             if percent > prev {
                 println!(
-                    "Uploading: {}% ({} / {})",
+                    "{}: {}% ({} / {}{})",
+                    self.action,
                     percent,
                     format_bytes(clamped),
                     format_bytes(self.total_bytes),
+                    self.rate_and_eta(clamped),
                 );
             }
         }
     }
 
-    fn finish(&self) {
+    /// `", 2.00 MB/s, ETA 1m 05s"`: the average rate since the start, and how
+    /// long the rest takes at it. Empty until there's a rate to speak of.
+    fn rate_and_eta(&self, done: u64) -> String {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if done == 0 || elapsed < 1.0 {
+            return String::new();
+        }
+        let rate = done as f64 / elapsed;
+        let remaining = (self.total_bytes - done) as f64 / rate;
+        format!(
+            ", {}/s, ETA {}",
+            format_bytes(rate as u64),
+            format_eta(Duration::from_secs_f64(remaining))
+        )
+    }
+
+    pub(super) fn finish(&self) {
         if let Some(pb) = &self.tty_bar {
-            pb.finish_with_message("✓ Build uploaded successfully!");
+            pb.finish_with_message(format!("✓ {}!", self.done));
         } else {
-            println!("Build uploaded successfully.");
+            println!("{}.", self.done);
+        }
+    }
+}
+
+/// How hard a single file is retried before the upload gives up on it.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries per file, on top of the first attempt. The budget is per file so
+    /// one flaky object can't starve the rest of theirs.
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with equal jitter: half the capped delay is fixed and
+    /// half is random, so a burst of failures across concurrent files doesn't
+    /// come back as a synchronized burst of retries. `random` is any `u32`; it's
+    /// a parameter so the curve is testable.
+    fn delay(&self, retry: u32, random: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.min(16)));
+        let capped = exponential.min(self.max_delay);
+        let half = capped / 2;
+        half + half.mul_f64(random as f64 / u32::MAX as f64)
+    }
+
+    /// The same budget and curve, applied by the operator to each part of a
+    /// multipart upload, so one bad part doesn't send the whole file again.
+    fn part_layer(&self, notice: PartRetryNotice) -> RetryLayer<PartRetryNotice> {
+        RetryLayer::new()
+            .with_jitter()
+            .with_min_delay(self.base_delay)
+            .with_max_delay(self.max_delay)
+            .with_max_times(self.max_retries as usize)
+            .with_notify(notice)
+    }
+}
+
+/// Part size and parts in flight for one large file.
+#[derive(Debug, Clone, Copy)]
+struct MultipartPlan {
+    part_size: usize,
+    concurrent: usize,
+}
+
+/// How a large file is split: the part size doubles from [`MIN_PART_SIZE`]
+/// until the file fits in [`TARGET_MAX_PARTS`] parts, so a 500 MB `.pck` goes up
+/// in 8 MiB parts and a 20 GB one in 32 MiB parts. `None` for files small
+/// enough to go up in a single request.
+fn part_size_for(size: u64) -> Option<u64> {
+    if size <= MULTIPART_THRESHOLD {
+        return None;
+    }
+    let mut part_size = MIN_PART_SIZE;
+    while size.div_ceil(part_size) > TARGET_MAX_PARTS && part_size < MAX_PART_SIZE {
+        part_size *= 2;
+    }
+    Some(part_size.min(MAX_PART_SIZE))
+}
+
+/// The parts a file of `size` goes up in, all this size bar the last: its
+/// multipart part size, or for smaller files the write buffer, which a file no
+/// bigger than it fills in one request. Fixed so the ETag the bucket derives
+/// from the parts can be predicted from the file.
+pub(super) fn upload_part_size(size: u64) -> u64 {
+    part_size_for(size).unwrap_or(WRITE_BUFFER_SIZE as u64)
+}
+
+/// Announces a part retry under `--verbose`. The retry itself happens inside
+/// the operator, on that part alone; the rest of the file keeps going.
+#[derive(Clone)]
+struct PartRetryNotice {
+    key: String,
+    verbose: bool,
+}
+
+impl RetryInterceptor for PartRetryNotice {
+    fn intercept(&self, err: &opendal::Error, dur: Duration) {
+        if self.verbose {
+            println!(
+                "Retrying part of {} in {:.1}s: {}",
+                self.key,
+                dur.as_secs_f64(),
+                err
+            );
         }
     }
 }
 
+fn random_u32() -> u32 {
+    let mut bytes = [0u8; 4];
+    // A failed fill only costs the jitter, not the retry.
+    let _ = SystemRandom::new().fill(&mut bytes);
+    u32::from_le_bytes(bytes)
+}
+
+/// A fresh set of credentials for the same bucket and key prefix.
+pub struct RenewedCredentials {
+    pub config: R2Config,
+    pub expires_in: Duration,
+}
+
+pub type CredentialRefresher =
+    Box<dyn Fn() -> BoxFuture<'static, Result<RenewedCredentials>> + Send + Sync>;
+
+/// Keeps the operator's temporary credentials ahead of their expiry. `refresh_at`
+/// sits a margin before the real expiry so a file that starts just before it
+/// still finishes on credentials that are valid; the tokio mutex makes the
+/// refresh single-flight when every concurrent file notices at once.
+struct CredentialRenewal {
+    refresh: CredentialRefresher,
+    refresh_at: tokio::sync::Mutex<Instant>,
+}
+
+/// When to swap credentials that live for `lifetime`: five minutes early, or a
+/// quarter of the lifetime early for credentials too short-lived for that.
+fn refresh_deadline(lifetime: Duration) -> Instant {
+    let margin = CREDENTIAL_REFRESH_MARGIN.min(lifetime / 4);
+    Instant::now() + lifetime.saturating_sub(margin)
+}
+
 pub struct R2Uploader {
-    operator: Operator,
+    operator: RwLock<Operator>,
     bucket: String,
-    concurrency: usize,
+    storage: StorageTarget,
+    concurrency: Concurrency,
+    retry: RetryPolicy,
+    renewal: Option<CredentialRenewal>,
+    headers: HeaderRules,
+    rate_limit: Option<RateLimiter>,
 }
 
-impl R2Uploader {
-    pub fn new(config: &R2Config, bucket: &str) -> Result<Self> {
-        let mut builder = S3::default()
-            .access_key_id(&config.access_key_id)
-            .secret_access_key(&config.secret_access_key)
-            .endpoint(&config.endpoint)
-            .bucket(bucket)
-            .region("auto");
-
-        if !config.session_token.is_empty() {
-            builder = builder.session_token(&config.session_token);
+/// An operator on `bucket` in `storage`, with the given temporary
+/// credentials where the storage takes any. Downloads read through the same
+/// setup uploads write through.
+pub(super) fn build_operator(
+    config: &R2Config,
+    bucket: &str,
+    storage: &StorageTarget,
+) -> Result<Operator> {
+    let endpoint = match storage {
+        StorageTarget::R2 => &config.endpoint,
+        StorageTarget::S3 { endpoint } => endpoint,
+        StorageTarget::Fs { root } => {
+            let root = root.join(bucket);
+            let root = root
+                .to_str()
+                .ok_or_else(|| anyhow::anyhow!("Storage root {} is not UTF-8", root.display()))?;
+            return Ok(Operator::new(Fs::default().root(root))?.finish());
         }
+        StorageTarget::Memory => return memory_bucket(bucket),
+    };
+    let mut builder = S3::default()
+        .access_key_id(&config.access_key_id)
+        .secret_access_key(&config.secret_access_key)
+        .endpoint(endpoint)
+        .bucket(bucket)
+        .region("auto");
+
+    if !config.session_token.is_empty() {
+        builder = builder.session_token(&config.session_token);
+    }
+
+    Ok(Operator::new(builder)?.finish())
+}
 
-        let operator = Operator::new(builder)?.finish();
+/// The process's in-memory `bucket`. Shared, so an operator rebuilt for new
+/// credentials still sees what the last one wrote.
+fn memory_bucket(bucket: &str) -> Result<Operator> {
+    static BUCKETS: OnceLock<Mutex<HashMap<String, Operator>>> = OnceLock::new();
+    let mut buckets = BUCKETS
+        .get_or_init(Default::default)
+        .lock()
+        .expect("memory buckets lock poisoned");
+    if let Some(operator) = buckets.get(bucket) {
+        return Ok(operator.clone());
+    }
+    let operator = Operator::new(Memory::default())?.finish();
+    buckets.insert(bucket.to_string(), operator.clone());
+    Ok(operator)
+}
 
+impl R2Uploader {
+    pub fn new(config: &R2Config, bucket: &str, storage: StorageTarget) -> Result<Self> {
         Ok(Self {
-            operator,
+            operator: RwLock::new(build_operator(config, bucket, &storage)?),
             bucket: bucket.to_string(),
-            concurrency: DEFAULT_CONCURRENCY,
+            storage,
+            concurrency: Concurrency::Fixed(DEFAULT_CONCURRENCY),
+            retry: RetryPolicy::default(),
+            renewal: None,
+            headers: HeaderRules::default(),
+            rate_limit: None,
         })
     }
 
-    #[allow(dead_code)]
-    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
-        if concurrency > 0 {
-            self.concurrency = concurrency;
-        }
+    /// Requests in flight at once. A fixed count of zero means one.
+    pub fn with_concurrency(mut self, concurrency: Concurrency) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Metadata to store on each object. Without this, objects still get the
+    /// type and encoding their extension implies.
+    pub fn with_object_headers(mut self, headers: HeaderRules) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Cap the whole upload, every file in flight together, at this many bytes
+    /// per second. `None` leaves it unlimited.
+    pub fn with_max_rate(mut self, bytes_per_sec: Option<u64>) -> Self {
+        self.rate_limit = bytes_per_sec.map(RateLimiter::new);
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Re-issue credentials through `refresh` as they near `expires_in`, and
+    /// whenever the bucket rejects the current ones mid-upload. Files already in
+    /// the bucket stay there; only the file that hit the rejection starts over.
+    pub fn with_credential_refresh(
+        mut self,
+        expires_in: Duration,
+        refresh: CredentialRefresher,
+    ) -> Self {
+        self.renewal = Some(CredentialRenewal {
+            refresh,
+            refresh_at: tokio::sync::Mutex::new(refresh_deadline(expires_in)),
+        });
         self
     }
 
+    /// The operator to start the next attempt with, renewing the credentials
+    /// first if they're due. `force` renews regardless, for when the bucket has
+    /// already said they're no good.
+    async fn current_operator(&self, force: bool, verbose: bool) -> Result<Operator> {
+        if let Some(renewal) = &self.renewal {
+            let mut refresh_at = renewal.refresh_at.lock().await;
+            if force || Instant::now() >= *refresh_at {
+                if verbose {
+                    println!("Renewing upload credentials");
+                }
+                let renewed = (renewal.refresh)()
+                    .await
+                    .context("Failed to renew upload credentials")?;
+                let operator = build_operator(&renewed.config, &self.bucket, &self.storage)?;
+                *self.operator.write().expect("operator lock poisoned") = operator;
+                *refresh_at = refresh_deadline(renewed.expires_in);
+            }
+        }
+        Ok(self
+            .operator
+            .read()
+            .expect("operator lock poisoned")
+            .clone())
+    }
+
+    /// The operator for a single request, retried the way files are.
+    async fn request_operator(&self) -> Result<Operator> {
+        Ok(self.current_operator(false, false).await?.layer(
+            RetryLayer::new()
+                .with_jitter()
+                .with_max_times(self.retry.max_retries as usize),
+        ))
+    }
+
+    /// Store `body` at `key` in one request.
+    pub async fn put_object(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<()> {
+        self.request_operator()
+            .await?
+            .write_with(key, body)
+            .content_type(content_type)
+            .await
+            .with_context(|| format!("Failed to upload {}", key))?;
+        Ok(())
+    }
+
+    /// `key`'s size and ETag as the bucket has them, unquoted; `None` when
+    /// there's no such object.
+    pub async fn stat_object(&self, key: &str) -> Result<Option<(u64, Option<String>)>> {
+        match self.request_operator().await?.stat(key).await {
+            Ok(meta) => Ok(Some((
+                meta.content_length(),
+                meta.etag().map(|etag| etag.trim_matches('"').to_string()),
+            ))),
+            Err(e) if e.kind() == opendal::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to check {}", key)),
+        }
+    }
+
+    /// `on_uploaded` is called with each object key as soon as that object is
+    /// in the bucket, which is what the upload journal hangs off.
     pub async fn upload_directory_from_scan(
         &self,
         scanned_files: &[ScannedFile],
         total_bytes: u64,
         prefix: &str,
         verbose: bool,
+        on_uploaded: &(dyn Fn(&str) -> Result<()> + Sync),
     ) -> Result<()> {
-        let manifest = build_manifest_from_scan(scanned_files, prefix);
+        let manifest = build_manifest_from_scan(scanned_files, prefix, &self.headers);
         if manifest.is_empty() {
             anyhow::bail!("No files found to upload");
         }
@@ -161,45 +874,330 @@ impl R2Uploader {
             );
         }
 
-        let progress = Arc::new(ProgressReporter::new(total_bytes));
-        let uploaded_bytes = Arc::new(AtomicU64::new(0));
-        let operator = self.operator.clone();
-        let concurrency = self.concurrency.max(1);
-
-        stream::iter(manifest.into_iter().map(|entry| {
-            let operator = operator.clone();
-            let progress = progress.clone();
-            let uploaded_bytes = uploaded_bytes.clone();
+        let progress = UploadProgress {
+            reporter: ProgressReporter::new(
+                total_bytes,
+                "Uploading",
+                "Build uploaded successfully",
+            ),
+            uploaded_bytes: AtomicU64::new(0),
+            total_bytes,
+            retries: AtomicU64::new(0),
+        };
+        // One permit per request in flight. A multipart file holds one per part
+        // it uploads at once, and a batch of tiny files one per file it sends
+        // at once, so neither multiplies the budget.
+        let budget = ConcurrencyBudget::new(self.concurrency, verbose);
 
-            async move {
-                upload_file(&operator, &entry, &progress, &uploaded_bytes, total_bytes).await?;
-                Ok::<(), anyhow::Error>(())
+        let archive = match scanned_files.first().map(|f| &f.source) {
+            Some(FileSource::Archive(archive)) => Some(archive.clone()),
+            _ => None,
+        };
+        let (entries, producer) = match archive {
+            Some(archive) => {
+                let (entries, producer) = stream_archive_entries(archive, manifest, budget.initial);
+                (entries, Some(producer))
             }
-        }))
-        .buffer_unordered(concurrency)
-        .try_collect::<Vec<_>>()
-        .await?;
+            None => (
+                stream::iter(manifest.into_iter().map(|entry| (entry, None))).boxed(),
+                None,
+            ),
+        };
+
+        let (progress, budget) = (&progress, &budget);
+        batch_tiny_files(entries)
+            // Permits are taken before the batch is handed on, so files are
+            // only pulled from the scan, or read out of an archive, as fast as
+            // the budget lets them go up.
+            .then(|batch| async move {
+                let plan = match batch.as_slice() {
+                    [(entry, _)] => self.multipart_plan(entry.size),
+                    _ => None,
+                };
+                let weight = match plan {
+                    Some(plan) => plan.concurrent,
+                    None => batch.len().min(self.tiny_batch_parallelism()),
+                };
+                let permits = budget.permits.acquire_many(weight as u32).await;
+                (batch, plan, weight, permits)
+            })
+            .map(|(batch, plan, weight, permits)| async move {
+                let _permits = permits?;
+                stream::iter(batch)
+                    .map(|(entry, body)| async move {
+                        self.upload_with_retries(&entry, body, plan, progress, verbose)
+                            .await?;
+                        on_uploaded(&entry.key)?;
+                        budget.request_done(progress);
+                        Ok::<(), anyhow::Error>(())
+                    })
+                    .buffer_unordered(weight)
+                    .try_collect::<Vec<_>>()
+                    .await
+            })
+            .buffer_unordered(budget.max_slots())
+            .try_collect::<Vec<_>>()
+            .await?;
 
-        progress.finish();
+        if let Some(producer) = producer {
+            producer.await.context("Reading the archive panicked")??;
+        }
+
+        progress.reporter.finish();
         Ok(())
     }
+
+    /// `None` for a file that goes up in one request. Never more parts in
+    /// flight than the whole upload's concurrency, or than the file has.
+    fn multipart_plan(&self, size: u64) -> Option<MultipartPlan> {
+        let part_size = part_size_for(size)?;
+        let parts = usize::try_from(size.div_ceil(part_size)).unwrap_or(usize::MAX);
+        Some(MultipartPlan {
+            part_size: part_size as usize,
+            concurrent: self.smallest_budget().min(parts),
+        })
+    }
+
+    /// Requests a batch of tiny files keeps in flight, and so permits it holds.
+    fn tiny_batch_parallelism(&self) -> usize {
+        self.smallest_budget().min(TINY_BATCH_PARALLELISM)
+    }
+
+    /// The fewest permits the budget can ever have, which is as many as one
+    /// file or batch may take without waiting on permits that never come.
+    fn smallest_budget(&self) -> usize {
+        match self.concurrency {
+            Concurrency::Fixed(n) => n.max(1),
+            Concurrency::Auto => AUTO_MIN_CONCURRENCY,
+        }
+    }
+
+    /// One file, start to finish, retrying the whole file on a retryable error.
+    /// Bytes a failed attempt already reported are taken back off the progress
+    /// total so the bar never counts a file twice.
+    ///
+    /// A multipart file retries each part on its own, inside the operator. What
+    /// reaches this loop has already used up its retries, so the file is only
+    /// started over when new credentials might get it through.
+    ///
+    /// `body` is the file's contents already on their way, from the single
+    /// pass over an archive. Only the first attempt gets it; a retry reads the
+    /// file again.
+    async fn upload_with_retries(
+        &self,
+        entry: &ManifestEntry,
+        mut body: Option<Body>,
+        plan: Option<MultipartPlan>,
+        progress: &UploadProgress,
+        verbose: bool,
+    ) -> Result<()> {
+        let mut retry = 0u32;
+        let mut force_renewal = false;
+        loop {
+            let mut operator = self.current_operator(force_renewal, verbose).await?;
+            if plan.is_some() {
+                operator = operator.layer(self.retry.part_layer(PartRetryNotice {
+                    key: entry.key.clone(),
+                    verbose,
+                }));
+            }
+            let reader = match body.take() {
+                Some(body) => body,
+                None => Body::open(entry).await?,
+            };
+            let mut sent = 0u64;
+            let limit = self.rate_limit.as_ref();
+            let err = match upload_file(&operator, entry, reader, plan, progress, limit, &mut sent)
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+
+            progress.take_back(sent);
+
+            let kind = err.downcast_ref::<opendal::Error>().map(|e| {
+                (
+                    e.is_temporary(),
+                    e.kind() == opendal::ErrorKind::PermissionDenied,
+                )
+            });
+            let (temporary, rejected) = kind.unwrap_or((false, false));
+            // A rejection is only worth retrying if new credentials could fix it.
+            let renewable = rejected && self.renewal.is_some();
+            if !(temporary || renewable) || retry >= self.retry.max_retries {
+                return Err(err);
+            }
+
+            retry += 1;
+            progress.retries.fetch_add(1, Ordering::Relaxed);
+            force_renewal = renewable;
+            let delay = self.retry.delay(retry - 1, random_u32());
+            if verbose {
+                println!(
+                    "Retrying {} in {:.1}s ({}/{}): {:#}",
+                    entry.key,
+                    delay.as_secs_f64(),
+                    retry,
+                    self.retry.max_retries,
+                    err
+                );
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
 }
 
-pub fn scan_directory(source_dir: &Path) -> Result<(Vec<ScannedFile>, u64)> {
+/// What a scan of `upload_dir` turned up: the files to upload, their combined
+/// size, everything left out along with the rule that left it out, and every
+/// symlink the walk met.
+pub struct DirectoryScan {
+    pub files: Vec<ScannedFile>,
+    pub total_bytes: u64,
+    pub excluded: Vec<ExcludedPath>,
+    pub links: Vec<ScannedLink>,
+}
+
+pub struct ExcludedPath {
+    pub relative_path: PathBuf,
+    pub rule: String,
+}
+
+/// A symlink under `upload_dir`. Excluded links aren't reported: the exclude
+/// already says what happened to them.
+#[derive(Debug)]
+pub struct ScannedLink {
+    pub relative_path: PathBuf,
+    /// Where the link resolves to; `None` when it's dangling.
+    pub target: Option<PathBuf>,
+    /// Resolves to somewhere outside `upload_dir`.
+    pub escapes: bool,
+    /// Its target was walked and uploaded; otherwise it was left out.
+    pub followed: bool,
+}
+
+impl DirectoryScan {
+    /// Name every link the scan left out, and every followed one that reaches
+    /// outside `upload_dir`. On stderr, so `--json` output stays parseable.
+    pub fn warn_about_links(&self) {
+        let mut skipped = false;
+        for link in &self.links {
+            if link.followed && !link.escapes {
+                continue;
+            }
+            let verb = if link.followed {
+                "Following"
+            } else {
+                skipped = true;
+                "Skipped"
+            };
+            eprintln!(
+                "{} {} symlink {}",
+                "Warning:".yellow().bold(),
+                verb,
+                describe_link(link)
+            );
+        }
+        if skipped {
+            eprintln!(
+                "Skipped links aren't uploaded. Set symlinks = \"follow\" in wavedash.toml to upload what they point to, or symlinks = \"error\" to refuse to push."
+            );
+        }
+    }
+}
+
+/// `audio → /repo/shared/audio (outside upload_dir)`.
+fn describe_link(link: &ScannedLink) -> String {
+    match &link.target {
+        None => format!("{} (dangling)", link.relative_path.display()),
+        Some(target) => format!(
+            "{} → {}{}",
+            link.relative_path.display(),
+            target.display(),
+            if link.escapes {
+                " (outside upload_dir)"
+            } else {
+                ""
+            }
+        ),
+    }
+}
+
+pub fn scan_directory(
+    source_dir: &Path,
+    rules: &ExcludeRules,
+    symlinks: SymlinkPolicy,
+) -> Result<DirectoryScan> {
     let source_dir = source_dir
         .canonicalize()
         .with_context(|| format!("Failed to resolve {}", source_dir.display()))?;
 
     let mut files = Vec::new();
     let mut total_bytes = 0u64;
+    let mut excluded = Vec::new();
+    let mut links = Vec::new();
+    let mut dangling = Vec::new();
+    let follow = symlinks == SymlinkPolicy::Follow;
 
-    for entry in WalkDir::new(&source_dir).follow_links(false) {
-        let entry = entry.with_context(|| {
-            format!(
-                "Failed to walk directory while scanning {}",
-                source_dir.display()
-            )
-        })?;
+    // Excluded directories are pruned here rather than filtered afterwards, so
+    // a `.git` or `.godot` cache is never walked at all. So are links the
+    // policy doesn't follow.
+    let walker = WalkDir::new(&source_dir)
+        .follow_links(follow)
+        .into_iter()
+        .filter_entry(|entry| {
+            if entry.depth() == 0 {
+                return true;
+            }
+            let Ok(relative) = entry.path().strip_prefix(&source_dir) else {
+                return true;
+            };
+            if let Some(rule) = rules.matched(relative, entry.file_type().is_dir()) {
+                excluded.push(ExcludedPath {
+                    relative_path: relative.to_path_buf(),
+                    rule,
+                });
+                return false;
+            }
+            if entry.path_is_symlink() {
+                let target = entry.path().canonicalize().ok();
+                links.push(ScannedLink {
+                    relative_path: relative.to_path_buf(),
+                    escapes: target
+                        .as_ref()
+                        .is_some_and(|target| !target.starts_with(&source_dir)),
+                    target,
+                    followed: follow,
+                });
+                return follow;
+            }
+            true
+        });
+
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            // Following a dangling link fails the walk before the filter sees
+            // it. There's nothing to follow, so it's left out like a skipped one.
+            Err(err) if follow && err.io_error().is_some() && is_dangling_link(err.path()) => {
+                let path = err.path().expect("checked by is_dangling_link");
+                dangling.push(ScannedLink {
+                    relative_path: path.strip_prefix(&source_dir).unwrap_or(path).to_path_buf(),
+                    target: None,
+                    escapes: false,
+                    followed: false,
+                });
+                continue;
+            }
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!(
+                        "Failed to walk directory while scanning {}",
+                        source_dir.display()
+                    )
+                })
+            }
+        };
 
         if !entry.file_type().is_file() {
             continue;
@@ -216,26 +1214,199 @@ pub fn scan_directory(source_dir: &Path) -> Result<(Vec<ScannedFile>, u64)> {
             .to_path_buf();
 
         files.push(ScannedFile {
-            local_path: path,
+            source: FileSource::Disk(path),
             relative_path: relative,
+            size: file_size,
         });
         total_bytes = total_bytes.saturating_add(file_size);
     }
 
-    Ok((files, total_bytes))
+    links.append(&mut dangling);
+    refuse_links(symlinks, &links)?;
+
+    Ok(DirectoryScan {
+        files,
+        total_bytes,
+        excluded,
+        links,
+    })
 }
 
-fn build_manifest_from_scan(scanned_files: &[ScannedFile], prefix: &str) -> Vec<ManifestEntry> {
+/// [`scan_directory`] for a build packed in an archive, from its listing. The
+/// same excludes apply. Links in an archive have nothing on disk to point at,
+/// so even `symlinks = "follow"` leaves them out, with the same warning as a
+/// skipped one.
+pub fn scan_archive(
+    archive: Arc<Archive>,
+    listing: &ArchiveListing,
+    rules: &ExcludeRules,
+    symlinks: SymlinkPolicy,
+) -> Result<DirectoryScan> {
+    let mut files = Vec::new();
+    let mut total_bytes = 0u64;
+    let mut excluded = Vec::new();
+
+    for entry in &listing.files {
+        let relative = PathBuf::from(&entry.path);
+        if let Some(rule) = rules.matched_file(&relative) {
+            excluded.push(ExcludedPath {
+                relative_path: relative,
+                rule,
+            });
+            continue;
+        }
+        files.push(ScannedFile {
+            source: FileSource::Archive(archive.clone()),
+            relative_path: relative,
+            size: entry.size,
+        });
+        total_bytes = total_bytes.saturating_add(entry.size);
+    }
+
+    let links: Vec<ScannedLink> = listing
+        .links
+        .iter()
+        .filter(|link| rules.matched_file(Path::new(&link.path)).is_none())
+        .map(|link| ScannedLink {
+            relative_path: PathBuf::from(&link.path),
+            target: link.target.clone(),
+            escapes: false,
+            followed: false,
+        })
+        .collect();
+    refuse_links(symlinks, &links)?;
+
+    Ok(DirectoryScan {
+        files,
+        total_bytes,
+        excluded,
+        links,
+    })
+}
+
+/// Under `symlinks = "error"`, any link at all stops the push.
+fn refuse_links(symlinks: SymlinkPolicy, links: &[ScannedLink]) -> Result<()> {
+    if symlinks == SymlinkPolicy::Error && !links.is_empty() {
+        anyhow::bail!(
+            "upload_dir contains symlinks, and wavedash.toml sets symlinks = \"error\":\n  {}\nReplace them with the files they point to, or set symlinks to \"follow\" or \"skip\".",
+            links
+                .iter()
+                .map(describe_link)
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+fn is_dangling_link(path: Option<&Path>) -> bool {
+    path.is_some_and(|path| {
+        path.symlink_metadata()
+            .is_ok_and(|meta| meta.file_type().is_symlink())
+            && !path.exists()
+    })
+}
+
+fn build_manifest_from_scan(
+    scanned_files: &[ScannedFile],
+    prefix: &str,
+    headers: &HeaderRules,
+) -> Vec<ManifestEntry> {
     scanned_files
         .iter()
         .map(|f| ManifestEntry {
-            path: f.local_path.clone(),
+            source: f.source.clone(),
+            path: build_object_key("", &f.relative_path),
             key: build_object_key(prefix, &f.relative_path),
+            size: f.size,
+            headers: headers.headers_for(&build_object_key("", &f.relative_path)),
         })
         .collect()
 }
 
-fn build_object_key(prefix: &str, relative: &Path) -> String {
+/// Runs of consecutive tiny files grouped into batches of up to
+/// [`TINY_BATCH_FILES`]; every other file on its own.
+fn batch_tiny_files(entries: QueuedEntries) -> BoxStream<'static, Vec<QueuedEntry>> {
+    stream::unfold(
+        (entries, None::<QueuedEntry>),
+        |(mut entries, mut carried)| async move {
+            let mut batch = Vec::new();
+            loop {
+                let next = match carried.take() {
+                    Some(entry) => Some(entry),
+                    None => entries.next().await,
+                };
+                let Some(entry) = next else {
+                    break;
+                };
+                if entry.0.size > TINY_FILE_BYTES {
+                    if batch.is_empty() {
+                        return Some((vec![entry], (entries, None)));
+                    }
+                    // Goes out on its own next time, after the batch it ended.
+                    carried = Some(entry);
+                    break;
+                }
+                batch.push(entry);
+                if batch.len() == TINY_BATCH_FILES {
+                    break;
+                }
+            }
+            (!batch.is_empty()).then_some((batch, (entries, carried)))
+        },
+    )
+    .boxed()
+}
+
+/// Hands out `manifest`'s entries in archive order, each with its contents
+/// already streaming from one pass over the archive on a blocking thread. The
+/// pass waits for the uploads to take each entry, so it never reads more than
+/// `concurrency` entries ahead of them. The returned task ends with the pass,
+/// and fails if it couldn't finish or an entry never turned up.
+fn stream_archive_entries(
+    archive: Arc<Archive>,
+    manifest: Vec<ManifestEntry>,
+    concurrency: usize,
+) -> (QueuedEntries, tokio::task::JoinHandle<Result<()>>) {
+    let (queue, queued) = mpsc::channel(concurrency);
+    let producer = tokio::task::spawn_blocking(move || {
+        let mut wanted: HashMap<String, ManifestEntry> = manifest
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        archive.for_each_entry(|entry, reader| {
+            let Some(upload) = wanted.remove(&entry.path) else {
+                return Ok(());
+            };
+            let (tx, rx) = mpsc::channel(1);
+            if queue
+                .blocking_send((upload, Some(Body::Chunks(rx))))
+                .is_err()
+            {
+                // The upload already failed, and its error is the one to report.
+                anyhow::bail!("Upload stopped");
+            }
+            send_chunks(reader, &tx)
+        })?;
+        if !wanted.is_empty() {
+            let mut missing: Vec<String> = wanted.into_keys().collect();
+            missing.sort();
+            anyhow::bail!(
+                "{} changed while it was being uploaded; these entries are gone: {}",
+                archive.path().display(),
+                missing.join(", ")
+            );
+        }
+        Ok(())
+    });
+    let entries = stream::unfold(queued, |mut queued| async move {
+        queued.recv().await.map(|item| (item, queued))
+    })
+    .boxed();
+    (entries, producer)
+}
+
+pub fn build_object_key(prefix: &str, relative: &Path) -> String {
     let relative_key = relative
         .components()
         .map(|comp| comp.as_os_str().to_string_lossy())
@@ -256,39 +1427,65 @@ fn build_object_key(prefix: &str, relative: &Path) -> String {
 async fn upload_file(
     operator: &Operator,
     entry: &ManifestEntry,
-    progress: &ProgressReporter,
-    uploaded_bytes: &Arc<AtomicU64>,
-    total_bytes: u64,
+    mut reader: Body,
+    plan: Option<MultipartPlan>,
+    progress: &UploadProgress,
+    limit: Option<&RateLimiter>,
+    sent: &mut u64,
 ) -> Result<()> {
-    let mut reader = File::open(&entry.path)
-        .await
-        .with_context(|| format!("Failed to open {}", entry.path.display()))?;
+    let headers = &entry.headers;
     let mut writer = operator
-        .writer(&entry.key)
+        .writer_with(&entry.key)
+        .content_type(&headers.content_type)
+        .cache_control(&headers.cache_control);
+    if let Some(encoding) = &headers.content_encoding {
+        writer = writer.content_encoding(encoding);
+    }
+    // Always exact chunks, so the parts (and the ETag) don't depend on how
+    // the reads happen to fall.
+    writer = match plan {
+        Some(plan) => writer.chunk(plan.part_size).concurrent(plan.concurrent),
+        None => writer.chunk(upload_part_size(entry.size) as usize),
+    };
+    let mut writer = writer
         .await
         .with_context(|| format!("Failed to create writer for {}", entry.key))?;
 
-    let mut buffer = vec![0u8; WRITE_BUFFER_SIZE];
-    loop {
-        let bytes_read = reader
-            .read(&mut buffer)
-            .await
-            .with_context(|| format!("Failed to read {}", entry.path.display()))?;
+    let result = write_file(&mut reader, &mut writer, entry, progress, limit, sent).await;
+    if result.is_err() && plan.is_some() {
+        // Parts already in the bucket are billed until the upload is either
+        // completed or aborted; the next attempt starts a fresh one anyway.
+        let _ = writer.abort().await;
+    }
+    result
+}
 
-        if bytes_read == 0 {
-            break;
+async fn write_file(
+    reader: &mut Body,
+    writer: &mut opendal::Writer,
+    entry: &ManifestEntry,
+    progress: &UploadProgress,
+    limit: Option<&RateLimiter>,
+    sent: &mut u64,
+) -> Result<()> {
+    let mut buffer = vec![0u8; WRITE_BUFFER_SIZE];
+    while let Some(chunk) = reader
+        .next_chunk(&mut buffer)
+        .await
+        .with_context(|| format!("Failed to read {}", entry.describe()))?
+    {
+        let bytes_read = chunk.len();
+        if let Some(limit) = limit {
+            limit.take(bytes_read as u64).await;
         }
-
         writer
-            .write(buffer[..bytes_read].to_vec())
+            .write(chunk)
             .await
             .with_context(|| format!("Failed to write {}", entry.key))?;
 
         // Update progress after each chunk is written
-        let new_total =
-            uploaded_bytes.fetch_add(bytes_read as u64, Ordering::Relaxed) + bytes_read as u64;
-        let clamped = new_total.min(total_bytes);
-        progress.update(clamped);
+        *sent += bytes_read as u64;
+        progress.add(bytes_read as u64);
     }
 
     writer
@@ -298,7 +1495,17 @@ async fn upload_file(
     Ok(())
 }
 
-fn format_bytes(bytes: u64) -> String {
+/// `"42s"`, `"3m 05s"`, `"1h 02m"`.
+fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
     const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
     if bytes == 0 {
         return "0 B".to_string();
@@ -318,3 +1525,287 @@ fn format_bytes(bytes: u64) -> String {
         format!("{:.2} {}", value, UNITS[unit_index])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excluded_files_are_skipped_and_left_out_of_the_size() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let root = dir.path();
+        std::fs::write(root.join("index.html"), "hello").expect("write");
+        std::fs::write(root.join("game.js.map"), "0123456789").expect("write");
+        std::fs::create_dir_all(root.join(".git/objects")).expect("mkdir");
+        std::fs::write(root.join(".git/objects/blob"), "0123456789").expect("write");
+
+        let rules = ExcludeRules::load(root, root, &[]).expect("rules");
+        let scan = scan_directory(root, &rules, SymlinkPolicy::Skip).expect("scan");
+
+        let uploaded: Vec<_> = scan.files.iter().map(|f| f.relative_path.clone()).collect();
+        assert_eq!(uploaded, vec![PathBuf::from("index.html")]);
+        assert_eq!(scan.total_bytes, 5);
+        // `.git` is reported once, as a directory, not file by file.
+        let mut skipped: Vec<_> = scan
+            .excluded
+            .iter()
+            .map(|e| e.relative_path.clone())
+            .collect();
+        skipped.sort();
+        assert_eq!(
+            skipped,
+            vec![PathBuf::from(".git"), PathBuf::from("game.js.map")]
+        );
+    }
+
+    #[test]
+    fn archive_scans_apply_excludes_to_every_entry() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("build.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).expect("create"));
+        for (name, contents) in [
+            ("index.html", "hello"),
+            ("game.js.map", "0123456789"),
+            (".git/objects/blob", "0123456789"),
+        ] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .expect("start file");
+            zip.write_all(contents.as_bytes()).expect("write");
+        }
+        zip.finish().expect("finish");
+
+        let archive = Arc::new(Archive::open(&path).expect("open"));
+        let listing = archive.list().expect("list");
+        let rules = ExcludeRules::load(&path, dir.path(), &[]).expect("rules");
+        let scan = scan_archive(archive, &listing, &rules, SymlinkPolicy::Skip).expect("scan");
+
+        let uploaded: Vec<_> = scan.files.iter().map(|f| f.relative_path.clone()).collect();
+        assert_eq!(uploaded, vec![PathBuf::from("index.html")]);
+        assert_eq!(scan.total_bytes, 5);
+        // No walk to prune `.git`, so the file inside it is matched through
+        // its parent directory.
+        let skipped: Vec<_> = scan
+            .excluded
+            .iter()
+            .map(|e| e.relative_path.clone())
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                PathBuf::from("game.js.map"),
+                PathBuf::from(".git/objects/blob")
+            ]
+        );
+
+        let contents = scan.files[0]
+            .read_with(|reader| {
+                let mut contents = String::new();
+                reader.read_to_string(&mut contents)?;
+                Ok(contents)
+            })
+            .expect("read");
+        assert_eq!(contents, "hello");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_follow_the_policy_and_escapes_are_noticed() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let shared = dir.path().join("shared");
+        let root = dir.path().join("build");
+        std::fs::create_dir_all(&shared).expect("mkdir");
+        std::fs::create_dir_all(&root).expect("mkdir");
+        std::fs::write(shared.join("theme.ogg"), "ogg").expect("write");
+        std::fs::write(root.join("index.html"), "hello").expect("write");
+        std::os::unix::fs::symlink(&shared, root.join("audio")).expect("symlink");
+        std::os::unix::fs::symlink(root.join("gone"), root.join("stale")).expect("symlink");
+        let rules = ExcludeRules::load(&root, &root, &[]).expect("rules");
+        let uploaded = |scan: &DirectoryScan| {
+            let mut paths: Vec<_> = scan.files.iter().map(|f| f.relative_path.clone()).collect();
+            paths.sort();
+            paths
+        };
+
+        let skip = scan_directory(&root, &rules, SymlinkPolicy::Skip).expect("scan");
+        assert_eq!(uploaded(&skip), vec![PathBuf::from("index.html")]);
+        let audio = skip
+            .links
+            .iter()
+            .find(|l| l.relative_path == Path::new("audio"))
+            .expect("audio link reported");
+        assert!(audio.escapes && !audio.followed);
+        assert!(skip.links.iter().any(|l| l.target.is_none()));
+
+        let follow = scan_directory(&root, &rules, SymlinkPolicy::Follow).expect("scan");
+        assert_eq!(
+            uploaded(&follow),
+            vec![
+                PathBuf::from("audio/theme.ogg"),
+                PathBuf::from("index.html")
+            ]
+        );
+        // The dangling link has nothing to follow and is left out, not fatal.
+        assert!(follow
+            .links
+            .iter()
+            .any(|l| l.relative_path == Path::new("stale") && !l.followed));
+
+        let err = scan_directory(&root, &rules, SymlinkPolicy::Error)
+            .err()
+            .expect("symlinks are refused");
+        assert!(format!("{:#}", err).contains("audio →"), "{:#}", err);
+    }
+
+    #[test]
+    fn part_size_grows_with_the_file() {
+        const MIB: u64 = 1024 * 1024;
+        assert_eq!(part_size_for(MULTIPART_THRESHOLD), None);
+        assert_eq!(part_size_for(500 * MIB), Some(8 * MIB));
+        assert_eq!(part_size_for(20 * 1024 * MIB), Some(32 * MIB));
+        // Within S3's part limit right up to its 5 TiB object limit.
+        let huge = 5 * 1024 * 1024 * MIB;
+        assert!(huge.div_ceil(part_size_for(huge).unwrap()) <= 10_000);
+    }
+
+    #[test]
+    fn parts_in_flight_fit_the_upload_budget() {
+        let config = R2Config {
+            access_key_id: "id".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: String::new(),
+            endpoint: "https://r2.invalid".to_string(),
+        };
+        let uploader = R2Uploader::new(&config, "bucket", StorageTarget::R2)
+            .expect("uploader")
+            .with_concurrency(Concurrency::Fixed(16));
+
+        let plan = uploader
+            .multipart_plan(1024 * 1024 * 1024)
+            .expect("multipart");
+        assert_eq!(plan.concurrent, 16);
+        // Nine 8 MiB parts can't use more than nine slots.
+        let plan = uploader
+            .multipart_plan(72 * 1024 * 1024)
+            .expect("multipart");
+        assert_eq!(plan.concurrent, 9);
+        assert!(uploader.multipart_plan(1024).is_none());
+        assert_eq!(uploader.tiny_batch_parallelism(), TINY_BATCH_PARALLELISM);
+
+        // A budget smaller than a batch's parallelism caps it, rather than the
+        // batch waiting on permits that don't exist.
+        let uploader = uploader.with_concurrency(Concurrency::Fixed(2));
+        assert_eq!(uploader.tiny_batch_parallelism(), 2);
+    }
+
+    #[test]
+    fn auto_concurrency_climbs_while_it_helps_and_halves_on_errors() {
+        let mut tuner = AutoTuner::new();
+        assert_eq!(tuner.next(100.0, 0), AUTO_MIN_CONCURRENCY + AUTO_STEP);
+        assert_eq!(tuner.next(200.0, 0), AUTO_MIN_CONCURRENCY + 2 * AUTO_STEP);
+        // No better than noise: turn around.
+        assert_eq!(tuner.next(205.0, 0), AUTO_MIN_CONCURRENCY + AUTO_STEP);
+        assert_eq!(tuner.next(300.0, 0), AUTO_MIN_CONCURRENCY);
+        // Never below the floor, however it's going.
+        assert_eq!(tuner.next(400.0, 0), AUTO_MIN_CONCURRENCY);
+
+        for _ in 0..100 {
+            tuner.next(f64::MAX, 0);
+        }
+        assert!(tuner.limit <= AUTO_MAX_CONCURRENCY);
+
+        tuner.limit = 40;
+        assert_eq!(tuner.next(1_000.0, 3), 20);
+        // Errors reset the comparison, so the next window climbs again.
+        assert_eq!(tuner.next(10.0, 0), 24);
+        assert_eq!(tuner.next(10.0, 0), 20);
+    }
+
+    #[tokio::test]
+    async fn tiny_files_are_batched_and_large_ones_go_alone() {
+        let entry = |path: &str, size: u64| {
+            let entry = ManifestEntry {
+                source: FileSource::Disk(PathBuf::from(path)),
+                path: path.to_string(),
+                key: path.to_string(),
+                size,
+                headers: ObjectHeaders {
+                    content_type: "application/octet-stream".to_string(),
+                    content_encoding: None,
+                    cache_control: String::new(),
+                },
+            };
+            (entry, None)
+        };
+        let mut entries = vec![entry("a", 10), entry("b", 10), entry("big", 1 << 20)];
+        entries.extend((0..TINY_BATCH_FILES + 1).map(|i| entry(&format!("t{}", i), 100)));
+        entries.push(entry("huge", 1 << 30));
+
+        let batches: Vec<Vec<String>> = batch_tiny_files(stream::iter(entries).boxed())
+            .map(|batch| batch.into_iter().map(|(e, _)| e.path).collect())
+            .collect()
+            .await;
+        let sizes: Vec<usize> = batches.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![2, 1, TINY_BATCH_FILES, 1, 1]);
+        assert_eq!(batches[1], vec!["big"]);
+        assert_eq!(batches[3], vec![format!("t{}", TINY_BATCH_FILES)]);
+        assert_eq!(batches[4], vec!["huge"]);
+    }
+
+    #[test]
+    fn the_rate_limit_lets_a_second_through_then_makes_writers_wait() {
+        let limiter = RateLimiter::new(1000);
+        let start = Instant::now();
+        assert_eq!(limiter.reserve(600, start), Duration::ZERO);
+        assert_eq!(limiter.reserve(400, start), Duration::ZERO);
+        // Overdrawn: this chunk goes out once the bucket has refilled for it.
+        assert_eq!(limiter.reserve(500, start), Duration::from_millis(500));
+        // The next writer queues behind that debt.
+        assert_eq!(limiter.reserve(500, start), Duration::from_secs(1));
+        // A long pause refills at most one second's worth.
+        let later = start + Duration::from_secs(60);
+        assert_eq!(limiter.reserve(1000, later), Duration::ZERO);
+        assert_eq!(limiter.reserve(100, later), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn etas_read_at_a_glance() {
+        assert_eq!(format_eta(Duration::from_secs(42)), "42s");
+        assert_eq!(format_eta(Duration::from_secs(185)), "3m 05s");
+        assert_eq!(format_eta(Duration::from_secs(3720)), "1h 02m");
+    }
+
+    #[test]
+    fn backoff_doubles_and_stays_under_the_cap() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+        };
+
+        // No jitter drawn: exactly half the exponential delay.
+        assert_eq!(policy.delay(0, 0), Duration::from_millis(50));
+        assert_eq!(policy.delay(1, 0), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, 0), Duration::from_millis(200));
+
+        // Full jitter drawn: the whole delay, but never past the cap.
+        assert_eq!(policy.delay(1, u32::MAX), Duration::from_millis(200));
+        assert_eq!(policy.delay(9, u32::MAX), Duration::from_secs(2));
+        assert_eq!(policy.delay(u32::MAX, u32::MAX), Duration::from_secs(2));
+    }
+
+    /// Credentials that live less than the margin would otherwise be renewed
+    /// before every single file.
+    #[test]
+    fn short_lived_credentials_are_renewed_a_quarter_early() {
+        let before = Instant::now();
+        let deadline = refresh_deadline(Duration::from_secs(60));
+        let lead = deadline.duration_since(before);
+        assert!(lead >= Duration::from_secs(44) && lead <= Duration::from_secs(46));
+
+        let hour = refresh_deadline(Duration::from_secs(3600)).duration_since(before);
+        assert!(hour >= Duration::from_secs(3300) - Duration::from_secs(1));
+        assert!(hour <= Duration::from_secs(3300) + Duration::from_secs(1));
+    }
+}
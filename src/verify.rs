@@ -0,0 +1,203 @@
+//! What a push leaves behind as proof of what it uploaded:
+//! `wavedash-manifest.json`, stored with the build's objects and named by
+//! digest when the build is marked complete, and `build push --verify`, which
+//! checks every object against the file it came from before that happens.
+//!
+//! Objects are checked by size and ETag, which for R2 is the MD5 of the
+//! object, or for a multipart upload the MD5 of its parts' MD5s. Uploads use
+//! fixed part sizes (see [`upload_part_size`]) precisely so the second kind
+//! can be predicted from the file.
+
+use anyhow::{Context, Result};
+use futures::{stream, StreamExt, TryStreamExt};
+use md5::{Digest, Md5};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+use super::uploader::{
+    build_object_key, upload_part_size, FileSource, R2Uploader, ScannedFile, DEFAULT_CONCURRENCY,
+};
+use crate::manifest::{hash_reader, BuildManifest};
+
+/// Where the manifest goes, next to the build's files. A file of the same
+/// name at the top of `upload_dir` is refused rather than overwritten.
+pub(super) const MANIFEST_OBJECT: &str = "wavedash-manifest.json";
+
+/// The manifest exactly as it's uploaded, and the SHA-256 of those bytes.
+pub(super) fn manifest_document(manifest: &BuildManifest) -> Result<(Vec<u8>, String)> {
+    let document = serde_json::to_vec_pretty(manifest)?;
+    let (_, digest) = hash_reader(&mut document.as_slice(), MANIFEST_OBJECT)?;
+    Ok((document, digest))
+}
+
+pub(super) fn refuse_reserved_name(files: &[ScannedFile]) -> Result<()> {
+    if files
+        .iter()
+        .any(|f| f.relative_path == Path::new(MANIFEST_OBJECT))
+    {
+        anyhow::bail!(
+            "{} at the top of upload_dir would collide with the manifest wavedash stores there. Rename it, or exclude it from the upload.",
+            MANIFEST_OBJECT
+        );
+    }
+    Ok(())
+}
+
+/// Stat the object behind every one of `files` under `prefix`, and fail,
+/// naming each one, if any is missing or differs in size or ETag.
+pub(super) async fn verify_uploads(
+    uploader: &R2Uploader,
+    files: &[ScannedFile],
+    prefix: &str,
+) -> Result<()> {
+    println!("Verifying {} uploaded files...", files.len());
+    let local = files.to_vec();
+    let expected = tokio::task::spawn_blocking(move || expected_etags(&local)).await??;
+
+    let remote: Vec<_> = stream::iter(files.iter().map(|file| async move {
+        let path = build_object_key("", &file.relative_path);
+        let stat = uploader
+            .stat_object(&build_object_key(prefix, &file.relative_path))
+            .await?;
+        Ok::<_, anyhow::Error>((path, file.size, stat))
+    }))
+    .buffer_unordered(DEFAULT_CONCURRENCY)
+    .try_collect()
+    .await?;
+
+    let mut problems = Vec::new();
+    for (path, size, stat) in remote {
+        let Some((remote_size, etag)) = stat else {
+            problems.push(format!("{}: missing from the bucket", path));
+            continue;
+        };
+        if remote_size != size {
+            problems.push(format!(
+                "{}: {} bytes in the bucket, {} locally",
+                path, remote_size, size
+            ));
+        } else if let Some(etag) = etag {
+            if Some(&etag) != expected.get(&path) {
+                problems.push(format!(
+                    "{}: contents in the bucket differ from the local file",
+                    path
+                ));
+            }
+        }
+    }
+    if !problems.is_empty() {
+        problems.sort();
+        anyhow::bail!(
+            "{} uploaded files don't match the local copies:\n  {}",
+            problems.len(),
+            problems.join("\n  ")
+        );
+    }
+    println!("✓ Verified {} files against the bucket", files.len());
+    Ok(())
+}
+
+/// The ETag each file's object should have, by its path in the build. Files
+/// in an archive are read in one pass over it, as for the manifest.
+fn expected_etags(files: &[ScannedFile]) -> Result<HashMap<String, String>> {
+    let mut etags = HashMap::with_capacity(files.len());
+    let mut packed = HashSet::new();
+    let mut archive = None;
+    for file in files {
+        let path = build_object_key("", &file.relative_path);
+        match &file.source {
+            FileSource::Disk(local) => {
+                let mut reader = std::fs::File::open(local)
+                    .with_context(|| format!("Failed to open {}", local.display()))?;
+                let etag = object_etag(&mut reader, file.size, &path)?;
+                etags.insert(path, etag);
+            }
+            FileSource::Archive(source) => {
+                archive = Some(source);
+                packed.insert(path);
+            }
+        }
+    }
+    if let Some(archive) = archive {
+        archive.for_each_entry(|entry, reader| {
+            if packed.contains(&entry.path) {
+                let etag = object_etag(reader, entry.size, &entry.path)?;
+                etags.insert(entry.path.clone(), etag);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(etags)
+}
+
+/// The ETag the bucket gives a `size`-byte object uploaded in
+/// [`upload_part_size`] parts: the MD5 of a single part, or the MD5 of every
+/// part's MD5 followed by `-` and the part count.
+fn object_etag(reader: &mut dyn Read, size: u64, label: &str) -> Result<String> {
+    let part_size = upload_part_size(size);
+    let mut parts = Vec::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let mut part = Md5::new();
+        let mut remaining = part_size;
+        while remaining > 0 {
+            let want = buffer.len().min(remaining as usize);
+            let read = reader
+                .read(&mut buffer[..want])
+                .with_context(|| format!("Failed to read {}", label))?;
+            if read == 0 {
+                break;
+            }
+            part.update(&buffer[..read]);
+            remaining -= read as u64;
+        }
+        let filled = remaining < part_size;
+        if filled || parts.is_empty() {
+            parts.push(part.finalize());
+        }
+        if remaining > 0 {
+            break;
+        }
+    }
+    if parts.len() == 1 {
+        return Ok(to_hex(&parts[0]));
+    }
+    let mut whole = Md5::new();
+    for part in &parts {
+        whole.update(part);
+    }
+    Ok(format!("{}-{}", to_hex(&whole.finalize()), parts.len()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_objects_are_tagged_by_md5_and_large_ones_by_their_parts() {
+        let etag = object_etag(&mut b"hello".as_slice(), 5, "hello").expect("etag");
+        assert_eq!(etag, "5d41402abc4b2a76b9719d911017c592");
+        let etag = object_etag(&mut b"".as_slice(), 0, "empty").expect("etag");
+        assert_eq!(etag, "d41d8cd98f00b204e9800998ecf8427e");
+
+        // Two full 8 MiB parts and a short third.
+        let part = upload_part_size(20 * 1024 * 1024) as usize;
+        let data: Vec<u8> = (0..part * 2 + 10).map(|i| (i % 251) as u8).collect();
+        let etag = object_etag(&mut data.as_slice(), data.len() as u64, "big").expect("etag");
+        let mut whole = Md5::new();
+        for chunk in data.chunks(part) {
+            whole.update(Md5::digest(chunk));
+        }
+        assert_eq!(etag, format!("{}-3", to_hex(&whole.finalize())));
+
+        // Exactly one part's worth is still a single-request upload.
+        let data = vec![7u8; part];
+        let etag = object_etag(&mut data.as_slice(), data.len() as u64, "exact").expect("etag");
+        assert_eq!(etag, to_hex(&Md5::digest(&data)));
+    }
+}
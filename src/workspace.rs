@@ -0,0 +1,398 @@
+//! `--all` and `--package`: one command across the members of a workspace (see
+//! [`config::find_workspace`]).
+//!
+//! Each member runs in a `wavedash` of its own — this command line, with
+//! `--config` pointed at the member — so a member behaves exactly as it does
+//! when run from its own directory, and no handler needs to know workspaces
+//! exist. They all run at once. Their output is captured and printed a member
+//! at a time as each finishes, except under `dev`, whose servers never finish:
+//! there every line is streamed as it comes, prefixed with the member's name.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use colored::Colorize;
+use std::ffi::OsString;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+use crate::config::{self, WorkspaceMember};
+
+/// Set in a member's environment to its name, so it can skip what the
+/// workspace run already did once for all of them.
+pub const ENV_MEMBER: &str = "WAVEDASH_WORKSPACE_MEMBER";
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct WorkspaceArgs {
+    #[arg(
+        long,
+        conflicts_with = "package",
+        help = "Run for every member of the workspace this config belongs to",
+        help_heading = "Workspace"
+    )]
+    pub all: bool,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Run for this workspace member (its directory name); repeat for more",
+        help_heading = "Workspace"
+    )]
+    pub package: Vec<String>,
+}
+
+impl WorkspaceArgs {
+    pub fn is_set(&self) -> bool {
+        self.all || !self.package.is_empty()
+    }
+
+    /// The members to run for, or `None` when neither flag was given and the
+    /// command runs on `config_path` as usual. `config_path` can be the root
+    /// or any member: either way the workspace is the one it belongs to.
+    pub fn members(&self, config_path: &Path) -> Result<Option<Vec<WorkspaceMember>>> {
+        if !self.is_set() {
+            return Ok(None);
+        }
+        let workspace = config::find_workspace(config_path)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} isn't part of a workspace. --all and --package need a wavedash.toml with [workspace] members at or above it.",
+                config_path.display()
+            )
+        })?;
+        let members = if self.all {
+            if workspace.members.is_empty() {
+                anyhow::bail!(
+                    "[workspace] in {} lists no members",
+                    workspace.root.display()
+                );
+            }
+            workspace.members
+        } else {
+            let mut selected = Vec::new();
+            for name in &self.package {
+                let member = workspace
+                    .members
+                    .iter()
+                    .find(|member| &member.name == name)
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = workspace
+                            .members
+                            .iter()
+                            .map(|member| member.name.as_str())
+                            .collect();
+                        anyhow::anyhow!(
+                            "No workspace member named {} in {}. Members: {}.",
+                            name,
+                            workspace.root.display(),
+                            names.join(", ")
+                        )
+                    })?;
+                if !selected.contains(member) {
+                    selected.push(member.clone());
+                }
+            }
+            selected
+        };
+
+        if members.len() > 1 {
+            let overrides = config::per_game_overrides();
+            if !overrides.is_empty() {
+                anyhow::bail!(
+                    "{} would apply to every member. Unset it, or pass a single --package.",
+                    overrides.join(" and ")
+                );
+            }
+        }
+        Ok(Some(members))
+    }
+}
+
+/// How a workspace run shows what its members print.
+#[derive(Debug, Clone, Copy)]
+pub enum Output {
+    /// Each member's output in one piece once it finishes. Under `json`, their
+    /// stdout is gathered into one object keyed by member name instead, so
+    /// stdout stays a single document. A member whose stdout isn't JSON is in
+    /// it as a string, and counts as failed.
+    Grouped { json: bool },
+    /// Every line as it comes, prefixed with the member's name.
+    Streamed,
+}
+
+/// Run this command for each of `members` at once. `Err` naming the members
+/// that failed, after every one has finished.
+pub async fn run_members(members: &[WorkspaceMember], output: Output) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to locate the wavedash executable")?;
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    let color = std::io::stdout().is_terminal();
+    let command = |member: &WorkspaceMember| {
+        let mut command = Command::new(&exe);
+        command
+            .args(member_args(args.iter().cloned(), &member.config_path))
+            .env(ENV_MEMBER, &member.name)
+            // A member can't be asked anything: it would be one prompt among
+            // several, with nobody sure which game it's for.
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if color {
+            command.env("CLICOLOR_FORCE", "1");
+        }
+        command
+    };
+
+    let failed = match output {
+        Output::Grouped { json } => run_grouped(members, command, json).await?,
+        Output::Streamed => run_streamed(members, command).await?,
+    };
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "{} of {} workspace members failed: {}",
+            failed.len(),
+            members.len(),
+            failed.join(", ")
+        );
+    }
+    if !matches!(output, Output::Grouped { json: true }) {
+        println!("✓ Done for all {} workspace members", members.len());
+    }
+    Ok(())
+}
+
+/// Names of the members that failed.
+async fn run_grouped(
+    members: &[WorkspaceMember],
+    command: impl Fn(&WorkspaceMember) -> Command,
+    json: bool,
+) -> Result<Vec<String>> {
+    let mut running = tokio::task::JoinSet::new();
+    for (index, member) in members.iter().enumerate() {
+        let child = command(member)
+            .spawn()
+            .with_context(|| format!("Failed to start wavedash for {}", member.name))?;
+        running.spawn(async move { (index, child.wait_with_output().await) });
+    }
+
+    let mut failed = Vec::new();
+    let mut documents = serde_json::Map::new();
+    while let Some(finished) = running.join_next().await {
+        let (index, result) = finished.context("Workspace member task panicked")?;
+        let member = &members[index];
+        let output =
+            result.with_context(|| format!("Failed to run wavedash for {}", member.name))?;
+        let header = format!("── {} ──", member.name).bold();
+        let mut succeeded = output.status.success();
+        if json {
+            eprintln!("{}", header);
+            // Output that isn't JSON still gets the member's place in the
+            // document, as a string, so no member goes missing from it.
+            let document = serde_json::from_slice(&output.stdout).unwrap_or_else(|_| {
+                if succeeded {
+                    eprintln!(
+                        "{} {} didn't print a JSON report",
+                        "Error:".red().bold(),
+                        member.name
+                    );
+                    succeeded = false;
+                }
+                serde_json::Value::String(String::from_utf8_lossy(&output.stdout).into_owned())
+            });
+            documents.insert(member.name.clone(), document);
+        } else {
+            println!("{}", header);
+            std::io::stdout().write_all(&output.stdout)?;
+            std::io::stdout().flush()?;
+        }
+        std::io::stderr().write_all(&output.stderr)?;
+        if !succeeded {
+            failed.push(member.name.clone());
+        }
+    }
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::Value::Object(documents))?
+        );
+    }
+    Ok(failed)
+}
+
+/// Names of the members that failed.
+async fn run_streamed(
+    members: &[WorkspaceMember],
+    command: impl Fn(&WorkspaceMember) -> Command,
+) -> Result<Vec<String>> {
+    let width = members
+        .iter()
+        .map(|member| member.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut running = tokio::task::JoinSet::new();
+    for member in members {
+        let mut child = command(member)
+            .spawn()
+            .with_context(|| format!("Failed to start wavedash for {}", member.name))?;
+        let prefix = format!("{:width$} │", member.name, width = width)
+            .cyan()
+            .to_string();
+        let stdout = child.stdout.take().map(BufReader::new);
+        let stderr = child.stderr.take().map(BufReader::new);
+        let name = member.name.clone();
+        running.spawn(async move {
+            let echo_stdout = async {
+                if let Some(stdout) = stdout {
+                    let mut lines = stdout.lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        println!("{} {}", prefix, line);
+                    }
+                }
+            };
+            let echo_stderr = async {
+                if let Some(stderr) = stderr {
+                    let mut lines = stderr.lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        eprintln!("{} {}", prefix, line);
+                    }
+                }
+            };
+            tokio::join!(echo_stdout, echo_stderr);
+            (name, child.wait().await)
+        });
+    }
+
+    let mut failed = Vec::new();
+    while let Some(finished) = running.join_next().await {
+        let (name, status) = finished.context("Workspace member task panicked")?;
+        if !status.map(|status| status.success()).unwrap_or(false) {
+            failed.push(name);
+        }
+    }
+    Ok(failed)
+}
+
+/// This process's arguments for the member whose config is at `config_path`:
+/// `--all`, `--package` and any `--config` taken out, and `--config` for the
+/// member put back. Everything else — the command, its flags, global ones like
+/// `--profile` — passes through as typed.
+fn member_args(args: impl IntoIterator<Item = OsString>, config_path: &Path) -> Vec<OsString> {
+    let mut args = args.into_iter();
+    let mut kept = Vec::new();
+    let mut trailing = Vec::new();
+    while let Some(arg) = args.next() {
+        let Some(text) = arg.to_str() else {
+            kept.push(arg);
+            continue;
+        };
+        match text {
+            "--" => {
+                trailing.push(arg);
+                trailing.extend(args.by_ref());
+            }
+            "--all" => {}
+            "--package" | "--config" | "-c" => {
+                args.next();
+            }
+            _ if text.starts_with("--package=") || text.starts_with("--config=") => {}
+            // `-cPATH` and `-c=PATH`: no other short flag starts with c.
+            _ if text.starts_with("-c") => {}
+            _ => kept.push(arg),
+        }
+    }
+    kept.push("--config".into());
+    kept.push(config_path.into());
+    kept.extend(trailing);
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &[&str]) -> Vec<OsString> {
+        text.iter().map(OsString::from).collect()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_member_that_prints_no_json_fails_the_grouped_run() {
+        let members: Vec<WorkspaceMember> = ["one", "two"]
+            .into_iter()
+            .map(|name| WorkspaceMember {
+                name: name.to_string(),
+                config_path: std::path::PathBuf::from(format!("/repo/{}/wavedash.toml", name)),
+            })
+            .collect();
+        let command = |member: &WorkspaceMember| {
+            let mut command = Command::new("sh");
+            let script = match member.name.as_str() {
+                "one" => "echo '{\"ok\": true}'",
+                _ => "echo 'Uploaded 3 files'",
+            };
+            command
+                .args(["-c", script])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            command
+        };
+
+        let failed = run_grouped(&members, command, true).await.expect("run");
+        assert_eq!(failed, vec!["two".to_string()]);
+        let failed = run_grouped(&members, command, false).await.expect("run");
+        assert!(failed.is_empty());
+    }
+
+    #[test]
+    fn a_member_gets_the_command_line_with_its_own_config() {
+        let member = Path::new("/repo/games/one/wavedash.toml");
+        assert_eq!(
+            member_args(
+                args(&[
+                    "--profile",
+                    "demo",
+                    "build",
+                    "push",
+                    "--all",
+                    "-c",
+                    "root.toml",
+                    "-m",
+                    "nightly",
+                ]),
+                member
+            ),
+            args(&[
+                "--profile",
+                "demo",
+                "build",
+                "push",
+                "-m",
+                "nightly",
+                "--config",
+                "/repo/games/one/wavedash.toml",
+            ])
+        );
+        assert_eq!(
+            member_args(
+                args(&[
+                    "achievement",
+                    "list",
+                    "--package",
+                    "one",
+                    "--package=two",
+                    "--config=x.toml",
+                    "-cy.toml",
+                    "--json",
+                ]),
+                member
+            ),
+            args(&[
+                "achievement",
+                "list",
+                "--json",
+                "--config",
+                "/repo/games/one/wavedash.toml",
+            ])
+        );
+    }
+}
@@ -0,0 +1,579 @@
+//! `wavedash build push` end to end: the real binary against a stand-in API
+//! (`WAVEDASH_API_HOST`) and a directory for storage (`WAVEDASH_STORAGE`), so
+//! what lands in the "bucket" is exactly what R2 would have been sent. Also
+//! `build download`, which reads it back. Only debug builds read
+//! `WAVEDASH_API_HOST`, so there's nothing to run against a release binary.
+
+#![cfg(debug_assertions)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Path as UrlPath, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+
+const GAME_ID: &str = "game-1";
+const BUILD_ID: &str = "build-1";
+const BUCKET: &str = "builds";
+const KEY_PREFIX: &str = "games/game-1/builds/0f3c";
+/// Where `build-0`, the latest build before any push, is stored.
+const BASE_KEY_PREFIX: &str = "games/game-1/builds/base";
+
+/// What the stand-in API was sent, and the latest build it reports.
+#[derive(Default)]
+struct Api {
+    latest_manifest: Option<Value>,
+    /// Fail the latest build lookup, as an API outage would.
+    latest_unavailable: bool,
+    created: Vec<Value>,
+    completed: Vec<Value>,
+    /// Build ids published, with the body each was sent.
+    published: Vec<(String, Value)>,
+}
+
+type Shared = Arc<Mutex<Api>>;
+
+async fn latest_manifest(State(api): State<Shared>) -> Result<Json<Value>, StatusCode> {
+    let api = api.lock().unwrap();
+    if api.latest_unavailable {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    Ok(Json(match &api.latest_manifest {
+        // Pushed from a wavedash.toml like `project`'s, which leaves the
+        // entrypoint at its default.
+        Some(manifest) => json!({
+            "gameBuildId": "build-0",
+            "manifest": manifest,
+            "entrypoint": "index.html",
+        }),
+        None => json!({ "gameBuildId": null }),
+    }))
+}
+
+async fn create_temp_creds(
+    State(api): State<Shared>,
+    UrlPath(game_id): UrlPath<String>,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    api.lock().unwrap().created.push(body);
+    Json(json!({
+        "gameBuildId": BUILD_ID,
+        "uuid": "0f3c",
+        "r2KeyPrefix": format!("games/{}/builds/0f3c", game_id),
+        "bucketName": BUCKET,
+        "credentials": {
+            "accessKeyId": "id",
+            "secretAccessKey": "secret",
+            "sessionToken": "token",
+        },
+        "endpoint": "https://r2.invalid",
+        "expiresIn": 3600,
+    }))
+}
+
+async fn upload_completed(
+    State(api): State<Shared>,
+    UrlPath((_, build_id)): UrlPath<(String, String)>,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    assert_eq!(build_id, BUILD_ID);
+    api.lock().unwrap().completed.push(body);
+    Json(json!({ "gameSlug": "game-one" }))
+}
+
+/// Reads `build-0` as the latest manifest says it is, and the pushed build
+/// as it was created.
+async fn create_read_creds(
+    State(api): State<Shared>,
+    UrlPath((_, build_id)): UrlPath<(String, String)>,
+) -> Json<Value> {
+    let api = api.lock().unwrap();
+    let (prefix, manifest, base) = if build_id == BUILD_ID {
+        let created = api.created.last().unwrap();
+        (
+            KEY_PREFIX,
+            created["manifest"].clone(),
+            created["baseBuildId"].clone(),
+        )
+    } else {
+        assert_eq!(build_id, "build-0");
+        (
+            BASE_KEY_PREFIX,
+            api.latest_manifest.clone().unwrap(),
+            Value::Null,
+        )
+    };
+    Json(json!({
+        "r2KeyPrefix": prefix,
+        "bucketName": BUCKET,
+        "credentials": {
+            "accessKeyId": "id",
+            "secretAccessKey": "secret",
+            "sessionToken": "token",
+        },
+        "endpoint": "https://r2.invalid",
+        "manifest": manifest,
+        "baseBuildId": base,
+    }))
+}
+
+async fn publish(
+    State(api): State<Shared>,
+    UrlPath((_, build_id)): UrlPath<(String, String)>,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    api.lock().unwrap().published.push((build_id, body));
+    Json(json!({ "releaseId": "release-1", "gameSlug": "game-one" }))
+}
+
+async fn serve_api(api: Shared) -> SocketAddr {
+    let app = Router::new()
+        .route(
+            "/api/games/:game_id/builds/latest/manifest",
+            get(latest_manifest),
+        )
+        .route(
+            "/api/games/:game_id/builds/create-temp-r2-creds",
+            post(create_temp_creds),
+        )
+        .route(
+            "/api/games/:game_id/builds/:build_id/upload-completed",
+            post(upload_completed),
+        )
+        .route(
+            "/api/games/:game_id/builds/:build_id/create-read-r2-creds",
+            post(create_read_creds),
+        )
+        .route(
+            "/api/games/:game_id/builds/:build_id/publish",
+            post(publish),
+        )
+        .with_state(api);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    addr
+}
+
+/// A project with a wavedash.toml and `files` under its upload_dir.
+fn project(root: &Path, files: &[(&str, &[u8])]) -> PathBuf {
+    let project = root.join("project");
+    for (path, contents) in files {
+        let path = project.join("dist").join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    std::fs::write(
+        project.join("wavedash.toml"),
+        format!("game_id = \"{}\"\nupload_dir = \"dist\"\n", GAME_ID),
+    )
+    .unwrap();
+    project
+}
+
+/// Run `wavedash` in `dir` with nothing from this process's environment,
+/// storing into `root/storage`. Returns what it printed, stdout then stderr.
+async fn wavedash(root: &Path, dir: &Path, api: SocketAddr, args: &[&str]) -> String {
+    let home = root.join("home");
+    std::fs::create_dir_all(&home).unwrap();
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_wavedash"))
+        .args(args)
+        .current_dir(dir)
+        .env_clear()
+        .env("HOME", &home)
+        .env("WAVEDASH_TOKEN", "test-key")
+        .env("WAVEDASH_API_HOST", format!("http://{}", api))
+        .env(
+            "WAVEDASH_STORAGE",
+            format!("fs:{}", root.join("storage").display()),
+        )
+        .output()
+        .await
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(
+        output.status.success(),
+        "wavedash {} failed:\n{}\n{}",
+        args.join(" "),
+        stdout,
+        stderr
+    );
+    stdout + &stderr
+}
+
+/// Run `wavedash build push` in `project`.
+async fn push(root: &Path, project: &Path, api: SocketAddr, args: &[&str]) -> String {
+    wavedash(root, project, api, &[&["build", "push"], args].concat()).await
+}
+
+/// Every object in the bucket, by key.
+fn stored(root: &Path) -> HashMap<String, Vec<u8>> {
+    let bucket = root.join("storage").join(BUCKET);
+    walkdir::WalkDir::new(&bucket)
+        .into_iter()
+        .map(Result::unwrap)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| {
+            let key = entry
+                .path()
+                .strip_prefix(&bucket)
+                .unwrap()
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/");
+            (key, std::fs::read(entry.path()).unwrap())
+        })
+        .collect()
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[tokio::test]
+async fn push_stores_every_file_under_the_build_prefix() {
+    let root = tempfile::tempdir().unwrap();
+    let files: &[(&str, &[u8])] = &[
+        ("index.html", b"<html></html>"),
+        ("assets/game.js", b"console.log('hi')"),
+        ("assets/levels/1.json", b"{}"),
+    ];
+    let project = project(root.path(), files);
+    let api = Shared::default();
+    let addr = serve_api(api.clone()).await;
+
+    let stdout = push(root.path(), &project, addr, &["--verify"]).await;
+    assert!(stdout.contains("Verified 3 files"), "{}", stdout);
+
+    let objects = stored(root.path());
+    let mut keys: Vec<&str> = objects.keys().map(String::as_str).collect();
+    keys.sort();
+    assert_eq!(
+        keys,
+        [
+            "games/game-1/builds/0f3c/assets/game.js",
+            "games/game-1/builds/0f3c/assets/levels/1.json",
+            "games/game-1/builds/0f3c/index.html",
+            "games/game-1/builds/0f3c/wavedash-manifest.json",
+        ]
+    );
+    for (path, contents) in files {
+        assert_eq!(objects[&format!("{}/{}", KEY_PREFIX, path)], *contents);
+    }
+
+    // The server is told the digest of the manifest that was stored, and the
+    // manifest lists what was sent at creation.
+    let api = api.lock().unwrap();
+    let manifest = &objects[&format!("{}/wavedash-manifest.json", KEY_PREFIX)];
+    assert_eq!(
+        api.completed,
+        [json!({ "manifestSha256": sha256_hex(manifest) })]
+    );
+    let stored_manifest: Value = serde_json::from_slice(manifest).unwrap();
+    assert_eq!(api.created.len(), 1);
+    assert_eq!(api.created[0]["manifest"], stored_manifest);
+}
+
+#[tokio::test]
+async fn push_stores_only_what_the_latest_build_lacks() {
+    let root = tempfile::tempdir().unwrap();
+    let project = project(
+        root.path(),
+        &[
+            ("index.html", b"<html></html>"),
+            ("assets/game.js", b"console.log('v2')"),
+        ],
+    );
+    let api = Shared::new(Mutex::new(Api {
+        latest_manifest: Some(json!({
+            "files": [
+                {
+                    "path": "index.html",
+                    "size": 13,
+                    "sha256": sha256_hex(b"<html></html>"),
+                },
+                {
+                    "path": "assets/game.js",
+                    "size": 17,
+                    "sha256": sha256_hex(b"console.log('v1')"),
+                },
+            ]
+        })),
+        ..Api::default()
+    }));
+    let addr = serve_api(api.clone()).await;
+
+    push(root.path(), &project, addr, &[]).await;
+
+    let mut keys: Vec<String> = stored(root.path()).into_keys().collect();
+    keys.sort();
+    assert_eq!(
+        keys,
+        [
+            "games/game-1/builds/0f3c/assets/game.js",
+            "games/game-1/builds/0f3c/wavedash-manifest.json",
+        ]
+    );
+    let api = api.lock().unwrap();
+    assert_eq!(api.created[0]["baseBuildId"], "build-0");
+}
+
+#[tokio::test]
+async fn download_after_an_incremental_push_includes_the_files_it_inherited() {
+    let root = tempfile::tempdir().unwrap();
+    // build-0, already in storage: the logo moves and game.js changes.
+    let base: &[(&str, &[u8])] = &[
+        ("index.html", b"<html></html>"),
+        ("assets/game.js", b"console.log('v1')"),
+        ("old/logo.png", b"logo"),
+    ];
+    for (path, contents) in base {
+        let path = root
+            .path()
+            .join("storage")
+            .join(BUCKET)
+            .join(BASE_KEY_PREFIX)
+            .join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    let files: &[(&str, &[u8])] = &[
+        ("index.html", b"<html></html>"),
+        ("assets/game.js", b"console.log('v2')"),
+        ("assets/logo.png", b"logo"),
+    ];
+    let project = project(root.path(), files);
+    let api = Shared::new(Mutex::new(Api {
+        latest_manifest: Some(json!({
+            "files": base
+                .iter()
+                .map(|(path, contents)| json!({
+                    "path": path,
+                    "size": contents.len(),
+                    "sha256": sha256_hex(contents),
+                }))
+                .collect::<Vec<_>>()
+        })),
+        ..Api::default()
+    }));
+    let addr = serve_api(api.clone()).await;
+
+    push(root.path(), &project, addr, &[]).await;
+    assert!(stored(root.path()).contains_key(&format!("{}/assets/game.js", KEY_PREFIX)));
+    assert!(!stored(root.path()).contains_key(&format!("{}/index.html", KEY_PREFIX)));
+
+    let out = root.path().join("out");
+    let output = wavedash(
+        root.path(),
+        &project,
+        addr,
+        &[
+            "build",
+            "download",
+            BUILD_ID,
+            "--out",
+            out.to_str().unwrap(),
+        ],
+    )
+    .await;
+    assert!(output.contains("Verified 3 files"), "{}", output);
+    for (path, contents) in files {
+        assert_eq!(
+            std::fs::read(out.join(path)).unwrap(),
+            *contents,
+            "{}",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn push_publish_with_nothing_changed_publishes_the_latest_build() {
+    let root = tempfile::tempdir().unwrap();
+    let project = project(root.path(), &[("index.html", b"<html></html>")]);
+    let api = Shared::new(Mutex::new(Api {
+        latest_manifest: Some(json!({
+            "files": [{
+                "path": "index.html",
+                "size": 13,
+                "sha256": sha256_hex(b"<html></html>"),
+            }]
+        })),
+        ..Api::default()
+    }));
+    let addr = serve_api(api.clone()).await;
+
+    let output = push(
+        root.path(),
+        &project,
+        addr,
+        &["--publish", "--title", "Same again"],
+    )
+    .await;
+    assert!(
+        output.contains("No changes since build build-0"),
+        "{}",
+        output
+    );
+    assert!(output.contains("Published build build-0"), "{}", output);
+
+    let api = api.lock().unwrap();
+    assert!(api.created.is_empty());
+    assert_eq!(
+        api.published,
+        [(
+            "build-0".to_string(),
+            json!({ "notes": { "title": "Same again" } })
+        )]
+    );
+}
+
+#[tokio::test]
+async fn push_with_a_new_message_over_the_same_files_is_a_new_build() {
+    let root = tempfile::tempdir().unwrap();
+    let project = project(root.path(), &[("index.html", b"<html></html>")]);
+    let api = Shared::new(Mutex::new(Api {
+        latest_manifest: Some(json!({
+            "files": [{
+                "path": "index.html",
+                "size": 13,
+                "sha256": sha256_hex(b"<html></html>"),
+            }]
+        })),
+        ..Api::default()
+    }));
+    let addr = serve_api(api.clone()).await;
+
+    let output = push(root.path(), &project, addr, &["-m", "Rebuilt for the jam"]).await;
+    assert!(!output.contains("No changes since"), "{}", output);
+
+    let api = api.lock().unwrap();
+    assert_eq!(api.created.len(), 1);
+    assert_eq!(api.created[0]["buildMessage"], "Rebuilt for the jam");
+    assert_eq!(api.created[0]["baseBuildId"], "build-0");
+    assert_eq!(api.completed.len(), 1);
+}
+
+#[tokio::test]
+async fn push_uploads_everything_when_the_latest_build_cant_be_fetched() {
+    let root = tempfile::tempdir().unwrap();
+    let project = project(
+        root.path(),
+        &[("index.html", b"<html></html>"), ("game.js", b"go()")],
+    );
+    let api = Shared::new(Mutex::new(Api {
+        latest_unavailable: true,
+        ..Api::default()
+    }));
+    let addr = serve_api(api.clone()).await;
+
+    let output = push(root.path(), &project, addr, &[]).await;
+    assert!(
+        output.contains("couldn't fetch the latest build"),
+        "{}",
+        output
+    );
+
+    let objects = stored(root.path());
+    assert!(objects.contains_key(&format!("{}/index.html", KEY_PREFIX)));
+    assert!(objects.contains_key(&format!("{}/game.js", KEY_PREFIX)));
+    assert!(api.lock().unwrap().created[0].get("baseBuildId").is_none());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn push_runs_the_build_command_first() {
+    let root = tempfile::tempdir().unwrap();
+    let project = project(root.path(), &[("index.html", b"stale")]);
+    std::fs::write(
+        project.join("wavedash.toml"),
+        format!(
+            "game_id = \"{}\"\nupload_dir = \"dist\"\n\n[build]\ncommand = \"printf %s \\\"$GREETING\\\" > dist/index.html\"\n\n[build.env]\nGREETING = \"fresh\"\n",
+            GAME_ID
+        ),
+    )
+    .unwrap();
+    let addr = serve_api(Shared::default()).await;
+
+    let stdout = push(root.path(), &project, addr, &[]).await;
+    assert!(stdout.contains("Running build"), "{}", stdout);
+    let objects = stored(root.path());
+    assert_eq!(objects[&format!("{}/index.html", KEY_PREFIX)], b"fresh");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn dry_run_reports_the_build_command_without_running_it() {
+    let root = tempfile::tempdir().unwrap();
+    let project = project(root.path(), &[("index.html", b"stale")]);
+    std::fs::write(
+        project.join("wavedash.toml"),
+        format!(
+            "game_id = \"{}\"\nupload_dir = \"dist\"\n\n[build]\ncommand = \"printf fresh > dist/index.html\"\n",
+            GAME_ID
+        ),
+    )
+    .unwrap();
+    let addr = serve_api(Shared::default()).await;
+
+    let stdout = push(root.path(), &project, addr, &["--dry-run", "--json"]).await;
+    let report: Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(report["buildCommand"], "printf fresh > dist/index.html");
+    assert_eq!(
+        std::fs::read(project.join("dist/index.html")).unwrap(),
+        b"stale"
+    );
+}
+
+#[tokio::test]
+async fn push_all_pushes_every_workspace_member_with_the_shared_excludes() {
+    let root = tempfile::tempdir().unwrap();
+    let workspace = root.path().join("project");
+    for game in ["game-1", "game-2"] {
+        let member = workspace.join("games").join(game);
+        std::fs::create_dir_all(member.join("dist")).unwrap();
+        std::fs::write(member.join("dist/index.html"), game).unwrap();
+        std::fs::write(member.join("dist/notes.psd"), "layers").unwrap();
+        std::fs::write(
+            member.join("wavedash.toml"),
+            format!("game_id = \"{}\"\nupload_dir = \"dist\"\n", game),
+        )
+        .unwrap();
+    }
+    std::fs::write(
+        workspace.join("wavedash.toml"),
+        "exclude = [\"*.psd\"]\n\n[workspace]\nmembers = [\"games/*\"]\n",
+    )
+    .unwrap();
+    let api = Shared::default();
+    let addr = serve_api(api.clone()).await;
+
+    let output = push(root.path(), &workspace, addr, &["--all"]).await;
+    assert!(output.contains("── game-1 ──"), "{}", output);
+    assert!(output.contains("── game-2 ──"), "{}", output);
+
+    let mut keys: Vec<String> = stored(root.path())
+        .into_keys()
+        .filter(|key| !key.ends_with("wavedash-manifest.json"))
+        .collect();
+    keys.sort();
+    assert_eq!(
+        keys,
+        [
+            "games/game-1/builds/0f3c/index.html",
+            "games/game-2/builds/0f3c/index.html",
+        ]
+    );
+    assert_eq!(api.lock().unwrap().completed.len(), 2);
+}